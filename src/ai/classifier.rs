@@ -0,0 +1,247 @@
+use crate::ai::image_classification::{classify, preprocess, Scores};
+use crate::ai::moderation::ModerationConfig;
+use crate::AiModel;
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tract_onnx::prelude::{TractError, TractResult};
+
+#[derive(Clone, Debug)]
+pub struct ClassifierConfig {
+    /// threads decoding images and running the model
+    pub threads: usize,
+    /// images waiting for a thread, a check waits for room once it's full
+    pub queue: usize,
+    /// the most images run through the model at once
+    pub batch_size: usize,
+    /// how long a thread waits for more images to fill a batch
+    pub batch_wait: Duration,
+    /// the model's outputs in order, see [`load_labels`](crate::ai::image_classification::load_labels)
+    pub labels: Vec<String>,
+}
+
+impl Default for ClassifierConfig {
+    fn default() -> Self {
+        Self {
+            threads: 2,
+            queue: 64,
+            batch_size: 1,
+            batch_wait: Duration::from_millis(10),
+            labels: vec!["safe".to_string(), "nsfw".to_string()],
+        }
+    }
+}
+
+impl ClassifierConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(threads) = env::var("INFERENCE_THREADS") {
+            config.threads = threads.parse().expect("env err -> INFERENCE_THREADS");
+        }
+        if let Ok(queue) = env::var("INFERENCE_QUEUE") {
+            config.queue = queue.parse().expect("env err -> INFERENCE_QUEUE");
+        }
+        if let Ok(batch) = env::var("INFERENCE_BATCH_SIZE") {
+            config.batch_size = batch.parse().expect("env err -> INFERENCE_BATCH_SIZE");
+        }
+        if let Ok(wait) = env::var("INFERENCE_BATCH_WAIT_MS") {
+            config.batch_wait = Duration::from_millis(wait.parse().expect("env err -> INFERENCE_BATCH_WAIT_MS"));
+        }
+
+        config
+    }
+}
+
+/// how the classifier has been doing since it started
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClassifierMetrics {
+    /// images waiting for a thread
+    pub queue_depth: usize,
+    pub images: u64,
+    pub batches: u64,
+    /// average time an image waited before its batch ran
+    pub wait_ms: f64,
+    /// average time a batch took, decoding included
+    pub run_ms: f64,
+}
+
+#[derive(Default)]
+struct Metrics {
+    queue_depth: AtomicUsize,
+    images: AtomicU64,
+    batches: AtomicU64,
+    wait_micros: AtomicU64,
+    run_micros: AtomicU64,
+}
+
+/// what a check fails with when the image itself can't be decoded, as opposed
+/// to the classifier failing with it. `TractError::is` tells them apart
+#[derive(Debug)]
+pub struct Undecodable(String);
+
+impl fmt::Display for Undecodable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Undecodable {}
+
+struct Request {
+    body: Vec<u8>,
+    queued_at: Instant,
+    answer: oneshot::Sender<TractResult<Scores>>,
+}
+
+/// classifies images on its own threads so decoding and inference never hold
+/// up the async workers. images that arrive together are run as one batch
+#[derive(Clone)]
+pub struct Classifier {
+    sender: mpsc::Sender<Request>,
+    metrics: Arc<Metrics>,
+    moderation: Arc<ModerationConfig>,
+}
+
+impl Classifier {
+    /// `model` has to have been optimized for `config.batch_size`. the threads
+    /// stop once the last clone is dropped
+    pub fn new(model: AiModel, config: ClassifierConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue.max(1));
+        let receiver = Arc::new(Mutex::new(receiver));
+        let model = Arc::new(model);
+        let metrics = Arc::new(Metrics::default());
+
+        for i in 0..config.threads.max(1) {
+            let (receiver, model, metrics, config) = (receiver.clone(), model.clone(), metrics.clone(), config.clone());
+            thread::Builder::new()
+                .name(format!("inference-{}", i))
+                .spawn(move || {
+                    while let Some(batch) = next_batch(&receiver, &config) {
+                        run(&model, &config.labels, &metrics, batch);
+                    }
+                })
+                .expect("classifier err -> spawn");
+        }
+
+        Self {
+            sender,
+            metrics,
+            moderation: Arc::new(ModerationConfig::default()),
+        }
+    }
+
+    pub fn with_moderation(mut self, moderation: ModerationConfig) -> Self {
+        self.moderation = Arc::new(moderation);
+        self
+    }
+
+    /// the thresholds the scores are held to
+    pub fn moderation(&self) -> &ModerationConfig {
+        &self.moderation
+    }
+
+    /// the image's scores
+    pub async fn check(&self, body: Vec<u8>) -> TractResult<Scores> {
+        let (answer, result) = oneshot::channel();
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        let request = Request {
+            body,
+            queued_at: Instant::now(),
+            answer,
+        };
+
+        if self.sender.send(request).await.is_err() {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            return Err(TractError::msg("classifier stopped"));
+        }
+
+        result.await.map_err(|_| TractError::msg("classifier stopped"))?
+    }
+
+    pub fn metrics(&self) -> ClassifierMetrics {
+        let images = self.metrics.images.load(Ordering::Relaxed);
+        let batches = self.metrics.batches.load(Ordering::Relaxed);
+        let average = |micros: &AtomicU64, count: u64| match count {
+            0 => 0.0,
+            count => micros.load(Ordering::Relaxed) as f64 / count as f64 / 1000.0,
+        };
+
+        ClassifierMetrics {
+            queue_depth: self.metrics.queue_depth.load(Ordering::Relaxed),
+            images,
+            batches,
+            wait_ms: average(&self.metrics.wait_micros, images),
+            run_ms: average(&self.metrics.run_micros, batches),
+        }
+    }
+}
+
+/// waits for an image, then up to `batch_wait` for more to fill the batch.
+/// `None` once the classifier is dropped
+fn next_batch(receiver: &Mutex<mpsc::Receiver<Request>>, config: &ClassifierConfig) -> Option<Vec<Request>> {
+    let mut receiver = receiver.lock().unwrap();
+    let mut batch = vec![receiver.blocking_recv()?];
+    let deadline = Instant::now() + config.batch_wait;
+
+    while batch.len() < config.batch_size {
+        match receiver.try_recv() {
+            Ok(request) => batch.push(request),
+            Err(mpsc::error::TryRecvError::Empty) if Instant::now() < deadline => thread::sleep(Duration::from_millis(1)),
+            Err(_) => break,
+        }
+    }
+
+    Some(batch)
+}
+
+fn run(model: &AiModel, labels: &[String], metrics: &Metrics, batch: Vec<Request>) {
+    let started = Instant::now();
+    metrics.queue_depth.fetch_sub(batch.len(), Ordering::Relaxed);
+    metrics.images.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    for request in &batch {
+        metrics.wait_micros.fetch_add(started.duration_since(request.queued_at).as_micros() as u64, Ordering::Relaxed);
+    }
+
+    // an image that can't be decoded is answered on its own, the rest run together
+    let mut images = Vec::with_capacity(batch.len());
+    let mut answers = Vec::with_capacity(batch.len());
+    for request in batch {
+        match preprocess(&request.body) {
+            Ok(image) => {
+                images.push(image);
+                answers.push(request.answer);
+            }
+            Err(e) => {
+                let _ = request.answer.send(Err(TractError::new(Undecodable(e.to_string()))));
+            }
+        }
+    }
+
+    if images.is_empty() {
+        return;
+    }
+
+    let result = classify(model, labels, &images);
+    // counted before answering, so whoever was waiting sees the batch in the metrics
+    metrics.batches.fetch_add(1, Ordering::Relaxed);
+    metrics.run_micros.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+    match result {
+        Ok(scores) => {
+            for (answer, scores) in answers.into_iter().zip(scores) {
+                let _ = answer.send(Ok(scores));
+            }
+        }
+        Err(e) => {
+            for answer in answers {
+                let _ = answer.send(Err(TractError::msg(e.to_string())));
+            }
+        }
+    }
+}
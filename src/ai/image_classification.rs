@@ -1,20 +1,73 @@
+use std::collections::BTreeMap;
 use std::io::Cursor;
-use actix_web::web::BytesMut;
+use std::path::Path;
 use tract_onnx::prelude::*;
 use image::{ImageReader};
-use tract_onnx::tract_core::ndarray::Axis;
+use tract_onnx::tract_core::ndarray::{Array4, Axis};
+use crate::ai::classifier::Classifier;
 use crate::AiModel;
 
-pub async fn check_safety(model: &AiModel,
-                          body: &BytesMut) -> TractResult<bool> {
+/// what the model says about an image, the probability of each label
+pub type Scores = BTreeMap<String, f32>;
 
-    let cursor = Cursor::new(body.to_vec());
+/// the labels of a model's outputs, in order. they're read from a JSON array
+/// next to the model, `model.labels.json` for `model.onnx`, and are `safe`
+/// and `nsfw` for a model without one
+pub fn load_labels(model_path: &str) -> Result<Vec<String>, String> {
+    let path = Path::new(model_path).with_extension("labels.json");
+
+    match std::fs::read(&path) {
+        Ok(json) => serde_json::from_slice(&json).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec!["safe".to_string(), "nsfw".to_string()]),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// `batch` images are classified in one run. the model only takes a fixed
+/// batch of one unless it's more, then any number up to it
+pub fn load_model(path: &str, batch: usize) -> TractResult<AiModel> {
+    optimize(onnx().model_for_path(path)?, batch)
+}
+
+pub fn optimize(model: InferenceModel, batch: usize) -> TractResult<AiModel> {
+    let n = if batch > 1 { model.sym("N").to_dim() } else { 1.to_dim() };
+
+    model
+        .with_input_fact(
+            0,
+            InferenceFact::dt_shape(f32::datum_type(), tvec![n, 3.to_dim(), 224.to_dim(), 224.to_dim()]),
+        )?
+        .into_optimized()?
+        .into_runnable()
+}
+
+/// `Err` unless the model has an output for each of the labels, scores would
+/// otherwise go to the wrong labels or to none
+pub fn check_outputs(model: &AiModel, labels: &[String]) -> Result<(), String> {
+    let fact = model.model().output_fact(0).map_err(|e| e.to_string())?;
+    let outputs = fact.shape.last().and_then(|dim| dim.to_i64().ok());
+
+    if outputs != Some(labels.len() as i64) {
+        return Err(format!("the model has {:?} outputs for {} labels", outputs, labels.len()));
+    }
+
+    Ok(())
+}
+
+/// the image's scores, classified on the [`Classifier`]'s threads
+pub async fn check_safety(classifier: &Classifier,
+                          body: &[u8]) -> TractResult<Scores> {
+    classifier.check(body.to_vec()).await
+}
+
+/// decodes the image and scales it to the `[3, 224, 224]` the model takes
+pub fn preprocess(body: &[u8]) -> TractResult<Tensor> {
+    let cursor = Cursor::new(body);
 
     let img = ImageReader::new(cursor).with_guessed_format()?.decode()?;
 
     let rgb_img = img.to_rgb8();
 
-    // let img = image::open("image2.jpg").unwrap().to_rgb8();
     let resized = image::imageops::resize(&rgb_img, 224, 224, image::imageops::FilterType::Lanczos3);
 
     let resized = resized
@@ -24,16 +77,37 @@ pub async fn check_safety(model: &AiModel,
         .collect::<Vec<f32>>();
 
     let input_array = tract_ndarray::Array3::from_shape_vec((224, 224, 3), resized)?;
-    let transpose_array = input_array.permuted_axes([2,0,1]);
-    let input_tensor = transpose_array.insert_axis(Axis(0)).into_tensor();
-    let result = model.run(tvec![TValue::from(input_tensor)])?;
+
+    Ok(input_array.permuted_axes([2,0,1]).into_tensor())
+}
+
+/// runs the model once over the preprocessed images, the scores of each
+pub fn classify(model: &AiModel, labels: &[String], images: &[Tensor]) -> TractResult<Vec<Scores>> {
+    let mut batch = Array4::<f32>::zeros((images.len(), 3, 224, 224));
+    for (i, image) in images.iter().enumerate() {
+        batch.index_axis_mut(Axis(0), i).assign(&image.to_array_view::<f32>()?);
+    }
+    let result = model.run(tvec![TValue::from(batch.into_tensor())])?;
 
     let output = result[0].to_array_view::<f32>()?;
-    let flat_output = output.as_slice().expect("Failed to convert to slice");
+    let output = output.to_shape((images.len(), labels.len()))?;
+
+    Ok(output
+        .outer_iter()
+        .map(|outputs| labels.iter().cloned().zip(probabilities(outputs.to_vec())).collect())
+        .collect())
+}
 
-    if flat_output[0] > flat_output[1] {
-        Ok(true)
-    } else {
-        Ok(false)
+/// softmax, unless the model ends in one already and its outputs are probabilities
+fn probabilities(outputs: Vec<f32>) -> Vec<f32> {
+    let sum: f32 = outputs.iter().sum();
+    if outputs.iter().all(|o| (0.0..=1.0).contains(o)) && (sum - 1.0).abs() < 1e-3 {
+        return outputs;
     }
-}
\ No newline at end of file
+
+    let max = outputs.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exp: Vec<f32> = outputs.iter().map(|o| (o - max).exp()).collect();
+    let sum: f32 = exp.iter().sum();
+
+    exp.into_iter().map(|e| e / sum).collect()
+}
@@ -1 +1,3 @@
-pub mod image_classification;
\ No newline at end of file
+pub mod image_classification;
+pub mod classifier;
+pub mod moderation;
\ No newline at end of file
@@ -0,0 +1,80 @@
+use crate::ai::image_classification::Scores;
+use std::env;
+
+/// what happens to an image given its scores
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// kept, but only its owner sees it until an admin approves it
+    Review,
+    Reject,
+}
+
+/// thresholds on the sum of the scores of `unsafe_labels`. at `reject` or
+/// above an image isn't taken, at `review` or above it's held for an admin
+#[derive(Clone, Debug)]
+pub struct ModerationConfig {
+    pub unsafe_labels: Vec<String>,
+    pub reject: f32,
+    pub review: f32,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            unsafe_labels: vec!["nsfw".to_string()],
+            reject: 0.8,
+            review: 0.5,
+        }
+    }
+}
+
+impl ModerationConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        // `NSFW_LABELS`, comma separated labels of the model that count as unsafe
+        if let Ok(labels) = env::var("NSFW_LABELS") {
+            config.unsafe_labels = labels.split(',').map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect();
+        }
+        if let Ok(reject) = env::var("NSFW_REJECT_THRESHOLD") {
+            config.reject = reject.parse().expect("env err -> NSFW_REJECT_THRESHOLD");
+        }
+        if let Ok(review) = env::var("NSFW_REVIEW_THRESHOLD") {
+            config.review = review.parse().expect("env err -> NSFW_REVIEW_THRESHOLD");
+        }
+        assert!(config.review <= config.reject, "env err -> NSFW_REVIEW_THRESHOLD above NSFW_REJECT_THRESHOLD");
+
+        config
+    }
+
+    /// `Err` unless there are unsafe labels and the model has each of them. one
+    /// it doesn't have would never score, so nothing would be held or rejected for it
+    pub fn validate(&self, labels: &[String]) -> Result<(), String> {
+        if self.unsafe_labels.is_empty() {
+            return Err("no unsafe labels".to_string());
+        }
+        if let Some(label) = self.unsafe_labels.iter().find(|label| !labels.contains(label)) {
+            return Err(format!("unsafe label {} isn't one of the model's {:?}", label, labels));
+        }
+
+        Ok(())
+    }
+
+    /// how likely the image is unsafe
+    pub fn score(&self, scores: &Scores) -> f32 {
+        self.unsafe_labels.iter().filter_map(|label| scores.get(label)).sum()
+    }
+
+    pub fn verdict(&self, scores: &Scores) -> Verdict {
+        let score = self.score(scores);
+
+        if score >= self.reject {
+            Verdict::Reject
+        } else if score >= self.review {
+            Verdict::Review
+        } else {
+            Verdict::Allow
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::env;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mode {
+    Production,
+    Development,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Database {
+    Memory,
+    RocksDb(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub mode: Mode,
+    pub host: String,
+    pub http_port: u16,
+    pub https_port: u16,
+    pub database: Database,
+    pub frontend_dir: String,
+    /// where data exports are built and kept until their link expires
+    pub export_dir: String,
+    /// where uploaded archives wait to be imported
+    pub import_dir: String,
+    /// where resumable uploads are kept until they're complete
+    pub upload_dir: String,
+    pub cors: CorsConfig,
+    pub headers: HeadersConfig,
+}
+
+/// cross origin access to the api, for a frontend served from another origin
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    pub credentials: bool,
+    /// seconds a browser may cache a preflight answer
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: vec![],
+            methods: list("GET,POST,HEAD,PATCH,DELETE"),
+            headers: list("content-type,x-csrf-token,tus-resumable,upload-length,upload-metadata,upload-offset"),
+            credentials: true,
+            max_age: 3600,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// `CORS_ORIGINS` (by default `PUBLIC_URL` or `https://DOMAIN`), `CORS_METHODS`,
+    /// `CORS_HEADERS` as comma separated lists, `CORS_CREDENTIALS`, `CORS_MAX_AGE`
+    pub fn from_env() -> Self {
+        let origins = match env::var("CORS_ORIGINS") {
+            Ok(origins) => list(&origins),
+            Err(_) => env::var("PUBLIC_URL")
+                .ok()
+                .or_else(|| env::var("DOMAIN").ok().map(|domain| format!("https://{}", domain)))
+                .into_iter()
+                .collect(),
+        };
+        let mut cors = Self {
+            origins,
+            ..Self::default()
+        };
+
+        if let Ok(methods) = env::var("CORS_METHODS") {
+            cors.methods = list(&methods);
+        }
+        if let Ok(headers) = env::var("CORS_HEADERS") {
+            cors.headers = list(&headers);
+        }
+        if let Ok(credentials) = env::var("CORS_CREDENTIALS") {
+            cors.credentials = credentials != "false" && credentials != "0";
+        }
+        if let Ok(max_age) = env::var("CORS_MAX_AGE") {
+            cors.max_age = max_age.parse().expect("env err -> CORS_MAX_AGE");
+        }
+
+        cors
+    }
+}
+
+/// response headers that are not per route
+#[derive(Clone, Debug)]
+pub struct HeadersConfig {
+    /// `Strict-Transport-Security` max-age, only sent by the https server
+    pub hsts_max_age: Option<u64>,
+    /// where browsers send content security policy violations
+    pub csp_report_uri: String,
+}
+
+impl Default for HeadersConfig {
+    fn default() -> Self {
+        Self {
+            hsts_max_age: None,
+            csp_report_uri: "/csp-report".to_string(),
+        }
+    }
+}
+
+impl HeadersConfig {
+    /// `HSTS_MAX_AGE` (one year by default, `0` turns it off) and `CSP_REPORT_URI`
+    pub fn from_env(mode: &Mode) -> Self {
+        let mut headers = Self::default();
+
+        if *mode == Mode::Production {
+            let max_age = match env::var("HSTS_MAX_AGE") {
+                Ok(max_age) => max_age.parse().expect("env err -> HSTS_MAX_AGE"),
+                Err(_) => 31_536_000,
+            };
+            headers.hsts_max_age = Some(max_age).filter(|max_age| *max_age > 0);
+        }
+        if let Ok(uri) = env::var("CSP_REPORT_URI") {
+            headers.csp_report_uri = uri;
+        }
+
+        headers
+    }
+}
+
+impl Config {
+    /// production: https on `HTTPS_PORT` with redirect on `HTTP_PORT`, rocksdb
+    /// development (`MODE=dev`): plain http on `HTTP_PORT`, in-memory db
+    pub fn from_env() -> Self {
+        let mode = match env::var("MODE").as_deref() {
+            Ok("dev") | Ok("development") | Ok("test") => Mode::Development,
+            _ => Mode::Production,
+        };
+
+        let default_http_port = match mode {
+            Mode::Production => 80,
+            Mode::Development => 5000,
+        };
+
+        let database = match env::var("DB_ENGINE").as_deref() {
+            Ok("memory") | Ok("mem") => Database::Memory,
+            Ok("rocksdb") => Database::RocksDb(db_path()),
+            _ => match mode {
+                Mode::Production => Database::RocksDb(db_path()),
+                Mode::Development => Database::Memory,
+            },
+        };
+
+        Self {
+            headers: HeadersConfig::from_env(&mode),
+            mode,
+            host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
+            http_port: port("HTTP_PORT", default_http_port),
+            https_port: port("HTTPS_PORT", 443),
+            database,
+            frontend_dir: env::var("FRONTEND_DIR").unwrap_or_else(|_| "../gallery-frontend".to_string()),
+            export_dir: env::var("EXPORT_DIR").unwrap_or_else(|_| "exports".to_string()),
+            import_dir: env::var("IMPORT_DIR").unwrap_or_else(|_| "imports".to_string()),
+            upload_dir: env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string()),
+            cors: CorsConfig::from_env(),
+        }
+    }
+
+    pub fn development() -> Self {
+        Self {
+            mode: Mode::Development,
+            host: "127.0.0.1".to_string(),
+            http_port: 5000,
+            https_port: 443,
+            database: Database::Memory,
+            frontend_dir: "../gallery-frontend".to_string(),
+            export_dir: "exports".to_string(),
+            import_dir: "imports".to_string(),
+            upload_dir: "uploads".to_string(),
+            cors: CorsConfig::default(),
+            headers: HeadersConfig::default(),
+        }
+    }
+
+    pub fn tls(&self) -> bool {
+        self.mode == Mode::Production
+    }
+}
+
+fn db_path() -> String {
+    env::var("DB_PATH").unwrap_or_else(|_| "database".to_string())
+}
+
+fn port(key: &str, default: u16) -> u16 {
+    match env::var(key) {
+        Ok(p) => p.parse().unwrap_or_else(|_| panic!("env err -> {}", key)),
+        Err(_) => default,
+    }
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
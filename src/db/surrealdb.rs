@@ -1,13 +1,230 @@
 
-use crate::model::post::Post;
-use crate::model::user::User;
+use crate::config::Database;
+use crate::ai::image_classification::Scores;
+use crate::model::admin::{AdminUser, ReviewPost};
+use crate::model::audit::{AuditEvent, NewAuditEvent, SecurityEvent};
+use crate::model::deletion::DeletionRequest;
+use crate::model::export::{Export, ExportPayment};
+use crate::model::import::{Import, ImportProgress};
+use crate::model::erasure::{ErasureReport, ErasureStep, ANONYMISED, DELETED};
+use crate::model::oidc::OidcState;
+use crate::model::job::Job;
+use crate::model::post::{Post, PostDetails};
+use crate::model::session::Session;
+use crate::model::upload::Upload;
+use crate::model::user::{Credentials, User};
+use crate::model::webauthn::Passkey;
+use crate::utils::security::random_id;
 use actix_web::web::Json;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
 use std::sync::LazyLock;
-use surrealdb::engine::local::Db;
+use surrealdb::engine::local::{Db, Mem, RocksDb};
 use surrealdb::{Response, Surreal};
 
 pub static DB: LazyLock<Surreal<Db>> = LazyLock::new(Surreal::init);
 
+pub async fn connect(database: &Database) -> surrealdb::Result<()> {
+    match database {
+        Database::Memory => DB.connect::<Mem>(()).await?,
+        Database::RocksDb(path) => DB.connect::<RocksDb>(path.as_str()).await?,
+    };
+
+    DB.use_ns("fdqms").await?;
+    DB.use_db("gallery").await?;
+
+    init().await
+}
+
+/// definitions are idempotent so tables added later also reach existing databases
+pub async fn init() -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        DEFINE TABLE IF NOT EXISTS user SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS upload_limit ON user TYPE int DEFAULT 0;
+        DEFINE FIELD IF NOT EXISTS transaction ON user TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS transaction_date ON user TYPE option<datetime>;
+        DEFINE FIELD IF NOT EXISTS username ON TABLE user TYPE string;
+        DEFINE FIELD IF NOT EXISTS password ON TABLE user TYPE string;
+        DEFINE FIELD IF NOT EXISTS email ON TABLE user TYPE string ASSERT string::is::email($value);
+        DEFINE FIELD IF NOT EXISTS email_verified ON TABLE user TYPE bool DEFAULT false;
+        DEFINE FIELD IF NOT EXISTS totp_secret ON TABLE user TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS totp_pending ON TABLE user TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS totp_last_step ON TABLE user TYPE option<int>;
+        DEFINE FIELD IF NOT EXISTS recovery_codes ON TABLE user TYPE option<array<string>>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE user TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS posts ON TABLE user FLEXIBLE TYPE array<object>;
+        DEFINE FIELD IF NOT EXISTS role ON TABLE user TYPE string DEFAULT 'user' ASSERT $value IN ['user', 'admin'];
+        DEFINE FIELD IF NOT EXISTS suspended ON TABLE user TYPE bool DEFAULT false;
+        DEFINE FIELD IF NOT EXISTS suspended_reason ON TABLE user TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS deactivated ON TABLE user TYPE bool DEFAULT false;
+        DEFINE INDEX IF NOT EXISTS uniq_email ON TABLE user COLUMNS email UNIQUE;
+        DEFINE INDEX IF NOT EXISTS uniq_username ON TABLE user COLUMNS username UNIQUE;
+        DEFINE INDEX IF NOT EXISTS uniq_transaction ON TABLE user COLUMNS transaction UNIQUE;
+        DEFINE TABLE IF NOT EXISTS friend TYPE RELATION IN user OUT user;
+        DEFINE FIELD IF NOT EXISTS accepted ON TABLE friend TYPE bool;
+        DEFINE INDEX IF NOT EXISTS uniq_friend ON TABLE friend COLUMNS in, out UNIQUE;
+        DEFINE TABLE IF NOT EXISTS session SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE session TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS device ON TABLE session TYPE string;
+        DEFINE FIELD IF NOT EXISTS ip ON TABLE session TYPE string;
+        DEFINE FIELD IF NOT EXISTS user_agent ON TABLE session TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE session TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS last_seen ON TABLE session TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS session_user ON TABLE session COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS lockout SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS failures ON TABLE lockout TYPE int DEFAULT 0;
+        DEFINE FIELD IF NOT EXISTS locked_until ON TABLE lockout TYPE option<datetime>;
+        DEFINE TABLE IF NOT EXISTS mail_token SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE mail_token TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS purpose ON TABLE mail_token TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE mail_token TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS mail_token_user ON TABLE mail_token COLUMNS user, purpose;
+        DEFINE TABLE IF NOT EXISTS webauthn_challenge SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE webauthn_challenge TYPE option<record<user>>;
+        DEFINE FIELD IF NOT EXISTS purpose ON TABLE webauthn_challenge TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE webauthn_challenge TYPE datetime DEFAULT time::now();
+        DEFINE TABLE IF NOT EXISTS passkey SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE passkey TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS credential_id ON TABLE passkey TYPE string;
+        DEFINE FIELD IF NOT EXISTS public_key ON TABLE passkey TYPE string;
+        DEFINE FIELD IF NOT EXISTS alg ON TABLE passkey TYPE int;
+        DEFINE FIELD IF NOT EXISTS sign_count ON TABLE passkey TYPE int DEFAULT 0;
+        DEFINE FIELD IF NOT EXISTS name ON TABLE passkey TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE passkey TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS last_used ON TABLE passkey TYPE option<datetime>;
+        DEFINE INDEX IF NOT EXISTS uniq_credential_id ON TABLE passkey COLUMNS credential_id UNIQUE;
+        DEFINE INDEX IF NOT EXISTS passkey_user ON TABLE passkey COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS oidc_state SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS provider ON TABLE oidc_state TYPE string;
+        DEFINE FIELD IF NOT EXISTS nonce ON TABLE oidc_state TYPE string;
+        DEFINE FIELD IF NOT EXISTS verifier ON TABLE oidc_state TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE oidc_state TYPE datetime DEFAULT time::now();
+        DEFINE TABLE IF NOT EXISTS oidc_identity SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE oidc_identity TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS provider ON TABLE oidc_identity TYPE string;
+        DEFINE FIELD IF NOT EXISTS subject ON TABLE oidc_identity TYPE string;
+        DEFINE FIELD IF NOT EXISTS email ON TABLE oidc_identity TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE oidc_identity TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS uniq_oidc_subject ON TABLE oidc_identity COLUMNS provider, subject UNIQUE;
+        DEFINE INDEX IF NOT EXISTS oidc_identity_user ON TABLE oidc_identity COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS audit_event SCHEMAFULL;
+        DEFINE FIELD OVERWRITE actor ON TABLE audit_event TYPE option<record<user>>;
+        DEFINE FIELD IF NOT EXISTS action ON TABLE audit_event TYPE string READONLY;
+        DEFINE FIELD OVERWRITE target ON TABLE audit_event TYPE option<record<user>>;
+        DEFINE FIELD IF NOT EXISTS outcome ON TABLE audit_event TYPE string ASSERT $value IN ['success', 'failure'] READONLY;
+        DEFINE FIELD IF NOT EXISTS detail ON TABLE audit_event TYPE option<string> READONLY;
+        DEFINE FIELD OVERWRITE ip ON TABLE audit_event TYPE option<string>;
+        DEFINE FIELD OVERWRITE user_agent ON TABLE audit_event TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE audit_event TYPE datetime VALUE time::now() READONLY;
+        DEFINE EVENT OVERWRITE audit_event_clear_only ON TABLE audit_event WHEN $event = 'UPDATE' THEN {
+            FOR $field IN ['actor', 'target', 'ip', 'user_agent'] {
+                IF $after[$field] != NONE AND $after[$field] != $before[$field] {
+                    THROW 'audit events can only be cleared';
+                };
+            };
+        };
+        DEFINE EVENT OVERWRITE audit_event_kept ON TABLE audit_event WHEN $event = 'DELETE' THEN {
+            THROW 'audit events can not be deleted';
+        };
+        DEFINE INDEX IF NOT EXISTS audit_event_created_at ON TABLE audit_event COLUMNS created_at;
+        DEFINE INDEX IF NOT EXISTS audit_event_actor ON TABLE audit_event COLUMNS actor;
+        DEFINE INDEX IF NOT EXISTS audit_event_target ON TABLE audit_event COLUMNS target;
+        DEFINE TABLE IF NOT EXISTS deletion_request SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE deletion_request TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS scheduled_at ON TABLE deletion_request TYPE datetime;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE deletion_request TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS claimed_by ON TABLE deletion_request TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS claimed_until ON TABLE deletion_request TYPE option<datetime>;
+        DEFINE FIELD IF NOT EXISTS attempts ON TABLE deletion_request TYPE int DEFAULT 0;
+        DEFINE FIELD IF NOT EXISTS last_error ON TABLE deletion_request TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS retry_at ON TABLE deletion_request TYPE option<datetime>;
+        DEFINE INDEX IF NOT EXISTS deletion_request_scheduled_at ON TABLE deletion_request COLUMNS scheduled_at;
+        DEFINE TABLE IF NOT EXISTS export SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE export TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS status ON TABLE export TYPE string ASSERT $value IN ['pending', 'ready', 'failed'];
+        DEFINE FIELD IF NOT EXISTS file ON TABLE export TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS size ON TABLE export TYPE option<int>;
+        DEFINE FIELD IF NOT EXISTS error ON TABLE export TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE export TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS expires_at ON TABLE export TYPE option<datetime>;
+        DEFINE INDEX IF NOT EXISTS export_user ON TABLE export COLUMNS user;
+        DEFINE FIELD IF NOT EXISTS open ON TABLE export TYPE option<record<user>>;
+        DEFINE INDEX IF NOT EXISTS export_open ON TABLE export COLUMNS open UNIQUE;
+        DEFINE TABLE IF NOT EXISTS import SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE import TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS status ON TABLE import TYPE string ASSERT $value IN ['pending', 'running', 'done', 'failed'];
+        DEFINE FIELD IF NOT EXISTS progress ON TABLE import FLEXIBLE TYPE object DEFAULT {};
+        DEFINE FIELD IF NOT EXISTS error ON TABLE import TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE import TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS import_user ON TABLE import COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS upload SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE upload TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS name ON TABLE upload TYPE string;
+        DEFINE FIELD IF NOT EXISTS ratio ON TABLE upload TYPE string;
+        DEFINE FIELD IF NOT EXISTS length ON TABLE upload TYPE int;
+        DEFINE FIELD IF NOT EXISTS file ON TABLE upload TYPE string;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE upload TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS expires_at ON TABLE upload TYPE datetime;
+        DEFINE INDEX IF NOT EXISTS upload_user ON TABLE upload COLUMNS user;
+        DEFINE INDEX IF NOT EXISTS upload_expires_at ON TABLE upload COLUMNS expires_at;
+        DEFINE TABLE IF NOT EXISTS job SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE job TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS kind ON TABLE job TYPE string;
+        DEFINE FIELD IF NOT EXISTS post ON TABLE job TYPE string;
+        DEFINE FIELD IF NOT EXISTS image ON TABLE job TYPE string;
+        DEFINE FIELD IF NOT EXISTS classify ON TABLE job TYPE bool DEFAULT false;
+        DEFINE FIELD IF NOT EXISTS status ON TABLE job TYPE string ASSERT $value IN ['pending', 'running', 'done', 'failed'];
+        DEFINE FIELD IF NOT EXISTS attempts ON TABLE job TYPE int DEFAULT 0;
+        DEFINE FIELD IF NOT EXISTS error ON TABLE job TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS result ON TABLE job TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS run_at ON TABLE job TYPE datetime DEFAULT time::now();
+        DEFINE FIELD IF NOT EXISTS claimed_by ON TABLE job TYPE option<string>;
+        DEFINE FIELD IF NOT EXISTS claimed_until ON TABLE job TYPE option<datetime>;
+        DEFINE FIELD IF NOT EXISTS created_at ON TABLE job TYPE datetime DEFAULT time::now();
+        DEFINE INDEX IF NOT EXISTS job_status_run_at ON TABLE job COLUMNS status, run_at;
+        DEFINE INDEX IF NOT EXISTS job_user ON TABLE job COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS image SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user ON TABLE image TYPE record<user>;
+        DEFINE FIELD IF NOT EXISTS post ON TABLE image TYPE string;
+        DEFINE INDEX IF NOT EXISTS image_user ON TABLE image COLUMNS user;
+        DEFINE TABLE IF NOT EXISTS erasure_report SCHEMAFULL;
+        DEFINE FIELD IF NOT EXISTS user_id ON TABLE erasure_report TYPE string;
+        DEFINE FIELD IF NOT EXISTS steps ON TABLE erasure_report FLEXIBLE TYPE array<object>;
+        DEFINE FIELD IF NOT EXISTS remaining ON TABLE erasure_report TYPE array<string>;
+        DEFINE FIELD IF NOT EXISTS verified ON TABLE erasure_report TYPE bool;
+        DEFINE FIELD IF NOT EXISTS completed_at ON TABLE erasure_report TYPE option<datetime>;
+    "#,
+    )
+    .await?
+    .check()?;
+
+    images_index().await
+}
+
+/// fills the `image` table from the posts stored before it existed. it's keyed
+/// by file name, so serving an image finds its owner without looking through every user
+async fn images_index() -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        IF array::len((SELECT VALUE id FROM image LIMIT 1)) = 0 {
+            FOR $user IN (SELECT id, posts FROM user) {
+                FOR $post IN $user.posts ?? [] {
+                    UPSERT type::thing('image', $post.image) SET user = $user.id, post = $post.id;
+                    IF $post.thumbnail {
+                        UPSERT type::thing('image', $post.thumbnail) SET user = $user.id, post = $post.id;
+                    };
+                };
+            };
+        };
+    "#,
+    )
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
 pub async fn add_premium(
     user_id: &String,
     transaction: &String,
@@ -75,7 +292,7 @@ pub async fn profile(user_id: &String) -> surrealdb::Result<User> {
     let mut result = DB
         .query(format!(
             r#"
-        SELECT record::id(id) AS id, username, email FROM {};
+        SELECT record::id(id) AS id, username, email, email_verified ?? false AS email_verified FROM {};
     "#,
             user_id
         ))
@@ -90,10 +307,11 @@ pub async fn user_search(user_id: &String, username: &String) -> surrealdb::Resu
     let mut result = DB
         .query(format!(
             r#"
-        SELECT record::id(id) AS id, username FROM user WHERE username = /^{}.*/ AND id != {} AND NOT (->friend->user OR <-friend<-user);
+        SELECT record::id(id) AS id, username FROM user WHERE string::starts_with(username, $username) AND id != {} AND deactivated != true AND NOT (->friend->user OR <-friend<-user);
     "#,
-            username, user_id
+            user_id
         ))
+        .bind(("username", username.to_string()))
         .await?;
 
     let user: Vec<User> = result.take(0)?;
@@ -158,7 +376,7 @@ pub async fn follow_reject(user_id: &String, friend_id: &String) -> surrealdb::R
 
 pub async fn follow_pendings(user_id: &String) -> surrealdb::Result<Vec<User>> {
     let mut result = DB.query(format!(r#"
-    (SELECT <-(friend WHERE accepted=false)<-user AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});
+    (SELECT <-(friend WHERE accepted=false)<-(user WHERE deactivated != true) AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});
     "#, user_id)).await?;
 
     let pendings: Vec<User> = result.take(0)?;
@@ -168,7 +386,7 @@ pub async fn follow_pendings(user_id: &String) -> surrealdb::Result<Vec<User>> {
 
 pub async fn follow_requests(user_id: &String) -> surrealdb::Result<Vec<User>> {
     let mut result = DB.query(format!(r#"
-        (SELECT ->(friend WHERE accepted=false)->user AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});
+        (SELECT ->(friend WHERE accepted=false)->(user WHERE deactivated != true) AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});
         "#, user_id)).await.expect("err");
     let requests: Vec<User> = result.take(0)?;
 
@@ -178,7 +396,7 @@ pub async fn follow_requests(user_id: &String) -> surrealdb::Result<Vec<User>> {
 pub async fn friend_post(user_id: &String, friend_id: &String) -> surrealdb::Result<Vec<Post>> {
     let mut result = DB
         .query(format!(
-            r#"(SELECT ->(friend WHERE out=user:{})->user[0].posts as posts FROM {})[0].posts"#,
+            r#"(SELECT ->(friend WHERE out=user:{})->(user WHERE deactivated != true)[0].posts as posts FROM {})[0].posts[WHERE status = NONE OR status = 'ready']"#,
             friend_id, user_id
         ))
         .await?;
@@ -188,7 +406,7 @@ pub async fn friend_post(user_id: &String, friend_id: &String) -> surrealdb::Res
 }
 
 pub async fn friends(user_id: &String) -> surrealdb::Result<Vec<User>> {
-    let mut result = DB.query(format!(r#"(SELECT ->(friend WHERE accepted=true)->user AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});"#, user_id)).await?;
+    let mut result = DB.query(format!(r#"(SELECT ->(friend WHERE accepted=true)->(user WHERE deactivated != true) AS friends FROM {})[0].friends.map(|$f| {{username: $f.username, id: record::id($f.id)}});"#, user_id)).await?;
 
     let friends: Vec<User> = result.take(0)?;
 
@@ -209,14 +427,16 @@ pub async fn post_delete(user_id: &String, post_id: &String) -> surrealdb::Resul
         .query(format!(
             r#"
     let $post = UPDATE {} SET posts -= posts[WHERE id = '{}'] RETURN BEFORE;
-    let $res = $post[0].posts[WHERE id = '{}'].image;
-    $res[0];
+    let $res = $post[0].posts[WHERE id = '{}'][0];
+    IF $res.image {{ DELETE type::thing('image', $res.image) }};
+    IF $res.thumbnail {{ DELETE type::thing('image', $res.thumbnail) }};
+    $res.image;
     "#,
             user_id, post_id, post_id
         ))
         .await?;
 
-    let image: Option<String> = result.take(2)?;
+    let image: Option<String> = result.take(4)?;
 
     Ok(image)
 }
@@ -241,6 +461,7 @@ pub async fn post_add(
     ratio: String,
     image: &String,
     user_id: &String,
+    status: &str,
 ) -> surrealdb::Result<String> {
     let mut result: Response = DB
         .query(format!(
@@ -249,14 +470,19 @@ pub async fn post_add(
     let $id = type::string(rand::uuid::v7());
     let $updated_data = UPDATE $user_id SET posts +=  {{
         id: $id,
-        image: '{}',
-        ratio: '{}'
+        image: $image,
+        ratio: $ratio,
+        status: $status
     }};
     $id;
     UPDATE $user_id SET upload_limit = upload_limit - 1;
+    UPSERT type::thing('image', $image) SET user = $user_id, post = $id;
     "#,
-            user_id, image, ratio
+            user_id
         ))
+        .bind(("image", image.to_string()))
+        .bind(("ratio", ratio))
+        .bind(("status", status.to_string()))
         .await?;
 
     let id: Option<String> = result.take(3)?;
@@ -264,46 +490,36 @@ pub async fn post_add(
     Ok(id.unwrap())
 }
 
-pub async fn login(username: &String, password: &String) -> surrealdb::Result<String> {
+pub async fn credentials(username: &String) -> surrealdb::Result<Option<Credentials>> {
     let mut result: Response = DB
-        .query(format!(
-            r#"
-        let $user = SELECT password, id FROM user WHERE username='{}' LIMIT 1;
-
-        IF array::len($user) == 0 {{
-            type::string(-1);
-        }} ELSE IF crypto::argon2::compare($user[0].password,'{}') {{
-            type::string($user[0].id);
-        }} ELSE {{
-            type::string(-1);
-        }};
-    "#,
-            username, password
-        ))
+        .query("SELECT type::string(id) AS id, password FROM user WHERE username=$username LIMIT 1;")
+        .bind(("username", username.to_string()))
         .await?;
 
-    let id: Option<String> = result.take(1)?;
+    let credentials: Option<Credentials> = result.take(0)?;
 
-    Ok(id.unwrap())
+    Ok(credentials)
 }
 
 pub async fn register(
     username: &String,
     email: &String,
-    password: &String,
+    password_hash: &String,
 ) -> surrealdb::Result<String> {
     let mut result: Response = DB
-        .query(format!(
+        .query(
             r#"
-    type::string((CREATE user CONTENT {{
-        username: '{}',
-        email: '{}',
-        password: crypto::argon2::generate('{}'),
+    type::string((CREATE user CONTENT {
+        username: $username,
+        email: $email,
+        password: $password,
         posts: []
-    }}).id);
+    }).id);
     "#,
-            username, email, password
-        ))
+        )
+        .bind(("username", username.to_string()))
+        .bind(("email", email.to_string()))
+        .bind(("password", password_hash.to_string()))
         .await?;
 
     let user: Option<String> = result.take(0)?;
@@ -314,42 +530,1573 @@ pub async fn register(
     Ok(id)
 }
 
-pub async fn change_password(
+pub async fn password_hash(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE password FROM {};"#, user_id))
+        .await?;
+
+    let hash: Option<String> = result.take(0)?;
+
+    Ok(hash)
+}
+
+pub async fn set_password(user_id: &String, password_hash: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET password = $password;"#, user_id))
+        .bind(("password", password_hash.to_string()))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn user_delete(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"DELETE {};"#, user_id)).await?;
+
+    Ok(())
+}
+
+pub async fn friend_delete(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+    $user = {};
+    DELETE friend WHERE in=$user OR out=$user;
+    "#,
+        user_id
+    ))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn session_create(
     user_id: &String,
-    old: &String,
-    new: &String,
+    session_id: &String,
+    device: &str,
+    ip: &str,
+    user_agent: &str,
 ) -> surrealdb::Result<()> {
-    DB
+    DB.query(format!(
+        r#"
+        CREATE type::thing('session', $sid) CONTENT {{
+            user: {},
+            device: $device,
+            ip: $ip,
+            user_agent: $user_agent
+        }};
+    "#,
+        user_id
+    ))
+    .bind(("sid", session_id.to_string()))
+    .bind(("device", device.to_string()))
+    .bind(("ip", ip.to_string()))
+    .bind(("user_agent", user_agent.to_string()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// true if the session is still active, refreshing its last_seen
+pub async fn session_touch(user_id: &String, session_id: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        array::len(UPDATE type::thing('session', $sid) SET last_seen = time::now() WHERE user = {} RETURN id) > 0;
+    "#,
+            user_id
+        ))
+        .bind(("sid", session_id.to_string()))
+        .await?;
+
+    let active: Option<bool> = result.take(0)?;
+
+    Ok(active.unwrap_or(false))
+}
+
+pub async fn sessions(user_id: &String) -> surrealdb::Result<Vec<Session>> {
+    let mut result: Response = DB
         .query(format!(
             r#"
-            $u = {};
-            let $user = SELECT password, id FROM $u;
-            IF crypto::argon2::compare($user[0].password,'{}') {{
-                UPDATE $u SET password = crypto::argon2::generate('{}');
-            }};
+        SELECT record::id(id) AS id, device, ip, user_agent, <string> created_at AS created_at, <string> last_seen AS last_seen
+        FROM session WHERE user = {} ORDER BY last_seen DESC;
     "#,
-            user_id, old, new
+            user_id
         ))
         .await?;
 
+    let sessions: Vec<Session> = result.take(0)?;
+
+    Ok(sessions)
+}
+
+pub async fn session_revoke(user_id: &String, session_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        DELETE type::thing('session', $sid) WHERE user = {};
+    "#,
+        user_id
+    ))
+    .bind(("sid", session_id.to_string()))
+    .await?;
+
     Ok(())
 }
 
-pub async fn user_delete(user_id: &String) -> surrealdb::Result<()> {
-    DB.query(format!(r#"DELETE {};"#, user_id)).await?;
+pub async fn session_revoke_all(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"DELETE session WHERE user = {};"#, user_id))
+        .await?;
 
     Ok(())
 }
 
-pub async fn friend_delete(user_id: &String) -> surrealdb::Result<()> {
+pub async fn session_revoke_others(user_id: &String, session_id: &String) -> surrealdb::Result<()> {
     DB.query(format!(
         r#"
-    $user = {};
-    DELETE friend WHERE in=$user OR out=$user;
+        DELETE session WHERE user = {} AND id != type::thing('session', $sid);
+    "#,
+        user_id
+    ))
+    .bind(("sid", session_id.to_string()))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn lockout_until(username: &str) -> surrealdb::Result<Option<DateTime<Utc>>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        LET $until = (SELECT VALUE locked_until FROM ONLY type::thing('lockout', $username));
+        IF $until != NONE {
+            time::unix($until);
+        };
+    "#,
+        )
+        .bind(("username", username.to_string()))
+        .await?;
+
+    let until: Option<i64> = result.take(1)?;
+
+    Ok(until.and_then(|u| DateTime::from_timestamp(u, 0)))
+}
+
+pub async fn lockout_fail(username: &str, max_failures: u32, lockout: Duration) -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        LET $l = type::thing('lockout', $username);
+        LET $until = (SELECT VALUE locked_until FROM ONLY $l);
+        IF $until != NONE AND $until <= time::now() {
+            DELETE $l;
+        };
+        UPSERT $l SET failures += 1;
+        UPDATE $l SET locked_until = time::now() + <duration> $lockout WHERE failures >= $max_failures AND locked_until = NONE;
+    "#,
+    )
+    .bind(("username", username.to_string()))
+    .bind(("max_failures", max_failures))
+    .bind(("lockout", format!("{}s", lockout.num_seconds())))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn lockout_clear(username: &str) -> surrealdb::Result<()> {
+    DB.query(r#"DELETE type::thing('lockout', $username);"#)
+        .bind(("username", username.to_string()))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn user_by_email(email: &String) -> surrealdb::Result<Option<User>> {
+    let mut result: Response = DB
+        .query("SELECT type::string(id) AS id, username, email FROM user WHERE email=$email LIMIT 1;")
+        .bind(("email", email.to_string()))
+        .await?;
+
+    let user: Option<User> = result.take(0)?;
+
+    Ok(user)
+}
+
+pub async fn email_verified(user_id: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE email_verified ?? false FROM {};"#, user_id))
+        .await?;
+
+    let verified: Option<bool> = result.take(0)?;
+
+    Ok(verified.unwrap_or(false))
+}
+
+pub async fn set_email_verified(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET email_verified = true;"#, user_id))
+        .await?;
+
+    Ok(())
+}
+
+/// replaces any earlier token of the same purpose, only the latest mail works
+pub async fn mail_token_create(user_id: &String, purpose: &str, jti: &String) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        DELETE mail_token WHERE user = {0} AND purpose = $purpose;
+        CREATE type::thing('mail_token', $jti) SET user = {0}, purpose = $purpose;
     "#,
         user_id
     ))
+    .bind(("purpose", purpose.to_string()))
+    .bind(("jti", jti.to_string()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// deletes the token and tells whether it existed, so each token is accepted once
+pub async fn mail_token_consume(user_id: &String, purpose: &str, jti: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"array::len(DELETE type::thing('mail_token', $jti) WHERE user = {} AND purpose = $purpose RETURN BEFORE) > 0;"#,
+            user_id
+        ))
+        .bind(("purpose", purpose.to_string()))
+        .bind(("jti", jti.to_string()))
+        .await?;
+
+    let consumed: Option<bool> = result.take(0)?;
+
+    Ok(consumed.unwrap_or(false))
+}
+
+pub async fn totp_secret(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE totp_secret FROM {};"#, user_id))
+        .await?;
+
+    let secret: Option<Option<String>> = result.take(0)?;
+
+    Ok(secret.flatten())
+}
+
+pub async fn totp_pending(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE totp_pending FROM {};"#, user_id))
+        .await?;
+
+    let secret: Option<Option<String>> = result.take(0)?;
+
+    Ok(secret.flatten())
+}
+
+pub async fn totp_set_pending(user_id: &String, secret: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET totp_pending = $secret;"#, user_id))
+        .bind(("secret", secret.to_string()))
+        .await?;
+
+    Ok(())
+}
+
+/// turns the pending secret on, `step` is the one used to confirm it
+pub async fn totp_enable(user_id: &String, step: i64, recovery_codes: Vec<String>) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"UPDATE {} SET totp_secret = totp_pending, totp_pending = NONE, totp_last_step = $step, recovery_codes = $codes;"#,
+        user_id
+    ))
+    .bind(("step", step))
+    .bind(("codes", recovery_codes))
+    .await?;
+
+    Ok(())
+}
+
+pub async fn totp_disable(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"UPDATE {} SET totp_secret = NONE, totp_pending = NONE, totp_last_step = NONE, recovery_codes = NONE;"#,
+        user_id
+    ))
     .await?;
 
     Ok(())
 }
+
+/// records `step` as used, false if it or a later step was already used
+pub async fn totp_use_step(user_id: &String, step: i64) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"array::len(UPDATE {} SET totp_last_step = $step WHERE totp_last_step = NONE OR totp_last_step < $step RETURN id) > 0;"#,
+            user_id
+        ))
+        .bind(("step", step))
+        .await?;
+
+    let used: Option<bool> = result.take(0)?;
+
+    Ok(used.unwrap_or(false))
+}
+
+/// removes the hashed recovery code, false if it isn't one of the user's
+pub async fn recovery_code_use(user_id: &String, code_hash: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"array::len(UPDATE {} SET recovery_codes -= $code WHERE recovery_codes CONTAINS $code RETURN id) > 0;"#,
+            user_id
+        ))
+        .bind(("code", code_hash.to_string()))
+        .await?;
+
+    let used: Option<bool> = result.take(0)?;
+
+    Ok(used.unwrap_or(false))
+}
+
+/// `user_id` is set for registration, login challenges aren't tied to a user yet
+/// expired challenges go first, anyone can ask for a login challenge
+pub async fn webauthn_challenge_create(challenge: &String, purpose: &str, user_id: Option<&String>) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        DELETE webauthn_challenge WHERE created_at < time::now() - 5m;
+        CREATE type::thing('webauthn_challenge', $challenge) SET purpose = $purpose, user = {};
+    "#,
+        user_id.map(|u| u.as_str()).unwrap_or("NONE")
+    ))
+    .bind(("challenge", challenge.to_string()))
+    .bind(("purpose", purpose.to_string()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// deletes the challenge, true if it was issued for this purpose and user in the last 5 minutes
+pub async fn webauthn_challenge_consume(challenge: &String, purpose: &str, user_id: Option<&String>) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        DELETE webauthn_challenge WHERE created_at < time::now() - 5m;
+        array::len(DELETE type::thing('webauthn_challenge', $challenge) WHERE purpose = $purpose AND user = {} RETURN BEFORE) > 0;
+    "#,
+            user_id.map(|u| u.as_str()).unwrap_or("NONE")
+        ))
+        .bind(("challenge", challenge.to_string()))
+        .bind(("purpose", purpose.to_string()))
+        .await?;
+
+    let consumed: Option<bool> = result.take(1)?;
+
+    Ok(consumed.unwrap_or(false))
+}
+
+pub async fn passkey_add(
+    user_id: &String,
+    credential_id: &String,
+    public_key: &String,
+    alg: i64,
+    sign_count: u32,
+    name: &String,
+) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        CREATE passkey SET user = {}, credential_id = $credential_id, public_key = $public_key,
+            alg = $alg, sign_count = $sign_count, name = $name;
+    "#,
+        user_id
+    ))
+    .bind(("credential_id", credential_id.to_string()))
+    .bind(("public_key", public_key.to_string()))
+    .bind(("alg", alg))
+    .bind(("sign_count", sign_count as i64))
+    .bind(("name", name.to_string()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+const PASSKEY_FIELDS: &str = r#"record::id(id) AS id, type::string(user) AS user, credential_id, public_key, alg, sign_count, name,
+    <string> created_at AS created_at, IF last_used != NONE { <string> last_used } AS last_used"#;
+
+pub async fn passkey_by_credential(credential_id: &String) -> surrealdb::Result<Option<Passkey>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"SELECT {} FROM passkey WHERE credential_id = $credential_id LIMIT 1;"#,
+            PASSKEY_FIELDS
+        ))
+        .bind(("credential_id", credential_id.to_string()))
+        .await?;
+
+    let passkey: Option<Passkey> = result.take(0)?;
+
+    Ok(passkey)
+}
+
+pub async fn passkeys(user_id: &String) -> surrealdb::Result<Vec<Passkey>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"SELECT {} FROM passkey WHERE user = {} ORDER BY created_at;"#,
+            PASSKEY_FIELDS, user_id
+        ))
+        .await?;
+
+    let passkeys: Vec<Passkey> = result.take(0)?;
+
+    Ok(passkeys)
+}
+
+pub async fn passkey_used(passkey_id: &String, sign_count: u32) -> surrealdb::Result<()> {
+    DB.query(r#"UPDATE type::thing('passkey', $id) SET sign_count = $sign_count, last_used = time::now();"#)
+        .bind(("id", passkey_id.to_string()))
+        .bind(("sign_count", sign_count as i64))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn passkey_delete(user_id: &String, passkey_id: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"array::len(DELETE type::thing('passkey', $id) WHERE user = {} RETURN BEFORE) > 0;"#,
+            user_id
+        ))
+        .bind(("id", passkey_id.to_string()))
+        .await?;
+
+    let deleted: Option<bool> = result.take(0)?;
+
+    Ok(deleted.unwrap_or(false))
+}
+
+pub async fn oidc_state_create(state: &String, provider: &String, nonce: &String, verifier: &String) -> surrealdb::Result<()> {
+    DB.query(r#"CREATE type::thing('oidc_state', $state) SET provider = $provider, nonce = $nonce, verifier = $verifier;"#)
+        .bind(("state", state.to_string()))
+        .bind(("provider", provider.to_string()))
+        .bind(("nonce", nonce.to_string()))
+        .bind(("verifier", verifier.to_string()))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// deletes the state, returns it if it was issued for this provider in the last 10 minutes
+pub async fn oidc_state_consume(state: &String, provider: &String) -> surrealdb::Result<Option<OidcState>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        DELETE oidc_state WHERE created_at < time::now() - 10m;
+        DELETE type::thing('oidc_state', $state) WHERE provider = $provider RETURN BEFORE;
+    "#,
+        )
+        .bind(("state", state.to_string()))
+        .bind(("provider", provider.to_string()))
+        .await?;
+
+    let state: Option<OidcState> = result.take(1)?;
+
+    Ok(state)
+}
+
+pub async fn oidc_identity_user(provider: &String, subject: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query("SELECT VALUE type::string(user) FROM oidc_identity WHERE provider = $provider AND subject = $subject LIMIT 1;")
+        .bind(("provider", provider.to_string()))
+        .bind(("subject", subject.to_string()))
+        .await?;
+
+    let user: Option<String> = result.take(0)?;
+
+    Ok(user)
+}
+
+pub async fn oidc_identity_add(
+    user_id: &String,
+    provider: &String,
+    subject: &String,
+    email: Option<&String>,
+) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"CREATE oidc_identity SET user = {}, provider = $provider, subject = $subject, email = $email;"#,
+        user_id
+    ))
+    .bind(("provider", provider.to_string()))
+    .bind(("subject", subject.to_string()))
+    .bind(("email", email.cloned()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// users created before roles existed have no `role` field
+pub async fn user_role(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE role ?? 'user' FROM {};"#, user_id))
+        .await?;
+
+    let role: Option<String> = result.take(0)?;
+
+    Ok(role)
+}
+
+/// returns false when there is no such user
+pub async fn set_role_by_username(username: &String, role: &str) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query("UPDATE user SET role = $role WHERE username = $username RETURN VALUE true;")
+        .bind(("username", username.to_string()))
+        .bind(("role", role.to_string()))
+        .await?;
+
+    let updated: Option<bool> = result.take(0)?;
+
+    Ok(updated.unwrap_or(false))
+}
+
+pub async fn user_exists(user_id: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE true FROM {};"#, user_id))
+        .await?;
+
+    let exists: Option<bool> = result.take(0)?;
+
+    Ok(exists.unwrap_or(false))
+}
+
+pub async fn username(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE username FROM {};"#, user_id))
+        .await?;
+
+    let username: Option<String> = result.take(0)?;
+
+    Ok(username)
+}
+
+/// users whose username or email starts with `query`, newest first
+pub async fn admin_users(query: &str, start: u32, limit: u32) -> surrealdb::Result<Vec<AdminUser>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        SELECT
+            record::id(id) AS id, username, email, email_verified ?? false AS email_verified,
+            role ?? 'user' AS role, suspended ?? false AS suspended, suspended_reason,
+            IF transaction_date != NONE AND transaction_date + 30d > time::now() AND upload_limit > 0
+                THEN <string> (transaction_date + 30d) END AS premium_until,
+            array::len(posts ?? []) AS posts, <string> created_at AS created_at
+        FROM user
+        WHERE string::starts_with(string::lowercase(username), $query) OR string::starts_with(string::lowercase(email), $query)
+        ORDER BY created_at DESC
+        LIMIT $limit START $start;
+    "#,
+        )
+        .bind(("query", query.to_lowercase()))
+        .bind(("start", start))
+        .bind(("limit", limit))
+        .await?;
+
+    let users: Vec<AdminUser> = result.take(0)?;
+
+    Ok(users)
+}
+
+/// also ends every session, the tokens already handed out stop working
+pub async fn user_suspend(user_id: &String, reason: Option<&String>) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        UPDATE {user} SET suspended = true, suspended_reason = $reason;
+        DELETE session WHERE user = {user};
+    "#,
+        user = user_id
+    ))
+    .bind(("reason", reason.cloned()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn user_unsuspend(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET suspended = false, suspended_reason = NONE;"#, user_id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// an image or thumbnail that isn't served: its owner is deactivated, or it
+/// hasn't been through processing or is held for review
+pub async fn image_hidden(image: &str) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        LET $owner = (SELECT VALUE user FROM ONLY type::thing('image', $image));
+        LET $user = IF $owner THEN
+            (SELECT deactivated, posts[WHERE image = $image OR thumbnail = $image][0].status AS status FROM ONLY $owner)
+        END;
+        $user.deactivated = true OR $user.status IN ['processing', 'failed', 'review'];
+    "#,
+        )
+        .bind(("image", image.to_string()))
+        .await?;
+
+    let hidden: Option<bool> = result.take(2)?;
+
+    Ok(hidden.unwrap_or(false))
+}
+
+pub async fn suspended(user_id: &String) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(r#"SELECT VALUE suspended ?? false FROM {};"#, user_id))
+        .await?;
+
+    let suspended: Option<bool> = result.take(0)?;
+
+    Ok(suspended.unwrap_or(false))
+}
+
+/// the same 30 days and upload limit a payment gives, without a transaction
+pub async fn premium_grant(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET transaction_date = time::now(), upload_limit = 20;"#, user_id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+pub async fn premium_revoke(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(r#"UPDATE {} SET transaction_date = NONE, upload_limit = 0;"#, user_id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// events are only ever created, the fields can't be rewritten. the ones that
+/// identify someone, `actor`, `target`, `ip` and `user_agent`, can only be
+/// cleared, which erasing an account does, see [`erase_user_records`]
+pub async fn audit_add(event: &NewAuditEvent) -> surrealdb::Result<()> {
+    let record = |id: &Option<String>| id.as_ref().map_or("NONE".to_string(), |id| id.to_string());
+
+    DB.query(format!(
+        r#"
+        CREATE audit_event SET
+            actor = {}, action = $action, target = {}, outcome = $outcome,
+            detail = $detail, ip = $ip, user_agent = $user_agent;
+    "#,
+        record(&event.actor),
+        record(&event.target)
+    ))
+    .bind(("action", event.action.clone()))
+    .bind(("outcome", event.outcome))
+    .bind(("detail", event.detail.clone()))
+    .bind(("ip", event.ip.clone()))
+    .bind(("user_agent", event.user_agent.clone()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn audit_events(limit: u32) -> surrealdb::Result<Vec<AuditEvent>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        SELECT
+            record::id(id) AS id, IF actor THEN record::id(actor) END AS actor, action,
+            IF target THEN record::id(target) END AS target, outcome, detail, ip, user_agent,
+            <string> created_at AS created_at
+        FROM audit_event ORDER BY created_at DESC LIMIT $limit;
+    "#,
+        )
+        .bind(("limit", limit))
+        .await?;
+
+    let events: Vec<AuditEvent> = result.take(0)?;
+
+    Ok(events)
+}
+
+/// what was done by or to the user, newest first. addresses of admins acting on the account are left out
+pub async fn security_history(user_id: &String, limit: u32) -> surrealdb::Result<Vec<SecurityEvent>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        SELECT
+            record::id(id) AS id, action, outcome, detail,
+            IF actor = NONE OR actor = $user THEN ip END AS ip,
+            IF actor = NONE OR actor = $user THEN user_agent END AS user_agent,
+            actor != NONE AND actor != $user AS by_admin,
+            <string> created_at AS created_at
+        FROM audit_event WHERE target = $user OR actor = $user
+        ORDER BY created_at DESC LIMIT $limit;
+    "#,
+            user_id
+        ))
+        .bind(("limit", limit))
+        .await?;
+
+    let events: Vec<SecurityEvent> = result.take(1)?;
+
+    Ok(events)
+}
+
+/// one request per user, its id is the user's. false when one is pending already
+pub async fn deletion_request_create(user_id: &String, scheduled_at: &DateTime<Utc>) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $request = type::thing('deletion_request', record::id({user}));
+        IF (SELECT VALUE id FROM ONLY $request) {{
+            false;
+        }} ELSE {{
+            CREATE $request SET user = {user}, scheduled_at = time::from::unix($at);
+            UPDATE {user} SET deactivated = true;
+            true;
+        }};
+    "#,
+            user = user_id
+        ))
+        .bind(("at", scheduled_at.timestamp()))
+        .await?
+        .check()?;
+
+    let created: Option<bool> = result.take(1)?;
+
+    Ok(created.unwrap_or(false))
+}
+
+/// also brings the account back if it's still there
+pub async fn deletion_request_delete(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        DELETE type::thing('deletion_request', record::id({user}));
+        UPDATE {user} SET deactivated = false;
+    "#,
+        user = user_id
+    ))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn deletion_request(user_id: &String) -> surrealdb::Result<Option<DeletionRequest>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        SELECT type::string(user) AS user_id, time::unix(scheduled_at) AS scheduled_at, attempts, last_error
+        FROM ONLY type::thing('deletion_request', record::id({}));
+    "#,
+            user_id
+        ))
+        .await?;
+
+    let request: Option<DeletionRequest> = result.take(0)?;
+
+    Ok(request)
+}
+
+pub async fn deletion_requests() -> surrealdb::Result<Vec<DeletionRequest>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        SELECT type::string(user) AS user_id, time::unix(scheduled_at) AS scheduled_at, attempts, last_error
+        FROM deletion_request ORDER BY scheduled_at;
+    "#,
+        )
+        .await?;
+
+    let requests: Vec<DeletionRequest> = result.take(0)?;
+
+    Ok(requests)
+}
+
+/// leases the requests due at `now` to `instance` for `lease_secs`. the update is one
+/// statement, so of several instances running the job only one gets each request,
+/// and one that dies mid-way leaves it to be picked up once the lease runs out
+pub async fn deletion_requests_claim(
+    instance: &str,
+    now: &DateTime<Utc>,
+    lease_secs: i64,
+) -> surrealdb::Result<Vec<DeletionRequest>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        LET $now = time::from::unix($at);
+        UPDATE deletion_request
+            SET claimed_by = $instance, claimed_until = $now + duration::from::secs($lease)
+            WHERE scheduled_at <= $now
+                AND (retry_at = NONE OR retry_at <= $now)
+                AND (claimed_until = NONE OR claimed_until < $now);
+        SELECT type::string(user) AS user_id, time::unix(scheduled_at) AS scheduled_at, attempts, last_error
+        FROM deletion_request WHERE claimed_by = $instance AND claimed_until > $now
+        ORDER BY scheduled_at;
+    "#,
+        )
+        .bind(("instance", instance.to_string()))
+        .bind(("at", now.timestamp()))
+        .bind(("lease", lease_secs))
+        .await?
+        .check()?;
+
+    let requests: Vec<DeletionRequest> = result.take(2)?;
+
+    Ok(requests)
+}
+
+/// gives a claimed request back after deleting the account failed, to be
+/// claimed again from `retry_at`
+pub async fn deletion_request_failed(
+    user_id: &String,
+    error: &str,
+    retry_at: &DateTime<Utc>,
+) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        UPDATE type::thing('deletion_request', record::id({}))
+            SET attempts += 1, last_error = $error, retry_at = time::from::unix($at),
+                claimed_by = NONE, claimed_until = NONE;
+    "#,
+        user_id
+    ))
+    .bind(("error", error.to_string()))
+    .bind(("at", retry_at.timestamp()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// removes the records tied to the user, the user record itself last. payments
+/// are kept for the books without the user. the audit log is append-only, so its
+/// events stay as a record that something happened, but without whom: the user's
+/// references are cleared, and so are the address and user agent of what the user
+/// did or what was tried on the account. an admin's own address is kept.
+/// `username` finds the login lockout, which is keyed by it, it's unknown once the
+/// user record is gone
+pub async fn erase_user_records(user_id: &String, username: Option<&str>) -> surrealdb::Result<Vec<ErasureStep>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        LET $uid = type::string($user);
+        RETURN array::len((DELETE friend WHERE in = $user OR out = $user RETURN BEFORE));
+        RETURN array::len((UPDATE payment SET user_id = NONE WHERE user_id = $uid RETURN BEFORE));
+        RETURN array::len((DELETE session WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE mail_token WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE webauthn_challenge WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE passkey WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE oidc_identity WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE export WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE import WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE upload WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE job WHERE user = $user RETURN BEFORE));
+        RETURN array::len((DELETE image WHERE user = $user RETURN BEFORE));
+        RETURN IF $username != NONE THEN array::len((DELETE type::thing('lockout', $username) RETURN BEFORE)) ELSE 0 END;
+        RETURN array::len((UPDATE audit_event SET
+            ip = IF actor = NONE OR actor = $user THEN NONE ELSE ip END,
+            user_agent = IF actor = NONE OR actor = $user THEN NONE ELSE user_agent END,
+            actor = IF actor = $user THEN NONE ELSE actor END,
+            target = IF target = $user THEN NONE ELSE target END
+            WHERE actor = $user OR target = $user RETURN BEFORE));
+        RETURN array::len((DELETE $user RETURN BEFORE));
+    "#,
+            user_id
+        ))
+        .bind(("username", username.map(str::to_string)))
+        .await?
+        .check()?;
+
+    let steps = [
+        ("friends", DELETED),
+        ("payments", ANONYMISED),
+        ("sessions", DELETED),
+        ("mail tokens", DELETED),
+        ("passkey challenges", DELETED),
+        ("passkeys", DELETED),
+        ("linked accounts", DELETED),
+        ("exports", DELETED),
+        ("imports", DELETED),
+        ("uploads", DELETED),
+        ("jobs", DELETED),
+        ("image index", DELETED),
+        ("login lockout", DELETED),
+        ("audit log", ANONYMISED),
+        ("account", DELETED),
+    ];
+    let mut erased = Vec::with_capacity(steps.len());
+    for (i, (data, action)) in steps.into_iter().enumerate() {
+        let count: Option<i64> = result.take(i + 2)?;
+        erased.push(ErasureStep::new(data, action, count.unwrap_or(0)));
+    }
+
+    Ok(erased)
+}
+
+/// how many records erasing the user should have removed are still there
+pub async fn erase_user_remaining(user_id: &String, username: Option<&str>) -> surrealdb::Result<BTreeMap<String, i64>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        LET $uid = type::string($user);
+        RETURN {{
+            friends: array::len((SELECT id FROM friend WHERE in = $user OR out = $user)),
+            payments: array::len((SELECT id FROM payment WHERE user_id = $uid)),
+            sessions: array::len((SELECT id FROM session WHERE user = $user)),
+            "mail tokens": array::len((SELECT id FROM mail_token WHERE user = $user)),
+            "passkey challenges": array::len((SELECT id FROM webauthn_challenge WHERE user = $user)),
+            passkeys: array::len((SELECT id FROM passkey WHERE user = $user)),
+            "linked accounts": array::len((SELECT id FROM oidc_identity WHERE user = $user)),
+            exports: array::len((SELECT id FROM export WHERE user = $user)),
+            imports: array::len((SELECT id FROM import WHERE user = $user)),
+            uploads: array::len((SELECT id FROM upload WHERE user = $user)),
+            jobs: array::len((SELECT id FROM job WHERE user = $user)),
+            "image index": array::len((SELECT id FROM image WHERE user = $user)),
+            "login lockout": IF $username != NONE THEN array::len((SELECT id FROM type::thing('lockout', $username))) ELSE 0 END,
+            "audit log": array::len((SELECT id FROM audit_event WHERE actor = $user OR target = $user)),
+            account: array::len((SELECT id FROM $user)),
+        }};
+    "#,
+            user_id
+        ))
+        .bind(("username", username.map(str::to_string)))
+        .await?
+        .check()?;
+
+    let remaining: Option<BTreeMap<String, i64>> = result.take(2)?;
+
+    Ok(remaining.unwrap_or_default())
+}
+
+/// one report per user, a resumed erasure replaces the one written before
+pub async fn erasure_report_save(report: &ErasureReport, completed_at: Option<&DateTime<Utc>>) -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        UPSERT type::thing('erasure_report', $id) SET
+            user_id = $user_id,
+            steps = $steps,
+            remaining = $remaining,
+            verified = $verified,
+            completed_at = IF $completed_at != NONE THEN time::from::unix($completed_at) END;
+    "#,
+    )
+    .bind(("id", report.user_id.trim_start_matches("user:").to_string()))
+    .bind(("user_id", report.user_id.clone()))
+    .bind(("steps", report.steps.clone()))
+    .bind(("remaining", report.remaining.clone()))
+    .bind(("verified", report.verified))
+    .bind(("completed_at", completed_at.map(|at| at.timestamp())))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+const ERASURE_REPORT_FIELDS: &str = r#"
+    user_id, steps, remaining, verified, IF completed_at THEN <string> completed_at END AS completed_at
+"#;
+
+pub async fn erasure_report(user_id: &str) -> surrealdb::Result<Option<ErasureReport>> {
+    let mut result: Response = DB
+        .query(format!(
+            "SELECT {} FROM ONLY type::thing('erasure_report', $id);",
+            ERASURE_REPORT_FIELDS
+        ))
+        .bind(("id", user_id.trim_start_matches("user:").to_string()))
+        .await?;
+
+    let report: Option<ErasureReport> = result.take(0)?;
+
+    Ok(report)
+}
+
+pub async fn erasure_reports(limit: u32) -> surrealdb::Result<Vec<ErasureReport>> {
+    let mut result: Response = DB
+        .query(format!(
+            "SELECT {} FROM erasure_report ORDER BY completed_at DESC LIMIT $limit;",
+            ERASURE_REPORT_FIELDS
+        ))
+        .bind(("limit", limit))
+        .await?;
+
+    let reports: Vec<ErasureReport> = result.take(0)?;
+
+    Ok(reports)
+}
+
+/// starts an export, unless the user has one that's being built or can still
+/// be downloaded. `open` is set on that one and is unique, so two requests
+/// at once don't both start one
+pub async fn export_create(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        UPDATE export SET open = NONE WHERE user = $user AND open != NONE AND expires_at != NONE AND expires_at <= time::now();
+        record::id((CREATE ONLY export SET user = $user, status = 'pending', open = $user).id);
+    "#,
+            user_id
+        ))
+        .await?;
+
+    let mut errors = result.take_errors();
+    if let Some(e) = errors.remove(&2) {
+        return match e {
+            surrealdb::Error::Db(surrealdb::error::Db::IndexExists { .. }) => Ok(None),
+            e => Err(e),
+        };
+    }
+    if let Some((_, e)) = errors.into_iter().next() {
+        return Err(e);
+    }
+
+    let id: Option<String> = result.take(2)?;
+
+    Ok(id)
+}
+
+pub async fn export_ready(id: &str, file: &str, size: u64, expires_at: &DateTime<Utc>) -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        UPDATE type::thing('export', $id)
+            SET status = 'ready', file = $file, size = $size, expires_at = time::from::unix($expires_at);
+    "#,
+    )
+    .bind(("id", id.to_string()))
+    .bind(("file", file.to_string()))
+    .bind(("size", size as i64))
+    .bind(("expires_at", expires_at.timestamp()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn export_failed(id: &str, error: &str) -> surrealdb::Result<()> {
+    DB.query(r#"UPDATE type::thing('export', $id) SET status = 'failed', error = $error, open = NONE;"#)
+        .bind(("id", id.to_string()))
+        .bind(("error", error.to_string()))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// fails the exports that were still being built when the server stopped, they
+/// would otherwise stay pending and block every later export of their user
+pub async fn exports_interrupted() -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        UPDATE export SET status = 'failed', error = 'interrupted by a restart', open = NONE
+            WHERE status = 'pending' RETURN VALUE record::id(id);
+    "#,
+        )
+        .await?
+        .check()?;
+
+    let ids: Vec<String> = result.take(0)?;
+
+    Ok(ids)
+}
+
+const EXPORT_FIELDS: &str = r#"
+    record::id(id) AS id, status, size, <string> created_at AS created_at,
+    IF expires_at THEN <string> expires_at END AS expires_at
+"#;
+
+/// the archive of the user's export while it can be downloaded, `id` alone
+/// doesn't reach another user's
+pub async fn export_file(user_id: &String, id: &str) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        SELECT VALUE file FROM export
+        WHERE id = type::thing('export', $id) AND user = {} AND status = 'ready' AND expires_at > time::now();
+    "#,
+            user_id
+        ))
+        .bind(("id", id.to_string()))
+        .await?;
+
+    let file: Option<String> = result.take(0)?;
+
+    Ok(file)
+}
+
+pub async fn export_latest(user_id: &String) -> surrealdb::Result<Option<Export>> {
+    let mut result: Response = DB
+        .query(format!(
+            "SELECT {} FROM export WHERE user = {} ORDER BY created_at DESC LIMIT 1;",
+            EXPORT_FIELDS, user_id
+        ))
+        .await?;
+
+    let export: Option<Export> = result.take(0)?;
+
+    Ok(export)
+}
+
+pub async fn export_files(user_id: &String) -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query(format!("SELECT VALUE file FROM export WHERE user = {} AND file != NONE;", user_id))
+        .await?;
+
+    let files: Vec<String> = result.take(0)?;
+
+    Ok(files)
+}
+
+/// removes the exports whose link has expired and returns their archives
+pub async fn exports_expire(now: &DateTime<Utc>) -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        DELETE export WHERE expires_at != NONE AND expires_at <= time::from::unix($now) RETURN BEFORE;
+    "#,
+        )
+        .bind(("now", now.timestamp()))
+        .await?
+        .check()?;
+
+    let expired: Vec<Option<String>> = result.take((0, "file"))?;
+
+    Ok(expired.into_iter().flatten().collect())
+}
+
+pub async fn payments(user_id: &String) -> surrealdb::Result<Vec<ExportPayment>> {
+    let mut result: Response = DB
+        .query(r#"SELECT transaction FROM payment WHERE user_id = $user_id;"#)
+        .bind(("user_id", user_id.to_string()))
+        .await?;
+
+    let payments: Vec<ExportPayment> = result.take(0)?;
+
+    Ok(payments)
+}
+
+/// starts an import, unless one is still running for the user
+pub async fn import_create(user_id: &String) -> surrealdb::Result<Option<String>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        IF (SELECT VALUE id FROM import WHERE user = $user AND status IN ['pending', 'running']) {{
+            NONE;
+        }} ELSE {{
+            record::id((CREATE ONLY import SET user = $user, status = 'pending').id);
+        }};
+    "#,
+            user_id
+        ))
+        .await?
+        .check()?;
+
+    let id: Option<String> = result.take(1)?;
+
+    Ok(id)
+}
+
+pub async fn import_update(
+    id: &str,
+    status: &str,
+    progress: &ImportProgress,
+    error: Option<&str>,
+) -> surrealdb::Result<()> {
+    DB.query(r#"UPDATE type::thing('import', $id) SET status = $status, progress = $progress, error = $error;"#)
+        .bind(("id", id.to_string()))
+        .bind(("status", status.to_string()))
+        .bind(("progress", progress.clone()))
+        .bind(("error", error.map(str::to_string)))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// fails the imports that were still running when the server stopped, see [`exports_interrupted`]
+pub async fn imports_interrupted() -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query(
+            r#"
+        UPDATE import SET status = 'failed', error = 'interrupted by a restart'
+            WHERE status IN ['pending', 'running'] RETURN VALUE record::id(id);
+    "#,
+        )
+        .await?
+        .check()?;
+
+    let ids: Vec<String> = result.take(0)?;
+
+    Ok(ids)
+}
+
+pub async fn import_latest(user_id: &String) -> surrealdb::Result<Option<Import>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        SELECT record::id(id) AS id, status, progress, error, <string> created_at AS created_at
+        FROM import WHERE user = {} ORDER BY created_at DESC LIMIT 1;
+    "#,
+            user_id
+        ))
+        .await?;
+
+    let import: Option<Import> = result.take(0)?;
+
+    Ok(import)
+}
+
+/// adds the upload, unless the user has `max_open` that haven't expired yet
+pub async fn upload_create(user_id: &String, upload: &Upload, max_open: usize) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $user = {};
+        IF count(SELECT VALUE id FROM upload WHERE user = $user AND expires_at > time::now()) >= $max_open {{
+            false;
+        }} ELSE {{
+            CREATE type::thing('upload', $id) SET
+                user = $user,
+                name = $name,
+                ratio = $ratio,
+                length = $length,
+                file = $file,
+                expires_at = time::from::unix($expires_at);
+            true;
+        }};
+    "#,
+            user_id
+        ))
+        .bind(("max_open", max_open))
+        .bind(("id", upload.id.clone()))
+        .bind(("name", upload.name.clone()))
+        .bind(("ratio", upload.ratio.clone()))
+        .bind(("length", upload.length))
+        .bind(("file", upload.file.clone()))
+        .bind(("expires_at", upload.expires_at.timestamp()))
+        .await?
+        .check()?;
+
+    let created: Option<bool> = result.take(1)?;
+
+    Ok(created.unwrap_or(false))
+}
+
+/// the user's upload until it expires, `id` alone doesn't reach another user's
+pub async fn upload(user_id: &String, id: &str) -> surrealdb::Result<Option<Upload>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        SELECT record::id(id) AS id, name, ratio, length, file, time::unix(expires_at) AS expires_at
+        FROM upload WHERE id = type::thing('upload', $id) AND user = {} AND expires_at > time::now();
+    "#,
+            user_id
+        ))
+        .bind(("id", id.to_string()))
+        .await?;
+
+    let upload: Option<Upload> = result.take(0)?;
+
+    Ok(upload)
+}
+
+pub async fn upload_delete(id: &str) -> surrealdb::Result<()> {
+    DB.query("DELETE type::thing('upload', $id);")
+        .bind(("id", id.to_string()))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+pub async fn upload_files(user_id: &String) -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query(format!("SELECT VALUE file FROM upload WHERE user = {};", user_id))
+        .await?;
+
+    let files: Vec<String> = result.take(0)?;
+
+    Ok(files)
+}
+
+/// removes the uploads left unfinished past their expiry and returns their files
+pub async fn uploads_expire(now: &DateTime<Utc>) -> surrealdb::Result<Vec<String>> {
+    let mut result: Response = DB
+        .query("DELETE upload WHERE expires_at <= time::from::unix($now) RETURN BEFORE;")
+        .bind(("now", now.timestamp()))
+        .await?
+        .check()?;
+
+    let files: Vec<String> = result.take((0, "file"))?;
+
+    Ok(files)
+}
+
+pub async fn post_exists(user_id: &String, post_id: &str) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!("SELECT VALUE posts.id CONTAINS $id FROM {};", user_id))
+        .bind(("id", post_id.to_string()))
+        .await?;
+
+    let exists: Option<bool> = result.take(0)?;
+
+    Ok(exists.unwrap_or(false))
+}
+
+/// marks the post ready with what processing found out about it, unless it's
+/// held for review. whether it's held
+pub async fn post_processed(user_id: &String, post_id: &str, details: &PostDetails) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        UPDATE {user} SET
+            posts[WHERE id = $id].width = $width,
+            posts[WHERE id = $id].height = $height,
+            posts[WHERE id = $id].taken_at = $taken_at,
+            posts[WHERE id = $id].camera = $camera,
+            posts[WHERE id = $id].thumbnail = $thumbnail,
+            posts[WHERE id = $id AND status != 'review'].status = 'ready';
+        (SELECT VALUE posts[WHERE id = $id][0].status FROM ONLY {user}) = 'review';
+        UPSERT type::thing('image', $thumbnail) SET user = {user}, post = $id;
+    "#,
+            user = user_id
+        ))
+        .bind(("id", post_id.to_string()))
+        .bind(("width", details.width))
+        .bind(("height", details.height))
+        .bind(("taken_at", details.taken_at.clone()))
+        .bind(("camera", details.camera.clone()))
+        .bind(("thumbnail", details.thumbnail.clone()))
+        .await?
+        .check()?;
+
+    let held: Option<bool> = result.take(1)?;
+
+    Ok(held.unwrap_or(false))
+}
+
+/// keeps the classifier's scores with the post, holding it for review if `held`
+pub async fn post_scored(user_id: &String, post_id: &str, scores: &Scores, held: bool) -> surrealdb::Result<()> {
+    DB.query(format!(
+        r#"
+        UPDATE {} SET posts[WHERE id = $id].scores = $scores;
+        IF $held {{ UPDATE {} SET posts[WHERE id = $id].status = 'review' }};
+    "#,
+        user_id, user_id
+    ))
+    .bind(("id", post_id.to_string()))
+    .bind(("scores", scores.clone()))
+    .bind(("held", held))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// the posts held for review, oldest first
+pub async fn post_reviews() -> surrealdb::Result<Vec<ReviewPost>> {
+    #[derive(serde::Deserialize)]
+    struct Held {
+        user_id: String,
+        username: String,
+        held: Vec<Post>,
+    }
+
+    let mut result: Response = DB
+        .query(
+            r#"
+        SELECT record::id(id) AS user_id, username, posts[WHERE status = 'review'] AS held
+            FROM user WHERE posts.status CONTAINS 'review';
+    "#,
+        )
+        .await?;
+
+    let users: Vec<Held> = result.take(0)?;
+    let mut posts: Vec<ReviewPost> = users
+        .into_iter()
+        .flat_map(|user| {
+            user.held.into_iter().map(move |post| ReviewPost {
+                user_id: user.user_id.clone(),
+                username: user.username.clone(),
+                post_id: post.id,
+                image: post.image,
+                scores: post.scores,
+            })
+        })
+        .collect();
+    // post ids are uuid v7s, in the order they were added
+    posts.sort_by(|a, b| a.post_id.cmp(&b.post_id));
+
+    Ok(posts)
+}
+
+pub async fn post_held(user_id: &String, post_id: &str) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!("(SELECT VALUE posts[WHERE id = $id][0].status FROM ONLY {}) = 'review';", user_id))
+        .bind(("id", post_id.to_string()))
+        .await?;
+
+    let held: Option<bool> = result.take(0)?;
+
+    Ok(held.unwrap_or(false))
+}
+
+/// lets a held post be seen, false if there's no such post held
+pub async fn post_approve(user_id: &String, post_id: &str) -> surrealdb::Result<bool> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $held = (SELECT VALUE posts[WHERE id = $id AND status = 'review'] FROM ONLY {user});
+        IF array::len($held ?? []) > 0 {{ UPDATE {user} SET posts[WHERE id = $id].status = 'ready' }};
+        array::len($held ?? []) > 0;
+    "#,
+            user = user_id
+        ))
+        .bind(("id", post_id.to_string()))
+        .await?;
+
+    let approved: Option<bool> = result.take(2)?;
+
+    Ok(approved.unwrap_or(false))
+}
+
+/// gives back the upload a post that was taken down used
+pub async fn upload_refund(user_id: &String) -> surrealdb::Result<()> {
+    DB.query(format!("UPDATE {} SET upload_limit += 1;", user_id))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+pub async fn job_create(
+    user_id: &String,
+    kind: &str,
+    post_id: &str,
+    image: &str,
+    classify: bool,
+) -> surrealdb::Result<String> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        RETURN record::id((CREATE ONLY job SET
+            user = {}, kind = $kind, post = $post, image = $image, classify = $classify, status = 'pending'
+        ).id);
+    "#,
+            user_id
+        ))
+        .bind(("kind", kind.to_string()))
+        .bind(("post", post_id.to_string()))
+        .bind(("image", image.to_string()))
+        .bind(("classify", classify))
+        .await?
+        .check()?;
+
+    let id: Option<String> = result.take(0)?;
+
+    Ok(id.unwrap())
+}
+
+const JOB_FIELDS: &str = r#"
+    record::id(id) AS id, kind, post AS post_id, image, classify, status, attempts, error, result,
+    <string> created_at AS created_at, type::string(user) AS user_id
+"#;
+
+/// leases up to `limit` jobs due at `now`, and ones whose lease ran out, for
+/// `lease_secs`. the update checks again that they're due, so of several
+/// workers claiming at once only one gets each job
+pub async fn jobs_claim(now: &DateTime<Utc>, lease_secs: i64, limit: u32) -> surrealdb::Result<Vec<Job>> {
+    let mut result: Response = DB
+        .query(format!(
+            r#"
+        LET $now = time::from::millis($at);
+        LET $due = (SELECT id, run_at FROM job
+            WHERE (status = 'pending' AND run_at <= $now) OR (status = 'running' AND claimed_until < $now)
+            ORDER BY run_at LIMIT $limit).id;
+        UPDATE $due SET status = 'running', claimed_by = $claim, claimed_until = $now + duration::from::secs($lease)
+            WHERE (status = 'pending' AND run_at <= $now) OR (status = 'running' AND claimed_until < $now);
+        SELECT {} FROM job WHERE claimed_by = $claim AND status = 'running';
+    "#,
+            JOB_FIELDS
+        ))
+        .bind(("at", now.timestamp_millis()))
+        .bind(("limit", limit))
+        .bind(("lease", lease_secs))
+        .bind(("claim", random_id()))
+        .await?
+        .check()?;
+
+    let jobs: Vec<Job> = result.take(3)?;
+
+    Ok(jobs)
+}
+
+/// the image passed classification, a retry doesn't run it again
+pub async fn job_classified(id: &str) -> surrealdb::Result<()> {
+    DB.query("UPDATE type::thing('job', $id) SET classify = false;")
+        .bind(("id", id.to_string()))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+pub async fn job_done(id: &str, result: Option<&str>) -> surrealdb::Result<()> {
+    DB.query("UPDATE type::thing('job', $id) SET status = 'done', result = $result, claimed_by = NONE, claimed_until = NONE;")
+        .bind(("id", id.to_string()))
+        .bind(("result", result.map(str::to_string)))
+        .await?
+        .check()?;
+
+    Ok(())
+}
+
+/// counts the failure and leaves the job until `run_at`
+pub async fn job_retry(id: &str, error: &str, run_at: &DateTime<Utc>) -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        UPDATE type::thing('job', $id)
+            SET status = 'pending', attempts += 1, error = $error, run_at = time::from::millis($at),
+                claimed_by = NONE, claimed_until = NONE;
+    "#,
+    )
+    .bind(("id", id.to_string()))
+    .bind(("error", error.to_string()))
+    .bind(("at", run_at.timestamp_millis()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+pub async fn job_failed(id: &str, error: &str) -> surrealdb::Result<()> {
+    DB.query(
+        r#"
+        UPDATE type::thing('job', $id)
+            SET status = 'failed', attempts += 1, error = $error, claimed_by = NONE, claimed_until = NONE;
+    "#,
+    )
+    .bind(("id", id.to_string()))
+    .bind(("error", error.to_string()))
+    .await?
+    .check()?;
+
+    Ok(())
+}
+
+/// the user's job, `id` alone doesn't reach another user's
+pub async fn job(user_id: &String, id: &str) -> surrealdb::Result<Option<Job>> {
+    let mut result: Response = DB
+        .query(format!(
+            "SELECT {} FROM job WHERE id = type::thing('job', $id) AND user = {};",
+            JOB_FIELDS, user_id
+        ))
+        .bind(("id", id.to_string()))
+        .await?;
+
+    let job: Option<Job> = result.take(0)?;
+
+    Ok(job)
+}
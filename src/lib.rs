@@ -1,5 +1,15 @@
+use actix_files::Files;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Logger, NormalizePath, TrailingSlash};
+use actix_web::{web, App};
 use tract_onnx::prelude::{Graph, TypedFact, TypedOp};
 use tract_onnx::tract_core;
+use crate::config::Config;
+use crate::model::app::AppData;
+use crate::middleware::headers::{cors, security_headers, SecurityHeaders};
+use crate::service::export_service::ExportService;
+use crate::service::import_service::ImportService;
 
 pub type AiModel = tract_core::model::typed::RunnableModel<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
 
@@ -9,4 +19,113 @@ pub mod model;
 pub mod middleware;
 pub mod ai;
 pub mod db;
-pub mod service;
\ No newline at end of file
+pub mod service;
+pub mod config;
+
+pub fn build_app(
+    config: &Config,
+    app_data: web::Data<AppData>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .wrap(from_fn(middleware::auth::auth_middleware))
+        .wrap(from_fn(middleware::csrf::csrf))
+        .wrap(from_fn(middleware::rate_limit::rate_limit))
+        // outside auth and csrf, preflights carry neither cookies nor the token
+        .wrap(cors(&config.cors))
+        .wrap(from_fn(security_headers))
+        .wrap(NormalizePath::new(TrailingSlash::Trim))
+        .wrap(Logger::default())
+        .app_data(app_data)
+        .app_data(web::Data::new(SecurityHeaders::new(&config.headers)))
+        .app_data(web::Data::new(ExportService::new(&config.export_dir)))
+        .app_data(web::Data::new(ImportService::new(&config.import_dir)))
+        .service(route::user::profile)
+        .service(route::user::logout)
+        .service(route::user::login)
+        .service(route::two_factor::login_two_factor)
+        .service(route::webauthn::login_start)
+        .service(route::webauthn::login_finish)
+        .service(route::oidc::providers)
+        .service(route::oidc::login)
+        .service(route::oidc::callback)
+        .service(route::user::register)
+        .service(route::user::refresh)
+        .service(route::user::users)
+        .service(route::user::check_premium)
+        .service(route::user::payment)
+        .service(route::user::delete)
+        .service(route::user::delete_status)
+        .service(route::user::delete_cancel)
+        .service(route::export::export_start)
+        .service(route::export::export_status)
+        .service(route::export::export_download)
+        .service(route::import::import_start)
+        .service(route::import::import_status)
+        .service(route::user::change_password)
+        .service(route::user::upload_limit)
+        .service(route::email::verify_email_send)
+        .service(route::email::verify_email)
+        .service(route::email::verify_email_link)
+        .service(route::email::password_reset_request)
+        .service(route::email::password_reset)
+        .service(route::email::password_reset_page)
+        .service(route::two_factor::enroll)
+        .service(route::two_factor::enable)
+        .service(route::two_factor::disable)
+        .service(route::webauthn::register_start)
+        .service(route::webauthn::register_finish)
+        .service(route::webauthn::passkeys)
+        .service(route::webauthn::passkey_delete)
+        .service(route::session::sessions)
+        .service(route::session::session_revoke)
+        .service(route::session::session_revoke_all)
+        .service(route::audit::security_history)
+        .service(route::friend::follow_requests)
+        .service(route::friend::follow_pendings)
+        .service(route::friend::follow_accept)
+        .service(route::friend::follow_reject)
+        .service(route::friend::follow)
+        .service(route::friend::unfollow)
+        .service(route::friend::friends)
+        .service(route::friend::friend_posts)
+        .service(route::post::upload)
+        .service(route::upload::upload_create)
+        .service(route::upload::upload_offset)
+        .service(route::upload::upload_append)
+        .service(route::upload::upload_cancel)
+        .service(route::post::post_delete)
+        .service(route::post::posts)
+        .service(route::post::get_file)
+        .service(route::post::get_thumbnail)
+        .service(route::job::job_status)
+        .service(
+            web::scope("/admin")
+                .wrap(from_fn(middleware::admin::admin_guard))
+                .service(route::admin::users)
+                .service(route::admin::suspend)
+                .service(route::admin::unsuspend)
+                .service(route::admin::premium_grant)
+                .service(route::admin::premium_revoke)
+                .service(route::admin::post_takedown)
+                .service(route::admin::reviews)
+                .service(route::admin::review_file)
+                .service(route::admin::post_approve)
+                .service(route::admin::post_reject)
+                .service(route::admin::deletions)
+                .service(route::admin::audit_log)
+                .service(route::admin::erasures)
+                .service(route::admin::inference),
+        )
+        .service(route::report::csp_report)
+        .service(route::index::word)
+        .service(route::index::index)
+        .service(Files::new("/", &config.frontend_dir))
+}
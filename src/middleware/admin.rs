@@ -0,0 +1,26 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::model::admin::ROLE_ADMIN;
+
+/// wraps the `/admin` scope, after [`auth_middleware`](super::auth::auth_middleware)
+/// has put the [`Caller`] into the request. the role is read on every request
+/// so a demoted admin loses access right away
+pub async fn admin_guard(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(user_id) = req.extensions().get::<Caller>().map(|caller| caller.user_id.clone()) else {
+        return Ok(req.into_response(HttpResponse::Unauthorized().body("Token not found")));
+    };
+
+    let role = db::surrealdb::user_role(&user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if role.as_deref() != Some(ROLE_ADMIN) {
+        return Ok(req.into_response(HttpResponse::Forbidden().body("admin only")));
+    }
+
+    next.call(req).await
+}
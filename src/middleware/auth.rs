@@ -1,30 +1,85 @@
 use actix_web::body::BoxBody;
-use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::{web, Error};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
 use actix_web::http::Method;
 use actix_web::middleware::Next;
+use std::future::{ready, Ready};
+use crate::db;
 use crate::model::app::AppData;
-use crate::utils::security::verify;
+use crate::utils::security::ACCESS_TOKEN;
+
+/// who made the request, from the token [`auth_middleware`] checked. it's
+/// kept with the request, so it's never another request's
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+impl FromRequest for Caller {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Caller>()
+                .cloned()
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Token not found")),
+        )
+    }
+}
 
 pub async fn auth_middleware(req: ServiceRequest, srv: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, Error> {
-    if req.path() == "/login" || req.path() == "/register" || req.path() == "/logout" {
+    if req.path() == "/login"
+        || req.path() == "/login/2fa"
+        || req.path().starts_with("/webauthn/login/")
+        || req.path().starts_with("/oidc/")
+        || req.path() == "/register"
+        || req.path() == "/logout"
+        || req.path() == "/refresh"
+        || req.path() == "/verify_email"
+        || req.path().starts_with("/password_reset")
+        || req.path() == "/csp-report"
+    {
         return srv.call(req).await;
     }
 
-    if req.method() == Method::POST || req.path() == "/post" || req.path() == "/profile" {
-        if let Some(cookie) = req.cookie("token") {
-            match verify(cookie.value(), "token") {
-                Ok(_user_id) => {
-                    if let Some(app_data) = req.app_data::<web::Data<AppData>>() {
-                        let mut user_id = app_data.user_id.lock().unwrap();
-                        *user_id = _user_id;
-                    }
+    let required = req.method() == Method::POST
+        || req.path() == "/post"
+        || req.path() == "/profile"
+        || req.path() == "/sessions"
+        || req.path() == "/webauthn/passkeys"
+        || req.path() == "/security/history"
+        || req.path() == "/delete/status"
+        || req.path() == "/export"
+        || req.path() == "/import"
+        || req.path().starts_with("/upload/")
+        || req.path().starts_with("/jobs/")
+        || req.path().starts_with("/admin/");
+
+    if let Some(cookie) = req.cookie("token") {
+        let app_data = req.app_data::<web::Data<AppData>>().expect("app err -> AppData");
+        match app_data.token_keys.verify(cookie.value(), ACCESS_TOKEN) {
+            Ok(claims) => {
+                let active = db::surrealdb::session_touch(&claims.sub, &claims.sid)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+                if active {
+                    req.extensions_mut().insert(Caller {
+                        user_id: claims.sub,
+                        session_id: claims.sid,
+                    });
+                } else if required {
+                    return Err(actix_web::error::ErrorUnauthorized("Session revoked"));
                 }
-                _ => return Err(actix_web::error::ErrorUnauthorized("Token invalid"))
             }
-        } else {
-            return Err(actix_web::error::ErrorUnauthorized("Token not found"));
+            _ if required => return Err(actix_web::error::ErrorUnauthorized("Token invalid")),
+            _ => {}
         }
+    } else if required {
+        return Err(actix_web::error::ErrorUnauthorized("Token not found"));
     }
 
     srv.call(req).await
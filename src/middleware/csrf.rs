@@ -0,0 +1,51 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use crate::utils::cookie::{self, CSRF, PRE_AUTH, REFRESH, TOKEN};
+use crate::utils::security::random_id;
+
+pub const HEADER: &str = "x-csrf-token";
+
+/// double submit: a request that changes state and carries credentials has to
+/// repeat the `csrf` cookie in the `X-CSRF-Token` header. another site can make
+/// the browser send the cookies but can't read them to set the header.
+/// responses to requests without the cookie set a fresh one.
+/// browsers send violation reports with the cookies but can't add the header.
+/// the reset form can't either, the mailed token it carries is proof enough
+pub async fn csrf(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let token = req.cookie(CSRF).map(|c| c.value().to_string());
+    let changes_state = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        && req.path() != "/csp-report"
+        && req.path() != "/password_reset";
+    let credentialed = [TOKEN, REFRESH, PRE_AUTH].iter().any(|name| req.cookie(name).is_some());
+
+    if changes_state && credentialed {
+        let header = req.headers().get(HEADER).and_then(|h| h.to_str().ok());
+
+        let valid = match (&token, header) {
+            (Some(token), Some(header)) => {
+                !token.is_empty() && token.len() == header.len() && openssl::memcmp::eq(token.as_bytes(), header.as_bytes())
+            }
+            _ => false,
+        };
+
+        if !valid {
+            return Ok(req.into_response(HttpResponse::Forbidden().body("csrf token missing or invalid")));
+        }
+    }
+
+    let mut response = next.call(req).await?;
+
+    if token.is_none() && !response.response().cookies().any(|c| c.name() == CSRF) {
+        response.response_mut().add_cookie(&csrf_cookie())?;
+    }
+
+    Ok(response)
+}
+
+/// readable by the frontend, which copies it into the header
+pub fn csrf_cookie() -> actix_web::cookie::Cookie<'static> {
+    cookie::build(CSRF, random_id()).http_only(false).finish()
+}
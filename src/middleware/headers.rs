@@ -0,0 +1,99 @@
+use actix_cors::Cors;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use crate::config::{CorsConfig, HeadersConfig};
+use crate::utils::csp::{Csp, REPORT_GROUP};
+
+/// `actix-cors` set up from the config, it also answers the preflight `OPTIONS`
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(config.methods.iter().map(String::as_str))
+        .allowed_headers(config.headers.iter().map(String::as_str))
+        // what resumable upload clients read from the answers
+        .expose_headers(["location", "tus-resumable", "upload-offset", "upload-length", "upload-expires"])
+        .max_age(config.max_age);
+
+    for origin in &config.origins {
+        cors = cors.allowed_origin(origin);
+    }
+    if config.credentials {
+        cors = cors.supports_credentials();
+    }
+
+    cors
+}
+
+/// the policy of each route prefix, the longest matching prefix wins
+pub struct SecurityHeaders {
+    routes: Vec<(String, HeaderValue)>,
+    default: HeaderValue,
+    reporting_endpoints: HeaderValue,
+    hsts: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &HeadersConfig) -> Self {
+        let default = Csp::app();
+        let routes = vec![("/file/", Csp::file()), ("/thumbnail/", Csp::file())];
+
+        let value = |csp: Csp| {
+            HeaderValue::from_str(&csp.report(&config.csp_report_uri).to_string()).expect("csp err -> header value")
+        };
+
+        let mut routes: Vec<(String, HeaderValue)> = routes
+            .into_iter()
+            .map(|(prefix, csp)| (prefix.to_string(), value(csp)))
+            .collect();
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        Self {
+            routes,
+            default: value(default),
+            reporting_endpoints: HeaderValue::from_str(&format!("{}=\"{}\"", REPORT_GROUP, config.csp_report_uri))
+                .expect("env err -> CSP_REPORT_URI"),
+            hsts: config
+                .hsts_max_age
+                .map(|max_age| HeaderValue::from_str(&format!("max-age={}; includeSubDomains", max_age)).unwrap()),
+        }
+    }
+
+    pub fn csp(&self, path: &str) -> &HeaderValue {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .map(|(_, value)| value)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// content security policy of the route, HSTS on the https server and the
+/// headers every response gets
+pub async fn security_headers<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<B>, Error> {
+    let Some(headers) = req.app_data::<web::Data<SecurityHeaders>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let path = req.path().to_string();
+    let mut response = next.call(req).await?;
+    let response_headers = response.headers_mut();
+
+    response_headers.insert(CONTENT_SECURITY_POLICY, headers.csp(&path).clone());
+    response_headers.insert(
+        HeaderName::from_static("reporting-endpoints"),
+        headers.reporting_endpoints.clone(),
+    );
+    response_headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    response_headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    response_headers.insert(REFERRER_POLICY, HeaderValue::from_static("strict-origin-when-cross-origin"));
+    if let Some(hsts) = &headers.hsts {
+        response_headers.insert(STRICT_TRANSPORT_SECURITY, hsts.clone());
+    }
+
+    Ok(response)
+}
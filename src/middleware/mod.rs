@@ -1,3 +1,6 @@
 pub mod auth;
-pub mod security;
-pub mod redirect;
\ No newline at end of file
+pub mod redirect;
+pub mod rate_limit;
+pub mod csrf;
+pub mod headers;
+pub mod admin;
\ No newline at end of file
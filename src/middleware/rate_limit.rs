@@ -0,0 +1,40 @@
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use crate::model::app::AppData;
+
+pub async fn rate_limit(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(app_data) = req.app_data::<web::Data<AppData>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    // reads aren't counted, `GET /export` is polled while `POST /export` is limited
+    let rule = match *req.method() {
+        Method::GET | Method::HEAD => None,
+        _ => app_data.rate_limiter.rule(req.path()),
+    };
+    if let Some(rule) = rule {
+        let client = req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+
+        if let Err(retry_after) = app_data.rate_limiter.acquire(rule, &client).await {
+            return Ok(req.into_response(too_many_requests(retry_after)));
+        }
+    }
+
+    next.call(req).await
+}
+
+pub fn retry_after_secs(retry_after: chrono::Duration) -> String {
+    ((retry_after.num_milliseconds() + 999) / 1000).max(1).to_string()
+}
+
+pub fn too_many_requests(retry_after: chrono::Duration) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after_secs(retry_after)))
+        .body("too many requests")
+}
@@ -0,0 +1,93 @@
+use crate::ai::image_classification::Scores;
+use crate::utils::validation::{Validate, ValidationError};
+use serde::{Deserialize, Serialize};
+
+pub const ROLE_ADMIN: &str = "admin";
+
+/// a user as the admin api shows it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub role: String,
+    pub suspended: bool,
+    pub suspended_reason: Option<String>,
+    pub premium_until: Option<String>,
+    pub posts: i64,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct UserQuery {
+    /// start of a username or an email
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub start: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    50
+}
+
+/// why an account was suspended or a post taken down, recorded in the audit log
+#[derive(Deserialize, Default)]
+pub struct ReasonForm {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// a post moderation is holding back, see `GET /admin/reviews`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewPost {
+    pub user_id: String,
+    pub username: String,
+    pub post_id: String,
+    /// an admin sees it at `/admin/reviews/{image}`
+    pub image: String,
+    pub scores: Option<Scores>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PendingDeletion {
+    pub user_id: String,
+    pub scheduled_at: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+}
+
+impl Validate for UserQuery {
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.q.chars().count() > 64 {
+            return Err(ValidationError {
+                field: "q",
+                message: "at most 64 characters".to_string(),
+            });
+        }
+        if self.limit == 0 || self.limit > 200 {
+            return Err(ValidationError {
+                field: "limit",
+                message: "1-200".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Validate for ReasonForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.reason.as_ref().is_some_and(|r| r.chars().count() > 500) {
+            return Err(ValidationError {
+                field: "reason",
+                message: "at most 500 characters".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,53 @@
 use crate::service::deletion_service::DeletionService;
-use crate::AiModel;
-use std::sync::{Arc, Mutex};
+use crate::service::mailer::Mailer;
+use crate::service::oidc::OidcService;
+use crate::service::password_service::PasswordService;
+use crate::service::rate_limiter::RateLimiter;
+use crate::service::upload_service::UploadService;
+use crate::utils::security::TokenKeys;
+use crate::ai::classifier::Classifier;
+use std::sync::Arc;
 use web3::Web3;
 
 pub struct AppData {
-    pub ai_model: AiModel,
-    pub user_id: Arc<Mutex<String>>,
+    pub classifier: Classifier,
     pub crypto_network: Web3<web3::transports::http::Http>,
     pub deletion_service: DeletionService,
+    pub rate_limiter: RateLimiter,
+    pub password_service: PasswordService,
+    pub mailer: Arc<dyn Mailer>,
+    pub oidc: OidcService,
+    pub upload_service: UploadService,
+    /// read from the env once at startup, a malformed `RETIRED_SECRET_KEYS` stops it there
+    pub token_keys: TokenKeys,
+}
+
+impl AppData {
+    pub fn new(
+        classifier: Classifier,
+        crypto_network: Web3<web3::transports::http::Http>,
+        deletion_service: DeletionService,
+        rate_limiter: RateLimiter,
+        password_service: PasswordService,
+        mailer: Arc<dyn Mailer>,
+        oidc: OidcService,
+    ) -> Self {
+        Self {
+            classifier,
+            crypto_network,
+            deletion_service,
+            rate_limiter,
+            password_service,
+            mailer,
+            oidc,
+            upload_service: UploadService::new("uploads"),
+            token_keys: TokenKeys::from_env(),
+        }
+    }
+
+    /// the uploads have to be the same for every worker, they lock what's being appended to
+    pub fn with_upload_service(mut self, upload_service: UploadService) -> Self {
+        self.upload_service = upload_service;
+        self
+    }
 }
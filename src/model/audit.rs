@@ -0,0 +1,97 @@
+use actix_web::HttpRequest;
+use serde::{Deserialize, Serialize};
+
+pub const SUCCESS: &str = "success";
+pub const FAILURE: &str = "failure";
+
+/// what [`audit_add`](crate::db::surrealdb::audit_add) writes. `actor` did it,
+/// `target` is the account it was done to; both are empty for a failed login
+/// to a username that doesn't exist
+#[derive(Debug)]
+pub struct NewAuditEvent {
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: &'static str,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(action: &str) -> Self {
+        Self {
+            actor: None,
+            action: action.to_string(),
+            target: None,
+            outcome: SUCCESS,
+            detail: None,
+            ip: None,
+            user_agent: None,
+        }
+    }
+
+    /// a user acting on their own account
+    pub fn by_user(action: &str, user_id: &str) -> Self {
+        Self::new(action).actor(user_id).target(user_id)
+    }
+
+    pub fn actor(mut self, user_id: &str) -> Self {
+        self.actor = Some(user_id.to_string());
+        self
+    }
+
+    pub fn target(mut self, user_id: &str) -> Self {
+        self.target = Some(user_id.to_string());
+        self
+    }
+
+    pub fn failed(mut self) -> Self {
+        self.outcome = FAILURE;
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// the client's address and user agent
+    pub fn from_request(mut self, req: &HttpRequest) -> Self {
+        self.ip = req.connection_info().realip_remote_addr().map(|ip| ip.to_string());
+        self.user_agent = req
+            .headers()
+            .get("user-agent")
+            .and_then(|ua| ua.to_str().ok())
+            .map(|ua| ua.chars().take(512).collect());
+        self
+    }
+}
+
+/// an event as the admin audit log shows it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuditEvent {
+    pub id: String,
+    pub actor: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+}
+
+/// an event in a user's own security history. `by_admin` marks actions an
+/// admin took on the account, whose address isn't shown
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SecurityEvent {
+    pub id: String,
+    pub action: String,
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub by_admin: bool,
+    pub created_at: String,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// an account waiting to be deleted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeletionRequest {
+    pub user_id: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub scheduled_at: DateTime<Utc>,
+    /// failed tries so far
+    #[serde(default)]
+    pub attempts: i64,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// what `GET /delete/status` tells the user about their account
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeletionStatus {
+    pub pending: bool,
+    pub scheduled_at: Option<String>,
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+pub const DELETED: &str = "deleted";
+pub const ANONYMISED: &str = "anonymised";
+
+/// what was done with one kind of data the account had
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErasureStep {
+    pub data: String,
+    pub action: String,
+    pub count: i64,
+}
+
+impl ErasureStep {
+    pub fn new(data: &str, action: &str, count: i64) -> Self {
+        Self {
+            data: data.to_string(),
+            action: action.to_string(),
+            count,
+        }
+    }
+}
+
+/// kept after an account is erased, as proof of what was removed. `remaining`
+/// lists the data still found for the account when it was checked afterwards,
+/// the erasure only counts as done when it's empty
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErasureReport {
+    pub user_id: String,
+    pub steps: Vec<ErasureStep>,
+    pub remaining: Vec<String>,
+    pub verified: bool,
+    pub completed_at: Option<String>,
+}
@@ -0,0 +1,34 @@
+use crate::model::post::Post;
+use crate::model::user::User;
+use serde::{Deserialize, Serialize};
+
+pub const PENDING: &str = "pending";
+pub const READY: &str = "ready";
+pub const FAILED: &str = "failed";
+
+/// an archive of the user's gallery, built in the background
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Export {
+    pub id: String,
+    pub status: String,
+    /// bytes, once it's ready
+    pub size: Option<i64>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportPayment {
+    pub transaction: String,
+}
+
+/// `manifest.json` in the archive, the images are next to it under `images/`
+#[derive(Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub username: String,
+    pub email: Option<String>,
+    pub posts: Vec<Post>,
+    pub friends: Vec<User>,
+    pub payments: Vec<ExportPayment>,
+    pub exported_at: String,
+}
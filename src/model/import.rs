@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+pub const PENDING: &str = "pending";
+pub const RUNNING: &str = "running";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+/// how far an import has got. `processed` of `total` images were looked at,
+/// each is counted in one of `imported`, `duplicates`, `rejected` (nsfw) or `failed`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ImportProgress {
+    pub total: i64,
+    pub processed: i64,
+    pub imported: i64,
+    pub duplicates: i64,
+    pub rejected: i64,
+    pub failed: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Import {
+    pub id: String,
+    pub status: String,
+    pub progress: ImportProgress,
+    /// why it stopped early, e.g. the upload limit ran out
+    pub error: Option<String>,
+    pub created_at: String,
+}
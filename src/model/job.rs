@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+pub const PENDING: &str = "pending";
+pub const RUNNING: &str = "running";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+/// the work a new image still needs once it's stored: classification, unless
+/// it was classified before it was queued, then its thumbnail and exif data
+pub const PROCESS_IMAGE: &str = "process_image";
+
+/// a unit of background work, see [`JobService`](crate::service::job_service::JobService)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub post_id: String,
+    pub image: String,
+    /// still to be checked for nsfw content
+    pub classify: bool,
+    pub status: String,
+    /// failed tries so far
+    pub attempts: i64,
+    pub error: Option<String>,
+    /// what it came to when it's done, e.g. `nsfw` for an image that was taken down
+    pub result: Option<String>,
+    pub created_at: String,
+    #[serde(skip_serializing)]
+    pub user_id: String,
+}
+
+/// results of a [`PROCESS_IMAGE`] job that didn't end with a ready post
+pub const NSFW: &str = "nsfw";
+pub const INVALID_IMAGE: &str = "invalid image";
+pub const POST_DELETED: &str = "post deleted";
+/// processed, but held for review, see [`REVIEW`](crate::model::post::REVIEW)
+pub const HELD: &str = "held for review";
@@ -1,3 +1,13 @@
 pub mod app;
 pub mod post;
-pub mod user;
\ No newline at end of file
+pub mod session;
+pub mod user;
+pub mod webauthn;
+pub mod oidc;
+pub mod admin;
+pub mod audit;
+pub mod deletion;pub mod erasure;
+pub mod export;
+pub mod import;
+pub mod upload;
+pub mod job;
@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+/// query of the redirect back from the provider, `error` when the user declined
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// what was generated when the login started, kept until the callback
+#[derive(Deserialize)]
+pub struct OidcState {
+    pub provider: String,
+    pub nonce: String,
+    pub verifier: String,
+}
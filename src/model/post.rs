@@ -1,13 +1,79 @@
+use crate::ai::image_classification::Scores;
+use crate::utils::validation::{self, Validate, ValidationError};
 use serde::{Deserialize, Serialize};
 
+pub const PROCESSING: &str = "processing";
+pub const READY: &str = "ready";
+pub const FAILED: &str = "failed";
+/// held by moderation until an admin approves it
+pub const REVIEW: &str = "review";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Post {
     pub id: String,
     pub image: String,
     pub ratio: String,
+    /// `processing` until its [`Job`](crate::model::job::Job) has run, posts
+    /// from before there were jobs are ready
+    #[serde(default = "ready")]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// when the photo was taken, from its exif data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    /// served at `/thumbnail/{thumbnail}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// what the nsfw classifier made of the image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<Scores>,
+}
+
+fn ready() -> String {
+    READY.to_string()
+}
+
+/// what processing found out about a post, see [`post_processed`](crate::db::surrealdb::post_processed)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PostDetails {
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    pub thumbnail: String,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct UploadForm {
     pub ratio: String,
 }
+
+impl Validate for UploadForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::ratio("ratio", &self.ratio)
+    }
+}
+
+/// what became of one file of a `POST /upload` with several, `status` is what
+/// an upload of that file alone would have been answered with
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadResult {
+    pub file: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// the job processing the image, see `GET /jobs/{id}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
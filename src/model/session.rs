@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    pub id: String,
+    pub device: String,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: String,
+    pub last_seen: String,
+    #[serde(default)]
+    pub current: bool,
+}
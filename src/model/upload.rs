@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// a resumable upload, see [`UploadService`](crate::service::upload_service::UploadService).
+/// its bytes are appended to `file` until there are `length` of them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Upload {
+    pub id: String,
+    /// the name the client gave the file, its extension is the image's
+    pub name: String,
+    pub ratio: String,
+    pub length: i64,
+    pub file: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+}
@@ -1,3 +1,4 @@
+use crate::utils::validation::{self, Validate, ValidationError};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -7,7 +8,15 @@ pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub password: Option<String>
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub id: String,
+    pub password: String,
 }
 
 #[derive(Deserialize)]
@@ -23,8 +32,81 @@ pub struct RegisterForm {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct EmailForm {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenForm {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordForm {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct CodeForm {
+    pub code: String,
+}
+
 #[derive(Deserialize)]
 pub struct ChangePasswordForm {
     pub old: String,
     pub new: String,
-}
\ No newline at end of file
+}
+
+impl Validate for LoginForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        check_login_username(&self.username)?;
+        validation::password("password", &self.password)
+    }
+}
+
+impl Validate for RegisterForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::username("username", &self.username)?;
+        validation::email("email", &self.email)?;
+        validation::new_password("password", &self.password)
+    }
+}
+
+impl Validate for ChangePasswordForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::password("old", &self.old)?;
+        validation::new_password("new", &self.new)
+    }
+}
+
+impl Validate for EmailForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::email("email", &self.email)
+    }
+}
+
+impl Validate for ResetPasswordForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::new_password("password", &self.password)
+    }
+}
+
+impl Validate for CodeForm {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validation::one_time_code("code", &self.code)
+    }
+}
+
+/// accounts created before validation existed may not match the username rules
+fn check_login_username(username: &str) -> Result<(), ValidationError> {
+    if username.is_empty() || username.chars().count() > 64 {
+        return Err(ValidationError {
+            field: "username",
+            message: "1-64 characters".to_string(),
+        });
+    }
+
+    Ok(())
+}
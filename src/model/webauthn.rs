@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/// `PublicKeyCredential.toJSON()` of `navigator.credentials.create()`, binary fields base64url
+#[derive(Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterFinishForm {
+    pub credential: RegistrationCredential,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// `PublicKeyCredential.toJSON()` of `navigator.credentials.get()`
+#[derive(Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle", default)]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginStartForm {
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Passkey {
+    pub id: String,
+    pub user: String,
+    pub credential_id: String,
+    #[serde(skip_serializing)]
+    pub public_key: String,
+    pub alg: i64,
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: String,
+    pub last_used: Option<String>,
+}
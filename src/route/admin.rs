@@ -0,0 +1,278 @@
+use actix_files::NamedFile;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use crate::db;
+use crate::model::admin::{PendingDeletion, ReasonForm, UserQuery};
+use crate::middleware::auth::Caller;
+use crate::model::app::AppData;
+use crate::model::audit::NewAuditEvent;
+use crate::route::audit;
+use crate::service::images;
+use crate::utils::validation::{self, Validate};
+
+/// validates the id from the path and checks the user exists
+async fn target(id: &str) -> Result<String, Error> {
+    validation::record_id("user_id", id)?;
+    let user_id = format!("user:{}", id);
+
+    if !db::surrealdb::user_exists(&user_id)
+        .await
+        .expect("err -> db::surrealdb::user_exists")
+    {
+        return Err(actix_web::error::ErrorNotFound("user not found"));
+    }
+
+    Ok(user_id)
+}
+
+/// done by the admin to the user
+fn event(req: &HttpRequest, admin_id: &str, action: &str, user_id: &str) -> NewAuditEvent {
+    NewAuditEvent::new(action).actor(admin_id).target(user_id).from_request(req)
+}
+
+#[get("/users")]
+pub async fn users(query: web::Query<UserQuery>) -> Result<HttpResponse, Error> {
+    query.validate()?;
+
+    let users = db::surrealdb::admin_users(&query.q, query.start, query.limit)
+        .await
+        .expect("err -> db::surrealdb::admin_users");
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[post("/users/{id}/suspend")]
+pub async fn suspend(
+    req: HttpRequest,
+    path: web::Path<String>,
+    form: Option<web::Json<ReasonForm>>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let form = form.map(|f| f.into_inner()).unwrap_or_default();
+    form.validate()?;
+    let user_id = target(&path).await?;
+    let admin_id = admin.user_id;
+
+    if user_id == admin_id {
+        return Err(actix_web::error::ErrorBadRequest("admins can't suspend themselves"));
+    }
+
+    db::surrealdb::user_suspend(&user_id, form.reason.as_ref())
+        .await
+        .expect("err -> db::surrealdb::user_suspend");
+    audit::record(NewAuditEvent {
+        detail: form.reason,
+        ..event(&req, &admin_id, "admin.suspend", &user_id)
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().body("suspended"))
+}
+
+#[post("/users/{id}/unsuspend")]
+pub async fn unsuspend(
+    req: HttpRequest,
+    path: web::Path<String>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let user_id = target(&path).await?;
+
+    db::surrealdb::user_unsuspend(&user_id)
+        .await
+        .expect("err -> db::surrealdb::user_unsuspend");
+    audit::record(event(&req, &admin.user_id, "admin.unsuspend", &user_id)).await;
+
+    Ok(HttpResponse::Ok().body("unsuspended"))
+}
+
+#[post("/users/{id}/premium/grant")]
+pub async fn premium_grant(
+    req: HttpRequest,
+    path: web::Path<String>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let user_id = target(&path).await?;
+
+    db::surrealdb::premium_grant(&user_id)
+        .await
+        .expect("err -> db::surrealdb::premium_grant");
+    audit::record(event(&req, &admin.user_id, "admin.premium_grant", &user_id)).await;
+
+    Ok(HttpResponse::Ok().body("premium granted"))
+}
+
+#[post("/users/{id}/premium/revoke")]
+pub async fn premium_revoke(
+    req: HttpRequest,
+    path: web::Path<String>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let user_id = target(&path).await?;
+
+    db::surrealdb::premium_revoke(&user_id)
+        .await
+        .expect("err -> db::surrealdb::premium_revoke");
+    audit::record(event(&req, &admin.user_id, "admin.premium_revoke", &user_id)).await;
+
+    Ok(HttpResponse::Ok().body("premium revoked"))
+}
+
+/// removes the post with its image and thumbnail, false if there's no such post
+async fn remove_post(user_id: &String, post_id: &String) -> bool {
+    let image = db::surrealdb::post_delete(user_id, post_id)
+        .await
+        .expect("err -> db::surrealdb::post_delete");
+    let Some(image) = image else {
+        return false;
+    };
+
+    if let Err(e) = tokio::fs::remove_file(format!("images/{}", image)).await {
+        println!("takedown err -> {} {}", image, e);
+    }
+    images::remove_thumbnail(&image).await;
+
+    true
+}
+
+/// removes the post and its image, whatever the owner's settings
+#[post("/users/{id}/posts/{post_id}/takedown")]
+pub async fn post_takedown(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: Option<web::Json<ReasonForm>>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let (id, post_id) = path.into_inner();
+    let form = form.map(|f| f.into_inner()).unwrap_or_default();
+    form.validate()?;
+    validation::record_id("post_id", &post_id)?;
+    let user_id = target(&id).await?;
+
+    if !remove_post(&user_id, &post_id).await {
+        return Err(actix_web::error::ErrorNotFound("post not found"));
+    }
+
+    let detail = match &form.reason {
+        Some(reason) => format!("post {}: {}", post_id, reason),
+        None => format!("post {}", post_id),
+    };
+    audit::record(event(&req, &admin.user_id, "admin.post_takedown", &user_id).detail(detail)).await;
+
+    Ok(HttpResponse::Ok().body("taken down"))
+}
+
+/// posts moderation held back, for an admin to approve or reject
+#[get("/reviews")]
+pub async fn reviews() -> HttpResponse {
+    let posts = db::surrealdb::post_reviews()
+        .await
+        .expect("err -> db::surrealdb::post_reviews");
+
+    HttpResponse::Ok().json(posts)
+}
+
+/// a held image, which `/file` doesn't serve
+#[get("/reviews/{file}")]
+pub async fn review_file(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let file_name = path.into_inner();
+    validation::file_name("file", &file_name)?;
+
+    let file = NamedFile::open_async(format!("images/{}", file_name)).await?;
+
+    Ok(file.into_response(&req))
+}
+
+#[post("/users/{id}/posts/{post_id}/approve")]
+pub async fn post_approve(req: HttpRequest, path: web::Path<(String, String)>, admin: Caller) -> Result<HttpResponse, Error> {
+    let (id, post_id) = path.into_inner();
+    validation::record_id("post_id", &post_id)?;
+    let user_id = target(&id).await?;
+
+    if !db::surrealdb::post_approve(&user_id, &post_id)
+        .await
+        .expect("err -> db::surrealdb::post_approve")
+    {
+        return Err(actix_web::error::ErrorNotFound("post not held for review"));
+    }
+
+    audit::record(event(&req, &admin.user_id, "admin.post_approve", &user_id).detail(format!("post {}", post_id))).await;
+
+    Ok(HttpResponse::Ok().body("approved"))
+}
+
+/// removes a held post like a takedown, and gives back the upload it used
+#[post("/users/{id}/posts/{post_id}/reject")]
+pub async fn post_reject(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    form: Option<web::Json<ReasonForm>>,
+    admin: Caller,
+) -> Result<HttpResponse, Error> {
+    let (id, post_id) = path.into_inner();
+    let form = form.map(|f| f.into_inner()).unwrap_or_default();
+    form.validate()?;
+    validation::record_id("post_id", &post_id)?;
+    let user_id = target(&id).await?;
+
+    let held = db::surrealdb::post_held(&user_id, &post_id)
+        .await
+        .expect("err -> db::surrealdb::post_held");
+    if !held || !remove_post(&user_id, &post_id).await {
+        return Err(actix_web::error::ErrorNotFound("post not held for review"));
+    }
+    db::surrealdb::upload_refund(&user_id)
+        .await
+        .expect("err -> db::surrealdb::upload_refund");
+
+    let detail = match &form.reason {
+        Some(reason) => format!("post {}: {}", post_id, reason),
+        None => format!("post {}", post_id),
+    };
+    audit::record(event(&req, &admin.user_id, "admin.post_reject", &user_id).detail(detail)).await;
+
+    Ok(HttpResponse::Ok().body("rejected"))
+}
+
+/// accounts waiting in [`DeletionService`](crate::service::deletion_service::DeletionService)
+#[get("/deletions")]
+pub async fn deletions(app_data: web::Data<AppData>) -> HttpResponse {
+    let pending: Vec<PendingDeletion> = app_data
+        .deletion_service
+        .pending()
+        .await
+        .expect("deletion service err -> pending")
+        .into_iter()
+        .map(|request| PendingDeletion {
+            user_id: request.user_id.trim_start_matches("user:").to_string(),
+            scheduled_at: request.scheduled_at.to_rfc3339(),
+            attempts: request.attempts,
+            last_error: request.last_error,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(pending)
+}
+
+#[get("/audit")]
+pub async fn audit_log() -> HttpResponse {
+    let events = db::surrealdb::audit_events(200)
+        .await
+        .expect("err -> db::surrealdb::audit_events");
+
+    HttpResponse::Ok().json(events)
+}
+
+/// how the nsfw classifier keeps up: images waiting, batches run and how long they took
+#[get("/inference")]
+pub async fn inference(app_data: web::Data<AppData>) -> HttpResponse {
+    HttpResponse::Ok().json(app_data.classifier.metrics())
+}
+
+/// what was removed from erased accounts, and whether checking afterwards found anything left
+#[get("/erasures")]
+pub async fn erasures() -> HttpResponse {
+    let reports = db::surrealdb::erasure_reports(200)
+        .await
+        .expect("err -> db::surrealdb::erasure_reports");
+
+    HttpResponse::Ok().json(reports)
+}
@@ -0,0 +1,25 @@
+use actix_web::{get, HttpResponse};
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::model::audit::NewAuditEvent;
+
+/// appends to the audit log. a failed write panics the handler like every other
+/// db error, an action that can't be recorded shouldn't look like it went through
+pub(crate) async fn record(event: NewAuditEvent) {
+    db::surrealdb::audit_add(&event)
+        .await
+        .expect("err -> db::surrealdb::audit_add");
+}
+
+/// logins, failed logins, password changes, payments and deletion requests
+/// of the account, and what admins did to it
+#[get("/security/history")]
+pub async fn security_history(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let events = db::surrealdb::security_history(&user_id, 100)
+        .await
+        .expect("err -> db::surrealdb::security_history");
+
+    HttpResponse::Ok().json(events)
+}
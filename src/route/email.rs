@@ -0,0 +1,224 @@
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::middleware::rate_limit::too_many_requests;
+use crate::model::app::AppData;
+use crate::model::audit::NewAuditEvent;
+use crate::model::user::{EmailForm, ResetPasswordForm, TokenForm};
+use crate::route::audit;
+use crate::service::mailer::Mail;
+use crate::utils::security::{public_url, random_id, TokenKeys, RESET_PASSWORD_TOKEN, VERIFY_EMAIL_TOKEN};
+use crate::utils::validation::Validate;
+use actix_web::{get, post, web, Either, HttpRequest, HttpResponse};
+
+/// signs a token for `purpose` and stores its id, which makes it single use
+async fn mail_token(keys: &TokenKeys, user_id: &String, purpose: &str) -> surrealdb::Result<String> {
+    let jti = random_id();
+    db::surrealdb::mail_token_create(user_id, purpose, &jti).await?;
+
+    Ok(keys.sign_with_id(purpose, user_id, "", &jti))
+}
+
+/// checks signature, expiry and purpose, then burns the token
+async fn consume_mail_token(keys: &TokenKeys, token: &str, purpose: &str) -> surrealdb::Result<Option<String>> {
+    let Ok(claims) = keys.verify(token, purpose) else {
+        return Ok(None);
+    };
+
+    if db::surrealdb::mail_token_consume(&claims.sub, purpose, &claims.jti).await? {
+        Ok(Some(claims.sub))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(crate) async fn send_verification(app_data: &AppData, user_id: &String, email: &str) -> anyhow::Result<()> {
+    let token = mail_token(&app_data.token_keys, user_id, VERIFY_EMAIL_TOKEN).await?;
+
+    app_data
+        .mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: "Verify your email".to_string(),
+            body: format!(
+                "Open the link below to verify your email address:\n\n{}/verify_email?token={}\n",
+                public_url(),
+                token
+            ),
+        })
+        .await
+}
+
+#[post("/verify_email/send")]
+pub async fn verify_email_send(app_data: web::Data<AppData>, caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    if let Err(retry_after) = app_data.rate_limiter.acquire_user("/verify_email/send", &user_id).await {
+        return too_many_requests(retry_after);
+    }
+
+    let user = db::surrealdb::profile(&user_id).await.expect("profile err");
+
+    if user.email_verified == Some(true) {
+        return HttpResponse::BadRequest().body("email already verified");
+    }
+
+    match send_verification(&app_data, &user_id, &user.email.unwrap_or_default()).await {
+        Ok(_) => HttpResponse::Ok().body("verification mail sent"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("mail err -> {}", e)),
+    }
+}
+
+#[post("/verify_email")]
+pub async fn verify_email(form: web::Json<TokenForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    verify(&app_data.token_keys, &form.token).await
+}
+
+/// the link in the verification mail
+#[get("/verify_email")]
+pub async fn verify_email_link(query: web::Query<TokenForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    verify(&app_data.token_keys, &query.token).await
+}
+
+async fn verify(keys: &TokenKeys, token: &str) -> HttpResponse {
+    let user_id = consume_mail_token(keys, token, VERIFY_EMAIL_TOKEN)
+        .await
+        .expect("err -> db::surrealdb::mail_token_consume");
+
+    match user_id {
+        Some(user_id) => {
+            db::surrealdb::set_email_verified(&user_id)
+                .await
+                .expect("err -> db::surrealdb::set_email_verified");
+
+            HttpResponse::Ok().body("email verified")
+        }
+        None => HttpResponse::BadRequest().body("invalid or expired token"),
+    }
+}
+
+/// answers the same whether or not the email belongs to an account
+#[post("/password_reset/request")]
+pub async fn password_reset_request(form: web::Json<EmailForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Err(retry_after) = app_data.rate_limiter.acquire_user("/password_reset/request", &form.email).await {
+        return too_many_requests(retry_after);
+    }
+
+    let user = db::surrealdb::user_by_email(&form.email)
+        .await
+        .expect("err -> db::surrealdb::user_by_email");
+
+    if let Some(user) = user {
+        let token = mail_token(&app_data.token_keys, &user.id, RESET_PASSWORD_TOKEN)
+            .await
+            .expect("err -> db::surrealdb::mail_token_create");
+
+        let mail = Mail {
+            to: form.email.clone(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Open the link below to choose a new password for {}:\n\n{}/password_reset?token={}\n\nIf you didn't ask for this, ignore this mail.\n",
+                user.username,
+                public_url(),
+                token
+            ),
+        };
+
+        if let Err(e) = app_data.mailer.send(mail).await {
+            println!("mail err -> {}", e);
+        }
+    }
+
+    HttpResponse::Ok().body("if the email belongs to an account, a reset link was sent")
+}
+
+/// the link in the reset mail, a form that sends the new password to `POST /password_reset`
+#[get("/password_reset")]
+pub async fn password_reset_page(query: web::Query<TokenForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    // only tokens signed here get into the page, they're base64url and dots
+    if app_data.token_keys.verify(&query.token, RESET_PASSWORD_TOKEN).is_err() {
+        return HttpResponse::BadRequest().body("invalid or expired token");
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(format!(
+            r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width"><title>Reset your password</title></head>
+<body>
+<form method="post" action="/password_reset">
+<input type="hidden" name="token" value="{}">
+<label>New password <input type="password" name="password" autocomplete="new-password" required></label>
+<button type="submit">Reset password</button>
+</form>
+</body>
+</html>
+"#,
+            query.token
+        ))
+}
+
+/// the reset link proves ownership of the email, so it is marked verified too.
+/// all sessions end, devices have to log in with the new password. takes JSON
+/// from the frontend or the form of `GET /password_reset`
+#[post("/password_reset")]
+pub async fn password_reset(
+    req: HttpRequest,
+    form: Either<web::Json<ResetPasswordForm>, web::Form<ResetPasswordForm>>,
+    app_data: web::Data<AppData>,
+) -> HttpResponse {
+    let form = match form {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner(),
+    };
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let Ok(claims) = app_data.token_keys.verify(&form.token, RESET_PASSWORD_TOKEN) else {
+        return HttpResponse::BadRequest().body("invalid or expired token");
+    };
+
+    let passwords = &app_data.password_service;
+    let user = db::surrealdb::profile(&claims.sub).await.expect("profile err");
+    let email = user.email.unwrap_or_default();
+
+    if let Err(e) = passwords.check_policy("password", &form.password, &user.username, &email).await {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let user_id = consume_mail_token(&app_data.token_keys, &form.token, RESET_PASSWORD_TOKEN)
+        .await
+        .expect("err -> db::surrealdb::mail_token_consume");
+
+    let Some(user_id) = user_id else {
+        return HttpResponse::BadRequest().body("invalid or expired token");
+    };
+
+    db::surrealdb::set_password(&user_id, &passwords.hash(&form.password).await)
+        .await
+        .expect("err -> db::surrealdb::set_password");
+    db::surrealdb::set_email_verified(&user_id)
+        .await
+        .expect("err -> db::surrealdb::set_email_verified");
+    db::surrealdb::session_revoke_all(&user_id)
+        .await
+        .expect("err -> db::surrealdb::session_revoke_all");
+    app_data
+        .rate_limiter
+        .succeed(&user.username)
+        .await
+        .expect("err -> rate_limiter::succeed");
+    audit::record(
+        NewAuditEvent::by_user("password_change", &user_id)
+            .detail("reset link")
+            .from_request(&req),
+    )
+    .await;
+
+    HttpResponse::Ok().body("password changed")
+}
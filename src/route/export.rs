@@ -0,0 +1,68 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::model::app::AppData;
+use crate::model::user::TokenForm;
+use crate::service::export_service::ExportService;
+use crate::utils::security::EXPORT_TOKEN;
+
+/// starts building an archive of the gallery, the link to it is mailed once it's
+/// ready. there's one at a time, until the last one's link has expired
+#[post("/export")]
+pub async fn export_start(app_data: web::Data<AppData>, exports: web::Data<ExportService>, caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let id = db::surrealdb::export_create(&user_id)
+        .await
+        .expect("err -> db::surrealdb::export_create");
+    let Some(id) = id else {
+        return HttpResponse::Conflict().body("export already running or ready");
+    };
+
+    exports.spawn(app_data, user_id, id.clone());
+
+    HttpResponse::Accepted().json(serde_json::json!({ "id": id }))
+}
+
+/// the latest export and where it is
+#[get("/export")]
+pub async fn export_status(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let export = db::surrealdb::export_latest(&user_id)
+        .await
+        .expect("err -> db::surrealdb::export_latest");
+
+    match export {
+        Some(export) => HttpResponse::Ok().json(export),
+        None => HttpResponse::NotFound().body("no export"),
+    }
+}
+
+/// the link from the mail, it works without logging in until it expires
+#[get("/export/download")]
+pub async fn export_download(
+    req: HttpRequest,
+    query: web::Query<TokenForm>,
+    app_data: web::Data<AppData>,
+) -> Result<HttpResponse, Error> {
+    let Ok(claims) = app_data.token_keys.verify(&query.token, EXPORT_TOKEN) else {
+        return Ok(HttpResponse::Unauthorized().body("link invalid or expired"));
+    };
+
+    let file = db::surrealdb::export_file(&claims.sub, &claims.sid)
+        .await
+        .expect("err -> db::surrealdb::export_file");
+    let Some(file) = file else {
+        return Ok(HttpResponse::NotFound().body("export not found"));
+    };
+
+    let file = NamedFile::open_async(file).await?.set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename("gallery-export.zip".to_string())],
+    });
+
+    Ok(file.into_response(&req))
+}
@@ -1,14 +1,13 @@
 use actix_web::{get, post, web, Error, HttpResponse};
 use crate::db;
-use crate::model::app::AppData;
+use crate::middleware::auth::Caller;
+use crate::utils::validation;
 
 #[post("/follow/{friend_id}")]
-pub async fn follow(app_data: web::Data<AppData>, f: web::Path<String>) -> Result<HttpResponse, Error> {
+pub async fn follow(f: web::Path<String>, caller: Caller) -> Result<HttpResponse, Error> {
     let friend_id = f.into_inner();
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+    validation::record_id("friend_id", &friend_id)?;
+    let user_id = caller.user_id;
 
     db::surrealdb::follow(&user_id, &friend_id).await.expect("err -> db::surrealdb::follow");
 
@@ -16,11 +15,10 @@ pub async fn follow(app_data: web::Data<AppData>, f: web::Path<String>) -> Resul
 }
 
 #[post("/unfollow")]
-pub async fn unfollow(app_data: web::Data<AppData>, body: String) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn unfollow(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("friend_id", &body)?;
+
+    let user_id = caller.user_id;
 
     db::surrealdb::unfollow(&user_id, &body).await.expect("err -> db::surrealdb::follow");
 
@@ -28,11 +26,10 @@ pub async fn unfollow(app_data: web::Data<AppData>, body: String) -> Result<Http
 }
 
 #[post("/follow/accept")]
-pub async fn follow_accept(app_data: web::Data<AppData>, body: String) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn follow_accept(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("friend_id", &body)?;
+
+    let user_id = caller.user_id;
 
     db::surrealdb::follow_accept(&user_id, &body).await.expect("err -> db::surrealdb::follow");
 
@@ -40,11 +37,10 @@ pub async fn follow_accept(app_data: web::Data<AppData>, body: String) -> Result
 }
 
 #[post("/follow/reject")]
-pub async fn follow_reject(app_data: web::Data<AppData>, body: String) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn follow_reject(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("friend_id", &body)?;
+
+    let user_id = caller.user_id;
 
     db::surrealdb::follow_reject(&user_id, &body).await.expect("err -> db::surrealdb::follow");
 
@@ -52,11 +48,8 @@ pub async fn follow_reject(app_data: web::Data<AppData>, body: String) -> Result
 }
 
 #[get("/follow/pendings")]
-pub async fn follow_pendings(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn follow_pendings(caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
     let pendings = db::surrealdb::follow_pendings(&user_id).await.expect("err -> db::surrealdb::follow_pendings");
 
     Ok(HttpResponse::Ok()
@@ -65,11 +58,8 @@ pub async fn follow_pendings(app_data: web::Data<AppData>) -> Result<HttpRespons
 }
 
 #[get("/follow/requests")]
-pub async fn follow_requests(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn follow_requests(caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     let requests = db::surrealdb::follow_requests(&user_id).await.expect("err -> db::surrealdb::follow_requests");
 
@@ -79,11 +69,8 @@ pub async fn follow_requests(app_data: web::Data<AppData>) -> Result<HttpRespons
 }
 
 #[get("/friends")]
-pub async fn friends(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn friends(caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     let friends = db::surrealdb::friends(&user_id).await.expect("err -> db::surrealdb::friends");
 
@@ -93,12 +80,10 @@ pub async fn friends(app_data: web::Data<AppData>) -> Result<HttpResponse, Error
 }
 
 #[get("/friend/{friend_id}/post")]
-pub async fn friend_posts(app_data: web::Data<AppData>, path: web::Path<String>) -> Result<HttpResponse, Error> {
+pub async fn friend_posts(path: web::Path<String>, caller: Caller) -> Result<HttpResponse, Error> {
     let friend_id = path.into_inner();
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+    validation::record_id("friend_id", &friend_id)?;
+    let user_id = caller.user_id;
     let f_posts = db::surrealdb::friend_post(&user_id, &friend_id).await.expect("err -> db::surrealdb::friend_posts");
 
     Ok(HttpResponse::Ok()
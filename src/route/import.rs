@@ -0,0 +1,108 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpResponse};
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::model::app::AppData;
+use crate::model::import::{ImportProgress, FAILED};
+use crate::service::import_service::{ImportService, MAX_ARCHIVE_BYTES};
+
+/// takes a ZIP of images, e.g. one from `POST /export` or a Google Takeout, and
+/// adds them as posts in the background
+#[post("/import")]
+pub async fn import_start(
+    mut payload: Multipart,
+    app_data: web::Data<AppData>,
+    imports: web::Data<ImportService>,
+    caller: Caller,
+) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
+
+    let last_date = db::surrealdb::check_premium(&user_id).await.expect("err -> db::user::check_premium");
+
+    if last_date == 0 {
+        return Err(actix_web::error::ErrorBadRequest("premium not found"));
+    }
+
+    let id = db::surrealdb::import_create(&user_id)
+        .await
+        .expect("err -> db::surrealdb::import_create");
+    let Some(id) = id else {
+        return Ok(HttpResponse::Conflict().body("import already running"));
+    };
+
+    let path = imports.path(&id);
+    let saved = save(&mut payload, &path).await;
+    if let Err(response) = saved {
+        db::surrealdb::import_update(&id, FAILED, &ImportProgress::default(), Some("upload failed"))
+            .await
+            .expect("err -> db::surrealdb::import_update");
+        let _ = tokio::fs::remove_file(&path).await;
+
+        return Ok(response);
+    }
+
+    imports.spawn(app_data, user_id, id.clone());
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({ "id": id })))
+}
+
+/// writes the `.zip` field to `path` as it arrives, other fields are skipped
+async fn save(payload: &mut Multipart, path: &std::path::Path) -> Result<(), HttpResponse> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
+    }
+
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+        let is_zip = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .is_some_and(|name| name.to_lowercase().ends_with(".zip"));
+
+        if !is_zip {
+            while field.next().await.is_some() {}
+            continue;
+        }
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
+        let mut size = 0;
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+            size += chunk.len() as u64;
+            if size > MAX_ARCHIVE_BYTES {
+                return Err(HttpResponse::PayloadTooLarge().body("archive too large"));
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
+        }
+        file.flush()
+            .await
+            .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?;
+
+        return Ok(());
+    }
+
+    Err(HttpResponse::BadRequest().body("zip file not found"))
+}
+
+/// the latest import and how far it has got
+#[get("/import")]
+pub async fn import_status(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let import = db::surrealdb::import_latest(&user_id)
+        .await
+        .expect("err -> db::surrealdb::import_latest");
+
+    match import {
+        Some(import) => HttpResponse::Ok().json(import),
+        None => HttpResponse::NotFound().body("no import"),
+    }
+}
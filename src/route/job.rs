@@ -0,0 +1,20 @@
+use actix_web::{get, web, Error, HttpResponse};
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::utils::validation;
+
+/// how far a background job has got, e.g. the one processing an upload
+#[get("/jobs/{id}")]
+pub async fn job_status(path: web::Path<String>, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("id", &path)?;
+    let user_id = caller.user_id;
+
+    let job = db::surrealdb::job(&user_id, &path)
+        .await
+        .expect("err -> db::surrealdb::job");
+
+    Ok(match job {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().body("job not found"),
+    })
+}
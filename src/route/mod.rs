@@ -1,4 +1,16 @@
 pub mod user;
 pub mod friend;
 pub mod index;
-pub mod post;
\ No newline at end of file
+pub mod post;
+pub mod session;
+pub mod email;
+pub mod two_factor;
+pub mod webauthn;
+pub mod oidc;
+pub mod report;
+pub mod admin;
+pub mod audit;
+pub mod export;
+pub mod import;
+pub mod upload;
+pub mod job;
\ No newline at end of file
@@ -0,0 +1,231 @@
+use crate::db;
+use crate::model::app::AppData;
+use crate::model::oidc::CallbackQuery;
+use crate::route::user::complete_login;
+use crate::route::two_factor;
+use crate::service::oidc::OidcProvider;
+use crate::utils::cookie;
+use crate::utils::oidc::{random_token, IdTokenClaims};
+use crate::utils::security::random_id;
+use crate::utils::validation;
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::SameSite;
+use actix_web::http::header::{HeaderValue, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+
+const STATE_COOKIE: &str = "oidc_state";
+
+/// names of the configured providers, for the login buttons
+#[get("/oidc/providers")]
+pub async fn providers(app_data: web::Data<AppData>) -> HttpResponse {
+    let names: Vec<&str> = app_data.oidc.providers().iter().map(|p| p.name.as_str()).collect();
+
+    HttpResponse::Ok().json(names)
+}
+
+/// redirects to the provider. the state is also set as a cookie so the
+/// callback only completes in the browser that started the login
+#[get("/oidc/{provider}/login")]
+pub async fn login(path: web::Path<String>, app_data: web::Data<AppData>) -> HttpResponse {
+    let Some(provider) = app_data.oidc.provider(&path) else {
+        return HttpResponse::NotFound().body("unknown provider");
+    };
+
+    let (state, nonce, verifier) = (random_token(), random_token(), random_token());
+    db::surrealdb::oidc_state_create(&state, &provider.name, &nonce, &verifier)
+        .await
+        .expect("err -> db::surrealdb::oidc_state_create");
+
+    let url = match app_data.oidc.authorization_url(provider, &state, &nonce, &verifier).await {
+        Ok(url) => url,
+        Err(e) => return HttpResponse::BadGateway().body(format!("oidc err -> {}", e)),
+    };
+
+    // Lax, the callback is a top level navigation coming from the provider's site
+    let state_cookie = cookie::build(STATE_COOKIE, state)
+        .path(format!("/oidc/{}/callback", provider.name))
+        .max_age(Duration::minutes(10))
+        .same_site(SameSite::Lax)
+        .finish();
+
+    HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .cookie(state_cookie)
+        .finish()
+}
+
+#[get("/oidc/{provider}/callback")]
+pub async fn callback(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<CallbackQuery>,
+    app_data: web::Data<AppData>,
+) -> HttpResponse {
+    let Some(provider) = app_data.oidc.provider(&path) else {
+        return HttpResponse::NotFound().body("unknown provider");
+    };
+
+    if let Some(error) = &query.error {
+        return HttpResponse::Unauthorized().body(format!("login cancelled: {}", error));
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return HttpResponse::BadRequest().body("missing code or state");
+    };
+
+    if req.cookie(STATE_COOKIE).is_none_or(|c| c.value() != state) {
+        return HttpResponse::Unauthorized().body("login failed");
+    }
+
+    let stored = db::surrealdb::oidc_state_consume(state, &provider.name)
+        .await
+        .expect("err -> db::surrealdb::oidc_state_consume");
+    let Some(stored) = stored else {
+        return HttpResponse::Unauthorized().body("login failed");
+    };
+
+    let claims = match app_data
+        .oidc
+        .exchange(provider, code, &stored.verifier, &stored.nonce)
+        .await
+    {
+        Ok(claims) => claims,
+        Err(e) => {
+            println!("oidc err -> {}", e);
+            return HttpResponse::Unauthorized().body("login failed");
+        }
+    };
+
+    let user_id = match account(&app_data, provider, &claims).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    let two_factor = db::surrealdb::totp_secret(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_secret");
+
+    let (response, location) = if two_factor.is_some() {
+        (
+            two_factor::challenge(&app_data.token_keys, &user_id),
+            format!("{}?two_factor=required", app_data.oidc.frontend_url()),
+        )
+    } else {
+        (
+            complete_login(&req, &app_data.token_keys, user_id, &format!("oidc {}", provider.name)).await,
+            app_data.oidc.frontend_url().to_string(),
+        )
+    };
+
+    // a suspended account, there are no login cookies to carry over
+    if !response.status().is_success() {
+        return response;
+    }
+
+    redirect(response, &location, &provider.name)
+}
+
+/// the linked account, or an existing account with the same verified email,
+/// or a new account. the provider has to vouch for the email before it is trusted
+async fn account(app_data: &AppData, provider: &OidcProvider, claims: &IdTokenClaims) -> Result<String, HttpResponse> {
+    let linked = db::surrealdb::oidc_identity_user(&provider.name, &claims.sub)
+        .await
+        .expect("err -> db::surrealdb::oidc_identity_user");
+    if let Some(user_id) = linked {
+        return Ok(user_id);
+    }
+
+    let Some(email) = claims.email.as_ref().filter(|_| claims.email_verified == Some(true)) else {
+        return Err(HttpResponse::Forbidden().body("the provider didn't share a verified email"));
+    };
+    if validation::email("email", email).is_err() {
+        return Err(HttpResponse::Forbidden().body("the provider didn't share a verified email"));
+    }
+
+    let existing = db::surrealdb::user_by_email(email)
+        .await
+        .expect("err -> db::surrealdb::user_by_email");
+
+    let user_id = match existing {
+        // whoever registered an unverified email may not own it, linking would let them in later
+        Some(user) => {
+            if !db::surrealdb::email_verified(&user.id)
+                .await
+                .expect("err -> db::surrealdb::email_verified")
+            {
+                return Err(HttpResponse::Conflict()
+                    .body("an account with this email exists, log in and verify the email before linking"));
+            }
+
+            user.id
+        }
+        None => {
+            let username = available_username(claims, email).await;
+            // there is no password, the random one is never shown
+            let user_id = db::surrealdb::register(&username, email, &app_data.password_service.hash(&random_token()).await)
+                .await
+                .expect("err -> db::surrealdb::register");
+            db::surrealdb::set_email_verified(&user_id)
+                .await
+                .expect("err -> db::surrealdb::set_email_verified");
+
+            user_id
+        }
+    };
+
+    db::surrealdb::oidc_identity_add(&user_id, &provider.name, &claims.sub, Some(email))
+        .await
+        .expect("err -> db::surrealdb::oidc_identity_add");
+
+    Ok(user_id)
+}
+
+/// `preferred_username` or the local part of the email, reduced to what
+/// usernames allow, with a random suffix when it is taken
+async fn available_username(claims: &IdTokenClaims, email: &str) -> String {
+    let wanted = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+
+    let mut base: String = wanted
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+        .take(24)
+        .collect();
+    while base.contains("..") {
+        base = base.replace("..", ".");
+    }
+    let base = base.trim_matches('.').to_string();
+    let base = if base.len() < 3 { "user".to_string() } else { base };
+
+    let mut candidate = base.clone();
+    loop {
+        if validation::username("username", &candidate).is_ok()
+            && db::surrealdb::credentials(&candidate)
+                .await
+                .expect("err -> db::surrealdb::credentials")
+                .is_none()
+        {
+            return candidate;
+        }
+
+        candidate = format!("{}_{}", base, &random_id()[..6]);
+    }
+}
+
+/// the callback is a browser navigation, answer with a redirect that keeps the login cookies
+fn redirect(mut response: HttpResponse, location: &str, provider: &str) -> HttpResponse {
+    let state_cookie = cookie::removal(STATE_COOKIE)
+        .path(format!("/oidc/{}/callback", provider))
+        .same_site(SameSite::Lax)
+        .finish();
+
+    *response.status_mut() = StatusCode::FOUND;
+    response.add_cookie(&state_cookie).expect("cookie err");
+    response
+        .headers_mut()
+        .insert(LOCATION, HeaderValue::from_str(location).expect("oidc err -> frontend url"));
+
+    response
+}
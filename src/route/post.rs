@@ -1,37 +1,62 @@
-use std::path::Path;
 use actix_files::NamedFile;
-use actix_multipart::Multipart;
+use actix_multipart::{Field, Multipart};
+use actix_web::http::StatusCode;
 use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
 use futures::StreamExt;
 use serde_json::json;
-use sha2::{Digest, Sha512};
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
-use crate::ai::image_classification::check_safety;
+use std::path::Path;
 use crate::db;
-use crate::model::app::AppData;
-use crate::model::post::UploadForm;
+use crate::middleware::auth::Caller;
+use crate::model::post::{UploadResult, PROCESSING};
+use crate::service::images::{self, Added, Rejected};
+use crate::utils::validation;
 
 #[get("/file/{file}")]
 async fn get_file(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, Error> {
     let file_name = path.into_inner();
+    validation::file_name("file", &file_name)?;
+
+    if db::surrealdb::image_hidden(&file_name)
+        .await
+        .expect("err -> db::surrealdb::image_hidden")
+    {
+        return Err(actix_web::error::ErrorNotFound("file not found"));
+    }
 
     let file = NamedFile::open_async(format!("images/{}", file_name)).await?;
 
     Ok(file.into_response(&req))
 }
 
+/// the thumbnail of an image, there once its post is processed
+#[get("/thumbnail/{file}")]
+async fn get_thumbnail(req: HttpRequest, path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let file_name = path.into_inner();
+    validation::file_name("file", &file_name)?;
+
+    if db::surrealdb::image_hidden(&file_name)
+        .await
+        .expect("err -> db::surrealdb::image_hidden")
+    {
+        return Err(actix_web::error::ErrorNotFound("file not found"));
+    }
+
+    let file = NamedFile::open_async(Path::new(images::THUMBNAIL_DIR).join(&file_name)).await?;
+
+    Ok(file.into_response(&req))
+}
+
 #[post("/post/delete")]
-async fn post_delete(body: String, app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+async fn post_delete(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("post_id", &body)?;
+
+    let user_id = caller.user_id;
 
     let image_name = db::surrealdb::post_delete(&user_id, &body).await.expect("err -> db::surrealdb::post_delete");
 
     match image_name {
         Some(image) => {
+            images::remove_thumbnail(&image).await;
             match tokio::fs::remove_file(format!("images/{}", image)).await {
                 Ok(_) => Ok(HttpResponse::Ok().body("silindi")),
                 Err(_) => {
@@ -46,24 +71,48 @@ async fn post_delete(body: String, app_data: web::Data<AppData>) -> Result<HttpR
 }
 
 #[get("/post")]
-async fn posts(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+async fn posts(caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     let result = db::surrealdb::post_get_all(&user_id).await.expect("err -> db::surrealdb::post_get_all");
 
     Ok(HttpResponse::Ok().content_type("application/json").json(result))
 }
 
+/// the most files one `POST /upload` takes
+const MAX_FILES: usize = 20;
+/// the most bytes of files one `POST /upload` holds on to before they're checked
+const MAX_REQUEST_BYTES: u64 = 2 * images::MAX_IMAGE_BYTES;
+/// the longest a field that isn't a file may be, `ratio` is a few bytes
+const MAX_FIELD_BYTES: usize = 64;
+
+/// the status an upload of a rejected image is answered with
+pub(crate) fn rejected_status(rejected: &Rejected) -> StatusCode {
+    match rejected {
+        Rejected::Exists => StatusCode::NOT_MODIFIED,
+        Rejected::Nsfw => StatusCode::NOT_ACCEPTABLE,
+        Rejected::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        Rejected::NoPremium | Rejected::Invalid(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// the answer to an upload that was taken, its post is processed in the background
+pub(crate) fn accepted(added: &Added) -> serde_json::Value {
+    json!({
+        "id": &added.post_id,
+        "image": &added.image,
+        "status": PROCESSING,
+        "job": &added.job_id
+    })
+}
+
+/// takes one or more files with a `ratio` for all of them or one for each, in
+/// the order of the files. a single file is answered as it always was, several
+/// with a result for each. accepted images are answered with 202 and stay
+/// `processing` until their job has run
 #[post("/upload")]
-async fn upload(mut payload: Multipart, app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let ai_model = &app_data.ai_model;
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+async fn upload(mut payload: Multipart, caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     let last_date = db::surrealdb::check_premium(&user_id).await.expect("err -> db::user::check_premium");
 
@@ -71,11 +120,9 @@ async fn upload(mut payload: Multipart, app_data: web::Data<AppData>) -> Result<
         return Err(actix_web::error::ErrorBadRequest("premium not found"))
     }
 
-    let mut file_name = String::new();
-    let mut file_path = String::new();
-    let mut body = web::BytesMut::new();
-
-    let mut fields = String::from("{");
+    let mut files = Vec::new();
+    let mut ratios = Vec::new();
+    let mut budget = MAX_REQUEST_BYTES;
 
     while let Some(item) = payload.next().await {
         let mut field = item?;
@@ -85,50 +132,31 @@ async fn upload(mut payload: Multipart, app_data: web::Data<AppData>) -> Result<
                 let filename = cd.get_filename().map(ToString::to_string);
 
                 if let Some(filename) = filename {
-                    let (_, extension) = filename.rsplit_once(".").unwrap();
-
-                    let mut hasher = Sha512::new();
-
-                    while let Some(chunk) = field.next().await {
-                        let data = chunk?;
-                        body.extend_from_slice(&data); //
-                        hasher.update(data);
+                    if files.len() == MAX_FILES {
+                        return Ok(HttpResponse::BadRequest().body(format!("At most {} files at once", MAX_FILES)));
                     }
 
-                    let hash = hex::encode(hasher.finalize());
-
-                    file_name = format!("{}.{}", hash, extension);
-                    file_path = format!("images/{}.{}", hash, extension);
-
-                    if Path::new(&file_path).exists() {
-                        return Ok(HttpResponse::NotModified().body("File already exist"));
-                    } else {
-                        let safety = check_safety(ai_model, &body).await.expect("ai err -> check_safety");
-
-                        if !safety {
-                            return Ok(HttpResponse::NotAcceptable().body("NSFW content"));
-                        }
-                    }
+                    let file = read_file(&mut field, &filename, &mut budget).await?;
+                    files.push((filename, file));
                 } else {
-                    let area_name = cd.get_name().unwrap_or("");
+                    let area_name = cd.get_name().unwrap_or("").to_string();
 
                     if area_name.is_empty() {
                         return Ok(HttpResponse::BadRequest().body("Fill in the required fields"));
                     }
 
-                    fields.push('"');
-                    fields.push_str(area_name);
-                    fields.push('"');
-                    fields.push(':');
-                    fields.push('"');
-
+                    let mut value = web::BytesMut::new();
                     while let Some(chunk) = field.next().await {
-                        let data = chunk?;
-                        fields.push_str(&String::from_utf8_lossy(&data));
+                        let chunk = chunk?;
+                        if value.len() + chunk.len() > MAX_FIELD_BYTES {
+                            return Ok(HttpResponse::BadRequest().body(format!("{} too long", area_name)));
+                        }
+                        value.extend_from_slice(&chunk);
                     }
 
-                    fields.push('"');
-                    fields.push(',');
+                    if area_name == "ratio" {
+                        ratios.push(String::from_utf8_lossy(&value).to_string());
+                    }
                 }
             }
             None => {
@@ -137,33 +165,88 @@ async fn upload(mut payload: Multipart, app_data: web::Data<AppData>) -> Result<
         }
     };
 
-    fields.pop();
-
-    fields.push('}');
+    if files.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("Data not found"));
+    }
+    if ratios.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("Invalid field"));
+    }
+    if ratios.len() != 1 && ratios.len() != files.len() {
+        return Ok(HttpResponse::BadRequest().body("One ratio for all files or one for each"));
+    }
 
-    let user_data: Option<UploadForm> = match serde_json::from_str(&fields) {
-        Ok(_user_data) => {
-            _user_data
-        }
-        Err(_) => {
-            return Ok(HttpResponse::BadRequest().body("Invalid field"));
-        }
-    };
+    let mut results = Vec::with_capacity(files.len());
+    for (i, (name, file)) in files.into_iter().enumerate() {
+        let ratio = ratios.get(i).unwrap_or(&ratios[0]).clone();
+        let added = match file {
+            Ok((extension, body)) => images::queue_image(&user_id, &body, &extension, ratio).await,
+            Err(rejected) => Err(rejected),
+        };
+        results.push((name, added));
+    }
 
-    match user_data {
-        Some(user_data) => {
-            let post_id = db::surrealdb::post_add(user_data.ratio, &file_name, &user_id).await.expect("db::surrealdb::err -> post_add");
+    if results.len() == 1 {
+        return Ok(match results.remove(0).1 {
+            Ok(added) => HttpResponse::Accepted().json(accepted(&added)),
+            Err(rejected) => HttpResponse::build(rejected_status(&rejected)).body(rejected.to_string()),
+        });
+    }
 
-            let mut file = File::create(&file_path).await?;
-            file.write_all(&body).await?;
+    let results: Vec<UploadResult> = results
+        .into_iter()
+        .map(|(file, added)| match added {
+            Ok(added) => UploadResult {
+                file,
+                status: StatusCode::ACCEPTED.as_u16(),
+                id: Some(added.post_id),
+                image: Some(added.image),
+                job: Some(added.job_id),
+                error: None,
+            },
+            Err(rejected) => UploadResult {
+                file,
+                status: rejected_status(&rejected).as_u16(),
+                id: None,
+                image: None,
+                job: None,
+                error: Some(rejected.to_string()),
+            },
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(results))
+}
 
-            Ok(HttpResponse::Ok().json(json!({
-                "id": &*post_id,
-                "image": &*file_name
-            })))
+/// the extension and content of a file field. the rest of a file that can't be
+/// taken is read and dropped, so the fields after it still arrive. what's kept
+/// comes out of `budget`, the whole request fails once it's used up
+async fn read_file(field: &mut Field, filename: &str, budget: &mut u64) -> Result<Result<(String, web::BytesMut), Rejected>, Error> {
+    let extension = match filename.rsplit_once(".") {
+        Some((_, extension)) if validation::extension("file", extension).is_ok() => extension.to_string(),
+        _ => {
+            while let Some(chunk) = field.next().await {
+                chunk?;
+            }
+            return Ok(Err(Rejected::Invalid("Invalid file extension".to_string())));
         }
-        None => {
-            Ok(HttpResponse::BadRequest().body("Data not found"))
+    };
+
+    let mut body = web::BytesMut::new();
+    let mut too_large = false;
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        too_large = too_large || (body.len() + chunk.len()) as u64 > images::MAX_IMAGE_BYTES;
+        if !too_large {
+            *budget = budget.checked_sub(chunk.len() as u64).ok_or_else(|| {
+                actix_web::error::ErrorPayloadTooLarge(format!("At most {} bytes at once", MAX_REQUEST_BYTES))
+            })?;
+            body.extend_from_slice(&chunk);
         }
     }
-}
\ No newline at end of file
+
+    if too_large {
+        return Ok(Err(Rejected::TooLarge));
+    }
+
+    Ok(Ok((extension, body)))
+}
@@ -0,0 +1,46 @@
+use actix_web::{post, web, HttpResponse};
+use serde_json::Value;
+
+/// reports are small, anything bigger isn't from a browser
+const MAX_REPORT_BYTES: usize = 64 * 1024;
+
+/// content security policy violations, `application/csp-report` from `report-uri`
+/// or `application/reports+json` from `report-to`. they are only logged
+#[post("/csp-report")]
+pub async fn csp_report(body: web::Bytes) -> HttpResponse {
+    if body.len() > MAX_REPORT_BYTES {
+        return HttpResponse::PayloadTooLarge().finish();
+    }
+
+    let Ok(report) = serde_json::from_slice::<Value>(&body) else {
+        return HttpResponse::BadRequest().body("malformed report");
+    };
+
+    let violations: Vec<&Value> = match &report {
+        Value::Array(reports) => reports
+            .iter()
+            .filter(|r| r["type"] == "csp-violation")
+            .map(|r| &r["body"])
+            .collect(),
+        _ => vec![&report["csp-report"]],
+    };
+
+    for violation in violations.into_iter().filter(|v| v.is_object()) {
+        let field = |legacy: &str, current: &str| {
+            violation[legacy]
+                .as_str()
+                .or_else(|| violation[current].as_str())
+                .unwrap_or("-")
+                .to_string()
+        };
+
+        println!(
+            "csp violation -> {} blocked {} on {}",
+            field("violated-directive", "effectiveDirective"),
+            field("blocked-uri", "blockedURL"),
+            field("document-uri", "documentURL"),
+        );
+    }
+
+    HttpResponse::NoContent().finish()
+}
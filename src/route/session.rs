@@ -0,0 +1,75 @@
+use actix_web::{get, post, Error, HttpRequest, HttpResponse};
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::route::user::removal_cookies;
+use crate::utils::security::random_id;
+use crate::utils::validation;
+
+/// records a new session for the request's client and returns its id
+pub(crate) async fn start(req: &HttpRequest, user_id: &String) -> surrealdb::Result<String> {
+    let session_id = random_id();
+    let ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or_default()
+        .to_string();
+    let user_agent = req
+        .headers()
+        .get("user-agent")
+        .and_then(|ua| ua.to_str().ok())
+        .unwrap_or_default();
+
+    db::surrealdb::session_create(user_id, &session_id, device(user_agent), &ip, user_agent).await?;
+
+    Ok(session_id)
+}
+
+fn device(user_agent: &str) -> &'static str {
+    let ua = user_agent.to_lowercase();
+
+    if ua.contains("ipad") || ua.contains("tablet") {
+        "tablet"
+    } else if ua.contains("mobile") || ua.contains("android") || ua.contains("iphone") {
+        "mobile"
+    } else if ua.is_empty() {
+        "unknown"
+    } else {
+        "desktop"
+    }
+}
+
+#[get("/sessions")]
+pub async fn sessions(caller: Caller) -> Result<HttpResponse, Error> {
+    let Caller { user_id, session_id } = caller;
+
+    let mut sessions = db::surrealdb::sessions(&user_id).await.expect("err -> db::surrealdb::sessions");
+
+    for session in sessions.iter_mut() {
+        session.current = session.id == session_id;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(sessions))
+}
+
+#[post("/sessions/revoke")]
+pub async fn session_revoke(caller: Caller, body: String) -> Result<HttpResponse, Error> {
+    validation::record_id("session_id", &body)?;
+
+    db::surrealdb::session_revoke(&caller.user_id, &body).await.expect("err -> db::surrealdb::session_revoke");
+
+    Ok(HttpResponse::Ok().body(""))
+}
+
+#[post("/sessions/revoke_all")]
+pub async fn session_revoke_all(caller: Caller) -> Result<HttpResponse, Error> {
+    db::surrealdb::session_revoke_all(&caller.user_id).await.expect("err -> db::surrealdb::session_revoke_all");
+
+    let mut response = HttpResponse::Ok();
+    for cookie in removal_cookies() {
+        response.cookie(cookie);
+    }
+
+    Ok(response.body("logged out everywhere"))
+}
@@ -0,0 +1,190 @@
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::middleware::rate_limit::{retry_after_secs, too_many_requests};
+use crate::model::app::AppData;
+use crate::model::audit::NewAuditEvent;
+use crate::model::user::CodeForm;
+use crate::route::audit;
+use crate::route::user::complete_login;
+use crate::utils::cookie::{self, PRE_AUTH};
+use crate::utils::security::{TokenKeys, PRE_AUTH_TOKEN};
+use crate::utils::totp;
+use crate::utils::validation::Validate;
+use actix_web::cookie::time::Duration;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde_json::json;
+
+/// answer to a correct password when the account has 2fa, the pre-auth
+/// cookie only opens `/login/2fa`
+pub(crate) fn challenge(keys: &TokenKeys, user_id: &str) -> HttpResponse {
+    let pre_auth_cookie = cookie::build(PRE_AUTH, keys.sign(PRE_AUTH_TOKEN, user_id, ""))
+        .path("/login/2fa")
+        .max_age(Duration::seconds(keys.pre_auth_ttl.num_seconds()))
+        .finish();
+
+    HttpResponse::Accepted()
+        .cookie(pre_auth_cookie)
+        .body("two factor required")
+}
+
+/// a current totp code that wasn't used before
+async fn check_totp(user_id: &String, secret: &str, code: &str) -> surrealdb::Result<bool> {
+    match totp::verify(secret, code, Utc::now().timestamp()) {
+        Some(step) => db::surrealdb::totp_use_step(user_id, step).await,
+        None => Ok(false),
+    }
+}
+
+#[post("/login/2fa")]
+pub async fn login_two_factor(req: HttpRequest, form: web::Json<CodeForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let claims = match req.cookie(PRE_AUTH).map(|c| app_data.token_keys.verify(c.value(), PRE_AUTH_TOKEN)) {
+        Some(Ok(claims)) => claims,
+        _ => return HttpResponse::Unauthorized().body("login failed"),
+    };
+    let user_id = claims.sub;
+
+    let rate_limiter = &app_data.rate_limiter;
+    let username = db::surrealdb::profile(&user_id).await.expect("profile err").username;
+
+    if let Err(retry_after) = rate_limiter.acquire_user("/login/2fa", &username).await {
+        return too_many_requests(retry_after);
+    }
+    if let Some(retry_after) = rate_limiter.locked(&username).await.expect("err -> rate_limiter::locked") {
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after_secs(retry_after)))
+            .body("account locked");
+    }
+
+    let secret = db::surrealdb::totp_secret(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_secret");
+
+    let verified = match secret {
+        Some(secret) if totp::is_code(&form.code) => {
+            check_totp(&user_id, &secret, &form.code)
+                .await
+                .expect("err -> db::surrealdb::totp_use_step")
+        }
+        Some(_) => db::surrealdb::recovery_code_use(&user_id, &totp::hash_recovery_code(&form.code))
+            .await
+            .expect("err -> db::surrealdb::recovery_code_use"),
+        None => false,
+    };
+
+    if !verified {
+        rate_limiter.fail(&username).await.expect("err -> rate_limiter::fail");
+        audit::record(
+            NewAuditEvent::new("login")
+                .target(&user_id)
+                .failed()
+                .detail("two factor code")
+                .from_request(&req),
+        )
+        .await;
+
+        return HttpResponse::Unauthorized().body("login failed");
+    }
+
+    rate_limiter.succeed(&username).await.expect("err -> rate_limiter::succeed");
+
+    let mut res = complete_login(&req, &app_data.token_keys, user_id, "password and two factor").await;
+    res.add_cookie(&cookie::removal(PRE_AUTH).path("/login/2fa").finish())
+        .expect("cookie err");
+
+    res
+}
+
+/// starts enrolment, 2fa is on once a code from the app is sent to `/2fa/enable`
+#[post("/2fa/enroll")]
+pub async fn enroll(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    if db::surrealdb::totp_secret(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_secret")
+        .is_some()
+    {
+        return HttpResponse::BadRequest().body("two factor already enabled");
+    }
+
+    let secret = totp::generate_secret();
+    db::surrealdb::totp_set_pending(&user_id, &secret)
+        .await
+        .expect("err -> db::surrealdb::totp_set_pending");
+
+    let username = db::surrealdb::profile(&user_id).await.expect("profile err").username;
+    let issuer = std::env::var("TOTP_ISSUER")
+        .unwrap_or_else(|_| std::env::var("DOMAIN").expect("env err -> DOMAIN"));
+
+    HttpResponse::Ok().json(json!({
+        "secret": secret,
+        "uri": totp::otpauth_uri(&issuer, &username, &secret),
+    }))
+}
+
+/// confirms enrolment and returns the recovery codes, the only time they are shown
+#[post("/2fa/enable")]
+pub async fn enable(form: web::Json<CodeForm>, caller: Caller) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let user_id = caller.user_id;
+
+    let pending = db::surrealdb::totp_pending(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_pending");
+
+    let Some(pending) = pending else {
+        return HttpResponse::BadRequest().body("no enrolment in progress");
+    };
+    let Some(step) = totp::verify(&pending, &form.code, Utc::now().timestamp()) else {
+        return HttpResponse::BadRequest().body("invalid code");
+    };
+
+    let recovery_codes = totp::generate_recovery_codes();
+    db::surrealdb::totp_enable(
+        &user_id,
+        step,
+        recovery_codes.iter().map(|c| totp::hash_recovery_code(c)).collect(),
+    )
+    .await
+    .expect("err -> db::surrealdb::totp_enable");
+
+    HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes }))
+}
+
+#[post("/2fa/disable")]
+pub async fn disable(form: web::Json<CodeForm>, caller: Caller) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let user_id = caller.user_id;
+
+    let secret = db::surrealdb::totp_secret(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_secret");
+
+    let Some(secret) = secret else {
+        return HttpResponse::BadRequest().body("two factor not enabled");
+    };
+
+    if !check_totp(&user_id, &secret, &form.code)
+        .await
+        .expect("err -> db::surrealdb::totp_use_step")
+    {
+        return HttpResponse::Forbidden().body("invalid code");
+    }
+
+    db::surrealdb::totp_disable(&user_id)
+        .await
+        .expect("err -> db::surrealdb::totp_disable");
+
+    HttpResponse::Ok().body("two factor disabled")
+}
@@ -0,0 +1,225 @@
+use actix_web::http::StatusCode;
+use actix_web::{delete, head, patch, post, web, Error, HttpRequest, HttpResponse, HttpResponseBuilder};
+use data_encoding::BASE64;
+use futures::StreamExt;
+use std::collections::HashMap;
+use tokio::io::AsyncWriteExt;
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::model::app::AppData;
+use crate::model::upload::Upload;
+use crate::route::post::{accepted, rejected_status};
+use crate::service::images::MAX_IMAGE_BYTES;
+use crate::utils::validation;
+
+/// resumable uploads follow the tus protocol, https://tus.io/protocols/resumable-upload,
+/// with its creation, expiration and termination extensions
+const TUS_VERSION: &str = "1.0.0";
+
+fn tus(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("Tus-Resumable", TUS_VERSION));
+    response
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|h| h.to_str().ok())
+}
+
+/// every request has to say which version of the protocol it speaks, the
+/// answer to one that doesn't speak ours
+fn unsupported(req: &HttpRequest) -> Option<HttpResponse> {
+    if header(req, "Tus-Resumable") == Some(TUS_VERSION) {
+        return None;
+    }
+
+    Some(
+        tus(StatusCode::PRECONDITION_FAILED)
+            .insert_header(("Tus-Version", TUS_VERSION))
+            .body("unsupported tus version"),
+    )
+}
+
+/// `Upload-Metadata`, comma separated keys each followed by a base64 value
+fn metadata(value: &str) -> HashMap<String, String> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.trim().split_once(' ').unwrap_or((pair.trim(), ""));
+            let value = BASE64.decode(value.as_bytes()).ok()?;
+
+            Some((key.to_string(), String::from_utf8(value).ok()?))
+        })
+        .collect()
+}
+
+fn expires(upload: &Upload) -> String {
+    upload.expires_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+async fn find(user_id: &String, id: &str) -> Result<Upload, HttpResponse> {
+    let upload = db::surrealdb::upload(user_id, id)
+        .await
+        .expect("err -> db::surrealdb::upload");
+
+    upload.ok_or_else(|| tus(StatusCode::NOT_FOUND).body("upload not found"))
+}
+
+/// starts an upload of `Upload-Length` bytes, `Upload-Metadata` has the
+/// `filename` and `ratio`. the parts are sent to the `Location` answered with
+#[post("/upload/resumable")]
+pub async fn upload_create(req: HttpRequest, app_data: web::Data<AppData>, caller: Caller) -> Result<HttpResponse, Error> {
+    if let Some(response) = unsupported(&req) {
+        return Ok(response);
+    }
+    let user_id = caller.user_id;
+
+    let last_date = db::surrealdb::check_premium(&user_id).await.expect("err -> db::user::check_premium");
+
+    if last_date == 0 {
+        return Err(actix_web::error::ErrorBadRequest("premium not found"));
+    }
+
+    let Some(length) = header(&req, "Upload-Length").and_then(|l| l.parse::<u64>().ok()) else {
+        return Ok(tus(StatusCode::BAD_REQUEST).body("Upload-Length missing or invalid"));
+    };
+    if length > MAX_IMAGE_BYTES {
+        return Ok(tus(StatusCode::PAYLOAD_TOO_LARGE).insert_header(("Tus-Max-Size", MAX_IMAGE_BYTES)).body("File too large"));
+    }
+
+    let mut metadata = header(&req, "Upload-Metadata").map(metadata).unwrap_or_default();
+    let name = metadata.remove("filename").or_else(|| metadata.remove("name")).unwrap_or_default();
+    match name.rsplit_once('.') {
+        Some((_, extension)) if validation::extension("file", extension).is_ok() => {}
+        _ => return Ok(tus(StatusCode::BAD_REQUEST).body("Invalid file extension")),
+    }
+    let ratio = metadata.remove("ratio").unwrap_or_default();
+    validation::ratio("ratio", &ratio)?;
+
+    let upload = app_data
+        .upload_service
+        .create(&user_id, name, ratio, length)
+        .await
+        .expect("upload service err -> create");
+    let Some(upload) = upload else {
+        return Ok(tus(StatusCode::CONFLICT).body("too many uploads open"));
+    };
+
+    Ok(tus(StatusCode::CREATED)
+        .insert_header(("Location", format!("/upload/resumable/{}", upload.id)))
+        .insert_header(("Upload-Expires", expires(&upload)))
+        .finish())
+}
+
+/// how much of the upload has arrived, to carry on from there
+#[head("/upload/resumable/{id}")]
+pub async fn upload_offset(req: HttpRequest, path: web::Path<String>, app_data: web::Data<AppData>, caller: Caller) -> Result<HttpResponse, Error> {
+    if let Some(response) = unsupported(&req) {
+        return Ok(response);
+    }
+    validation::record_id("id", &path)?;
+    let upload = match find(&caller.user_id, &path).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+
+    let offset = app_data.upload_service.offset(&upload).await.expect("upload service err -> offset");
+
+    Ok(tus(StatusCode::OK)
+        .insert_header(("Upload-Offset", offset))
+        .insert_header(("Upload-Length", upload.length))
+        .insert_header(("Upload-Expires", expires(&upload)))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish())
+}
+
+/// appends the body at `Upload-Offset`, which has to be where the upload is
+/// at. what arrives is kept when the connection drops. the part that
+/// completes the upload is answered like `POST /upload`
+#[patch("/upload/resumable/{id}")]
+pub async fn upload_append(
+    req: HttpRequest,
+    path: web::Path<String>,
+    mut payload: web::Payload,
+    app_data: web::Data<AppData>,
+    caller: Caller,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = unsupported(&req) {
+        return Ok(response);
+    }
+    if header(&req, "Content-Type") != Some("application/offset+octet-stream") {
+        return Ok(tus(StatusCode::UNSUPPORTED_MEDIA_TYPE).body("Content-Type must be application/offset+octet-stream"));
+    }
+    let Some(offset) = header(&req, "Upload-Offset").and_then(|o| o.parse::<u64>().ok()) else {
+        return Ok(tus(StatusCode::BAD_REQUEST).body("Upload-Offset missing or invalid"));
+    };
+    validation::record_id("id", &path)?;
+    let upload = match find(&caller.user_id, &path).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+
+    let Some(_busy) = app_data.upload_service.lock(&upload.id) else {
+        return Ok(tus(StatusCode::LOCKED).body("upload in progress"));
+    };
+
+    let current = app_data.upload_service.offset(&upload).await.expect("upload service err -> offset");
+    if offset != current {
+        return Ok(tus(StatusCode::CONFLICT).insert_header(("Upload-Offset", current)).body("Upload-Offset doesn't match"));
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&upload.file)
+        .await
+        .expect("upload err -> open");
+    let mut offset = current;
+    let mut received = Ok(());
+    while let Some(chunk) = payload.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                received = Err(e);
+                break;
+            }
+        };
+        if offset + chunk.len() as u64 > upload.length as u64 {
+            file.flush().await.expect("upload err -> flush");
+            return Ok(tus(StatusCode::BAD_REQUEST).insert_header(("Upload-Offset", offset)).body("more than Upload-Length"));
+        }
+        file.write_all(&chunk).await.expect("upload err -> write");
+        offset += chunk.len() as u64;
+    }
+    file.flush().await.expect("upload err -> flush");
+    received?;
+
+    if offset < upload.length as u64 {
+        return Ok(tus(StatusCode::NO_CONTENT).insert_header(("Upload-Offset", offset)).finish());
+    }
+
+    Ok(match app_data.upload_service.finish(&caller.user_id, &upload).await {
+        Ok(added) => tus(StatusCode::ACCEPTED).insert_header(("Upload-Offset", offset)).json(accepted(&added)),
+        Err(rejected) => tus(rejected_status(&rejected)).insert_header(("Upload-Offset", offset)).body(rejected.to_string()),
+    })
+}
+
+/// gives up on the upload
+#[delete("/upload/resumable/{id}")]
+pub async fn upload_cancel(req: HttpRequest, path: web::Path<String>, app_data: web::Data<AppData>, caller: Caller) -> Result<HttpResponse, Error> {
+    if let Some(response) = unsupported(&req) {
+        return Ok(response);
+    }
+    validation::record_id("id", &path)?;
+    let upload = match find(&caller.user_id, &path).await {
+        Ok(upload) => upload,
+        Err(response) => return Ok(response),
+    };
+
+    let Some(_busy) = app_data.upload_service.lock(&upload.id) else {
+        return Ok(tus(StatusCode::LOCKED).body("upload in progress"));
+    };
+
+    app_data.upload_service.remove(&upload).await.expect("upload service err -> remove");
+
+    Ok(tus(StatusCode::NO_CONTENT).finish())
+}
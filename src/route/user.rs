@@ -1,126 +1,244 @@
 use crate::db;
+use crate::middleware::auth::Caller;
+use crate::middleware::csrf::csrf_cookie;
+use crate::middleware::rate_limit::{retry_after_secs, too_many_requests};
 use crate::model::app::AppData;
+use crate::model::audit::NewAuditEvent;
+use crate::model::deletion::DeletionStatus;
 use crate::model::user::{ChangePasswordForm, LoginForm, RegisterForm};
-use crate::utils::security::{   sign};
+use crate::route::{audit, email, session, two_factor};
+use crate::utils::cookie::{self, LOGGED, PREMIUM, REFRESH, TOKEN};
+use crate::utils::security::{TokenKeys, ACCESS_TOKEN, REFRESH_TOKEN};
+use crate::utils::validation::{self, Validate};
 use actix_web::cookie::time::{Duration, OffsetDateTime};
-use actix_web::cookie::{Cookie, SameSite};
-use actix_web::{get, post, web, Error, HttpResponse};
+use actix_web::cookie::Cookie;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
 use std::str::FromStr;
 use web3::types::{Address, BlockId, H256, U64};
 
+/// deactivates the account and schedules its deletion, it's hidden from
+/// everyone else until then
 #[post("/delete")]
-pub async fn delete(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn delete(req: HttpRequest, app_data: web::Data<AppData>, caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
+
+    if app_data
+        .deletion_service
+        .scheduled(&user_id)
+        .await
+        .expect("deletion service err -> scheduled")
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict().body("deletion already requested"));
+    }
 
     app_data
         .deletion_service
         .delete(&user_id)
         .await
         .expect("deletion service err -> ");
+    audit::record(NewAuditEvent::by_user("deletion_request", &user_id).from_request(&req)).await;
 
-    let cookie = Cookie::build("token", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    let logged_cookie = Cookie::build("logged", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    let premium_cookie = Cookie::build("premium", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
+    let mut response = HttpResponse::Ok();
+    for cookie in removal_cookies() {
+        response.cookie(cookie);
+    }
 
-    Ok(HttpResponse::Ok()
-        .cookie(cookie)
-        .cookie(logged_cookie)
-        .cookie(premium_cookie)
-        .body("Delete successful"))
+    Ok(response.body("Delete successful"))
+}
+
+#[get("/delete/status")]
+pub async fn delete_status(app_data: web::Data<AppData>, caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let scheduled = app_data
+        .deletion_service
+        .scheduled(&user_id)
+        .await
+        .expect("deletion service err -> scheduled");
+
+    HttpResponse::Ok().json(DeletionStatus {
+        pending: scheduled.is_some(),
+        scheduled_at: scheduled.map(|at| at.to_rfc3339()),
+    })
+}
+
+/// logging in during the grace period doesn't cancel the deletion, this does
+#[post("/delete/cancel")]
+pub async fn delete_cancel(req: HttpRequest, app_data: web::Data<AppData>, caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    if app_data
+        .deletion_service
+        .scheduled(&user_id)
+        .await
+        .expect("deletion service err -> scheduled")
+        .is_none()
+    {
+        return HttpResponse::NotFound().body("no deletion scheduled");
+    }
+
+    app_data
+        .deletion_service
+        .cancel(&user_id)
+        .await
+        .expect("deletion service err -> cancel");
+    audit::record(NewAuditEvent::by_user("deletion_cancel", &user_id).from_request(&req)).await;
+
+    HttpResponse::Ok().body("deletion cancelled")
 }
 
 #[get("/logout")]
-pub async fn logout() -> HttpResponse {
-    let cookie = Cookie::build("token", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    let logged_cookie = Cookie::build("logged", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    let premium_cookie = Cookie::build("premium", "")
-        .max_age(Duration::ZERO)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    HttpResponse::Ok()
-        .cookie(cookie)
-        .cookie(logged_cookie)
-        .cookie(premium_cookie)
-        .body("exit successful")
+pub async fn logout(req: HttpRequest, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Some(Ok(claims)) = req.cookie(TOKEN).map(|c| app_data.token_keys.verify(c.value(), ACCESS_TOKEN)) {
+        db::surrealdb::session_revoke(&claims.sub, &claims.sid)
+            .await
+            .expect("err -> db::surrealdb::session_revoke");
+    }
+
+    let mut response = HttpResponse::Ok();
+    for cookie in removal_cookies() {
+        response.cookie(cookie);
+    }
+
+    response.body("exit successful")
 }
 
 #[post("/login")]
-pub async fn login(form: web::Json<LoginForm>, app_data: web::Data<AppData>) -> HttpResponse {
+pub async fn login(req: HttpRequest, form: web::Json<LoginForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
 
-    let user_id = db::surrealdb::login(&form.username, &form.password)
+    let rate_limiter = &app_data.rate_limiter;
+
+    if let Err(retry_after) = rate_limiter.acquire_user("/login", &form.username).await {
+        return too_many_requests(retry_after);
+    }
+    if let Some(retry_after) = rate_limiter.locked(&form.username).await.expect("err -> rate_limiter::locked") {
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after_secs(retry_after)))
+            .body("account locked");
+    }
+
+    let passwords = &app_data.password_service;
+    let credentials = db::surrealdb::credentials(&form.username)
         .await
-        .unwrap();
+        .expect("err -> db::surrealdb::credentials");
+
+    let verified = passwords
+        .verify(&form.password, credentials.as_ref().map(|c| c.password.as_str()))
+        .await;
+
+    if !verified {
+        rate_limiter.fail(&form.username).await.expect("err -> rate_limiter::fail");
+
+        let event = match &credentials {
+            Some(credentials) => NewAuditEvent::new("login").target(&credentials.id),
+            // not the attempted name, it's often a password typed into the wrong field
+            None => NewAuditEvent::new("login").detail("unknown username".to_string()),
+        };
+        audit::record(event.failed().from_request(&req)).await;
 
-    if user_id == "-1" {
         HttpResponse::Unauthorized().body("login failed")
     } else {
-        let token = sign("token", &user_id);
+        let credentials = credentials.unwrap();
+        let user_id = credentials.id;
+
+        rate_limiter.succeed(&form.username).await.expect("err -> rate_limiter::succeed");
+
+        if passwords.needs_rehash(&credentials.password) {
+            db::surrealdb::set_password(&user_id, &passwords.hash(&form.password).await)
+                .await
+                .expect("err -> db::surrealdb::set_password");
+        }
 
-        app_data
-            .deletion_service
-            .cancel(&user_id)
+        let two_factor = db::surrealdb::totp_secret(&user_id)
             .await
-            .expect("deletion service cancel err ->");
+            .expect("err -> db::surrealdb::totp_secret");
 
-        let mut uid = app_data.user_id.lock().unwrap();
-        *uid = user_id;
+        if two_factor.is_some() {
+            return two_factor::challenge(&app_data.token_keys, &user_id);
+        }
 
-        let logged_cookie = Cookie::build("logged", "1")
-            .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-            .finish();
-        let token_cookie = Cookie::build("token", token)
-            .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-            .http_only(true)
-            .same_site(SameSite::Strict)
-            .finish();
-        HttpResponse::Ok()
-            .cookie(token_cookie)
-            .cookie(logged_cookie)
-            .body("login successful")
+        complete_login(&req, &app_data.token_keys, user_id, "password").await
+    }
+}
+
+/// starts the session and sets the token cookies, once every login step has passed.
+/// `method` is recorded in the audit log
+pub(crate) async fn complete_login(req: &HttpRequest, keys: &TokenKeys, user_id: String, method: &str) -> HttpResponse {
+    let event = NewAuditEvent::new("login").target(&user_id).from_request(req);
+
+    if db::surrealdb::suspended(&user_id)
+        .await
+        .expect("err -> db::surrealdb::suspended")
+    {
+        audit::record(event.failed().detail(format!("{}, account suspended", method))).await;
+
+        return HttpResponse::Forbidden().body("account suspended");
     }
+
+    let session_id = session::start(req, &user_id)
+        .await
+        .expect("err -> db::surrealdb::session_create");
+    let cookies = session_cookies(keys, &user_id, &session_id);
+    audit::record(event.actor(&user_id).detail(method)).await;
+
+    let mut response = HttpResponse::Ok();
+    for cookie in cookies {
+        response.cookie(cookie);
+    }
+
+    response.body("login successful")
 }
 
 #[post("/change_password")]
 pub async fn change_password(
+    req: HttpRequest,
     form: web::Json<ChangePasswordForm>,
     app_data: web::Data<AppData>,
+    caller: Caller,
 ) -> HttpResponse {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let Caller { user_id, session_id } = caller;
+
+    let passwords = &app_data.password_service;
+    let hash = db::surrealdb::password_hash(&user_id)
+        .await
+        .expect("err -> db::surrealdb::password_hash");
+
+    if !passwords.verify(&form.old, hash.as_deref()).await {
+        audit::record(NewAuditEvent::by_user("password_change", &user_id).failed().from_request(&req)).await;
+
+        return HttpResponse::Forbidden().body("wrong password");
+    }
+
+    let user = db::surrealdb::profile(&user_id).await.expect("profile err");
+    let email = user.email.unwrap_or_default();
 
-    db::surrealdb::change_password(&user_id, &form.old, &form.new)
+    if let Err(e) = passwords.check_policy("new", &form.new, &user.username, &email).await {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    db::surrealdb::set_password(&user_id, &passwords.hash(&form.new).await)
         .await
-        .expect("change password err");
+        .expect("err -> db::surrealdb::set_password");
+
+    db::surrealdb::session_revoke_others(&user_id, &session_id)
+        .await
+        .expect("err -> db::surrealdb::session_revoke_others");
+    audit::record(NewAuditEvent::by_user("password_change", &user_id).from_request(&req)).await;
 
     HttpResponse::Ok().body("password changed")
 }
 
 #[get("/profile")]
-pub async fn profile(app_data: web::Data<AppData>) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn profile(caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     let profile = db::surrealdb::profile(&user_id).await.expect("profile err");
 
@@ -130,40 +248,104 @@ pub async fn profile(app_data: web::Data<AppData>) -> Result<HttpResponse, Error
 }
 
 #[post("/register")]
-pub async fn register(form: web::Json<RegisterForm>, app_data: web::Data<AppData>) -> HttpResponse {
+pub async fn register(req: HttpRequest, form: web::Json<RegisterForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Err(e) = form.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    let passwords = &app_data.password_service;
+
+    if let Err(e) = passwords
+        .check_policy("password", &form.password, &form.username, &form.email)
+        .await
+    {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
     let user_id = db::surrealdb::register(
         &String::from(&form.username),
         &String::from(&form.email),
-        &String::from(&form.password),
+        &passwords.hash(&form.password).await,
     )
     .await
     .expect("err -> db::surrealdb::register");
-    let token = sign("token", &user_id);
+    let session_id = session::start(&req, &user_id)
+        .await
+        .expect("err -> db::surrealdb::session_create");
+    let cookies = session_cookies(&app_data.token_keys, &user_id, &session_id);
 
-    let mut uid = app_data.user_id.lock().unwrap();
-    *uid = user_id;
+    if let Err(e) = email::send_verification(&app_data, &user_id, &form.email).await {
+        println!("mail err -> {}", e);
+    }
+
+    let mut response = HttpResponse::Ok();
+    for cookie in cookies {
+        response.cookie(cookie);
+    }
+
+    response.body("register successful")
+}
 
-    let logged_cookie = Cookie::build("logged", "1")
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .finish();
-    let token_cookie = Cookie::build("token", token)
-        .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
-        .http_only(true)
-        .same_site(SameSite::Strict)
-        .finish();
+#[post("/refresh")]
+pub async fn refresh(req: HttpRequest, app_data: web::Data<AppData>) -> HttpResponse {
+    let keys = &app_data.token_keys;
+
+    let claims = match req.cookie(REFRESH).map(|c| keys.verify(c.value(), REFRESH_TOKEN)) {
+        Some(Ok(claims)) => claims,
+        _ => return HttpResponse::Unauthorized().body("refresh failed"),
+    };
+
+    let active = db::surrealdb::session_touch(&claims.sub, &claims.sid)
+        .await
+        .expect("err -> db::surrealdb::session_touch");
+
+    if !active {
+        return HttpResponse::Unauthorized().body("session revoked");
+    }
+
+    let token_cookie = cookie::build(TOKEN, keys.sign(ACCESS_TOKEN, &claims.sub, &claims.sid)).finish();
 
     HttpResponse::Ok()
         .cookie(token_cookie)
-        .cookie(logged_cookie)
-        .body("register successful")
+        .cookie(refresh_cookie(keys, keys.sign(REFRESH_TOKEN, &claims.sub, &claims.sid)))
+        .body("refresh successful")
+}
+
+fn refresh_cookie(keys: &TokenKeys, refresh_token: String) -> Cookie<'static> {
+    let max_age = keys.refresh_ttl.num_seconds();
+
+    cookie::build(REFRESH, refresh_token)
+        .path("/refresh")
+        .max_age(Duration::seconds(max_age))
+        .finish()
+}
+
+/// the access and refresh tokens, the `logged` flag the frontend reads and a
+/// new csrf token, so one planted before the login is worthless after it
+fn session_cookies(keys: &TokenKeys, user_id: &str, session_id: &str) -> Vec<Cookie<'static>> {
+    vec![
+        cookie::build(TOKEN, keys.sign(ACCESS_TOKEN, user_id, session_id)).finish(),
+        refresh_cookie(keys, keys.sign(REFRESH_TOKEN, user_id, session_id)),
+        cookie::build(LOGGED, "1").http_only(false).finish(),
+        csrf_cookie(),
+    ]
+}
+
+/// expires what [`session_cookies`] and `/premium` set
+pub(crate) fn removal_cookies() -> Vec<Cookie<'static>> {
+    vec![
+        cookie::removal(TOKEN).finish(),
+        cookie::removal(LOGGED).finish(),
+        cookie::removal(PREMIUM).finish(),
+        cookie::removal(REFRESH).path("/refresh").finish(),
+    ]
 }
 
 #[post("/users")]
-pub async fn users(app_data: web::Data<AppData>, body: String) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn users(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::username_prefix("username", &body)?;
+
+    let user_id = caller.user_id;
 
     let users = db::surrealdb::user_search(&user_id, &body)
         .await
@@ -175,11 +357,8 @@ pub async fn users(app_data: web::Data<AppData>, body: String) -> Result<HttpRes
 }
 
 #[get("/premium")]
-pub async fn check_premium(app_data: web::Data<AppData>) -> HttpResponse {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn check_premium(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
 
     if user_id.is_empty() {
         return HttpResponse::Unauthorized().body(format!("{}", false));
@@ -192,9 +371,9 @@ pub async fn check_premium(app_data: web::Data<AppData>) -> HttpResponse {
     if last_date == 0 {
         HttpResponse::Unauthorized().body("Premium not found")
     } else {
-        let premium_cookie = Cookie::build("premium", "1")
+        let premium_cookie = cookie::build(PREMIUM, "1")
             .expires(OffsetDateTime::from_unix_timestamp(last_date / 1000).unwrap())
-            .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
+            .http_only(false)
             .finish();
 
         HttpResponse::Ok()
@@ -204,31 +383,20 @@ pub async fn check_premium(app_data: web::Data<AppData>) -> HttpResponse {
 }
 
 #[post("/payment")]
-pub async fn payment(app_data: web::Data<AppData>, body: String) -> Result<HttpResponse, Error> {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn payment(req: HttpRequest, app_data: web::Data<AppData>, body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    let user_id = caller.user_id;
 
     if user_id.is_empty() {
-        return Err(actix_web::error::ErrorBadRequest(format!("{}", body)));
+        return Err(actix_web::error::ErrorBadRequest(body.to_string()));
     }
 
-    if db::surrealdb::check_transaction(&body)
+    if !db::surrealdb::email_verified(&user_id)
         .await
-        .expect("err -> db::surrealdb::check_transaction")
+        .expect("err -> db::surrealdb::email_verified")
     {
-        return Err(actix_web::error::ErrorBadRequest(
-            "transaction already exist",
-        ));
+        return Err(actix_web::error::ErrorForbidden("email not verified"));
     }
 
-    db::surrealdb::add_transaction(&user_id, &body)
-        .await
-        .expect("err -> db::surrealdb::add_transaction");
-
-    let crypto_network = app_data.crypto_network.clone();
-
     let hash_str = body.trim_start_matches("0x");
     let tx_hash: H256 = match hex::decode(hash_str) {
         Ok(h) => {
@@ -240,6 +408,21 @@ pub async fn payment(app_data: web::Data<AppData>, body: String) -> Result<HttpR
         Err(_) => return Err(actix_web::error::ErrorBadRequest("invalid hash format")),
     };
 
+    if db::surrealdb::check_transaction(&body)
+        .await
+        .expect("err -> db::surrealdb::check_transaction")
+    {
+        return Err(actix_web::error::ErrorBadRequest(
+            "transaction already exist",
+        ));
+    }
+
+    db::surrealdb::add_transaction(&user_id, &body)
+        .await
+        .expect("err -> db::surrealdb::add_transaction");
+
+    let crypto_network = app_data.crypto_network.clone();
+
     let mut receipt = None;
     let max_attempts = 6;
     let mut attempts = 0;
@@ -269,7 +452,12 @@ pub async fn payment(app_data: web::Data<AppData>, body: String) -> Result<HttpR
 
     let receipt = receipt.unwrap();
 
+    // the transaction is stored from here on, a rejected one is worth a record
+    let payment = NewAuditEvent::by_user("payment", &user_id).from_request(&req);
+
     if receipt.status != Some(U64::from(1)) {
+        audit::record(payment.failed().detail(format!("{}: validator not accepted", body))).await;
+
         return Err(actix_web::error::ErrorBadRequest("validator not accepted"));
     }
 
@@ -307,9 +495,13 @@ pub async fn payment(app_data: web::Data<AppData>, body: String) -> Result<HttpR
     if let Some(to_address) = tx.to {
         if format!("{:?}", to_address).to_lowercase() != format!("{:?}", my_address).to_lowercase()
         {
+            audit::record(payment.failed().detail(format!("{}: wrong address", body))).await;
+
             return Err(actix_web::error::ErrorBadRequest("wrong address"));
         }
     } else {
+        audit::record(payment.failed().detail(format!("{}: invalid address", body))).await;
+
         return Err(actix_web::error::ErrorBadRequest("invalid address"));
     }
 
@@ -319,24 +511,25 @@ pub async fn payment(app_data: web::Data<AppData>, body: String) -> Result<HttpR
         db::surrealdb::add_premium(&user_id, &body, &transaction_date)
             .await
             .expect("err -> db::user::add_premium");
-        let premium_cookie = Cookie::build("premium", "1")
+        audit::record(payment.detail(format!("{}: {} avax", body, amount))).await;
+
+        let premium_cookie = cookie::build(PREMIUM, "1")
             .expires(OffsetDateTime::from_unix_timestamp(transaction_date as i64).unwrap())
-            .domain(std::env::var("DOMAIN").expect("env err -> DOMAIN"))
+            .http_only(false)
             .finish();
         Ok(HttpResponse::Ok()
             .cookie(premium_cookie)
             .body(format!("{}", transaction_date)))
     } else {
+        audit::record(payment.failed().detail(format!("{}: {} avax is too little", body, amount))).await;
+
         Err(actix_web::error::ErrorBadRequest("invalid money"))
     }
 }
 
 #[get("/upload_limit")]
-pub async fn upload_limit(app_data: web::Data<AppData>) -> HttpResponse {
-    let user_id = {
-        let uid = app_data.user_id.lock().unwrap();
-        uid.clone()
-    };
+pub async fn upload_limit(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
 
     if user_id.is_empty() {
         HttpResponse::Unauthorized().body(format!("{}", false));
@@ -0,0 +1,253 @@
+use crate::db;
+use crate::middleware::auth::Caller;
+use crate::middleware::rate_limit::too_many_requests;
+use crate::model::app::AppData;
+use crate::model::audit::NewAuditEvent;
+use crate::model::webauthn::{AuthenticationCredential, LoginStartForm, RegisterFinishForm};
+use crate::route::audit;
+use crate::route::user::complete_login;
+use crate::utils::validation;
+use crate::utils::webauthn::{self, RelyingParty, ES256, RS256};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use serde_json::json;
+
+const REGISTER: &str = "register";
+const LOGIN: &str = "login";
+const TIMEOUT_MS: i64 = 300_000;
+
+/// options for `navigator.credentials.create()`
+#[post("/webauthn/register/start")]
+pub async fn register_start(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let rp = RelyingParty::from_env();
+    let challenge = webauthn::challenge();
+    db::surrealdb::webauthn_challenge_create(&challenge, REGISTER, Some(&user_id))
+        .await
+        .expect("err -> db::surrealdb::webauthn_challenge_create");
+
+    let username = db::surrealdb::profile(&user_id).await.expect("profile err").username;
+    let existing = db::surrealdb::passkeys(&user_id).await.expect("err -> db::surrealdb::passkeys");
+
+    HttpResponse::Ok().json(json!({
+        "challenge": challenge,
+        "rp": { "id": rp.id, "name": rp.name },
+        "user": {
+            "id": webauthn::encode(user_id.as_bytes()),
+            "name": username,
+            "displayName": username,
+        },
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": ES256 },
+            { "type": "public-key", "alg": RS256 },
+        ],
+        "timeout": TIMEOUT_MS,
+        "attestation": "none",
+        "excludeCredentials": existing
+            .iter()
+            .map(|p| json!({ "type": "public-key", "id": p.credential_id }))
+            .collect::<Vec<_>>(),
+        "authenticatorSelection": {
+            "residentKey": "preferred",
+            "userVerification": "required",
+        },
+    }))
+}
+
+#[post("/webauthn/register/finish")]
+pub async fn register_finish(form: web::Json<RegisterFinishForm>, caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let name = form.name.clone().unwrap_or_else(|| "Passkey".to_string());
+    if name.trim().is_empty() || name.chars().count() > 64 {
+        return HttpResponse::BadRequest().body("name: 1-64 characters");
+    }
+
+    let response = &form.credential.response;
+    let (Ok(client_data_json), Ok(attestation_object)) = (
+        webauthn::decode(&response.client_data_json),
+        webauthn::decode(&response.attestation_object),
+    ) else {
+        return HttpResponse::BadRequest().body("malformed credential");
+    };
+    let Ok(challenge) = webauthn::client_challenge(&client_data_json) else {
+        return HttpResponse::BadRequest().body("malformed credential");
+    };
+
+    if !db::surrealdb::webauthn_challenge_consume(&challenge, REGISTER, Some(&user_id))
+        .await
+        .expect("err -> db::surrealdb::webauthn_challenge_consume")
+    {
+        return HttpResponse::BadRequest().body("unknown or expired challenge");
+    }
+
+    let key = match webauthn::verify_registration(&RelyingParty::from_env(), &challenge, &client_data_json, &attestation_object) {
+        Ok(key) => key,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+
+    let credential_id = webauthn::encode(&key.credential_id);
+    if credential_id != form.credential.id.trim_end_matches('=') {
+        return HttpResponse::BadRequest().body("credential id mismatch");
+    }
+    if db::surrealdb::passkey_by_credential(&credential_id)
+        .await
+        .expect("err -> db::surrealdb::passkey_by_credential")
+        .is_some()
+    {
+        return HttpResponse::Conflict().body("passkey already registered");
+    }
+
+    db::surrealdb::passkey_add(
+        &user_id,
+        &credential_id,
+        &webauthn::encode(&key.public_key),
+        key.alg,
+        key.sign_count,
+        &name,
+    )
+    .await
+    .expect("err -> db::surrealdb::passkey_add");
+
+    HttpResponse::Ok().body("passkey registered")
+}
+
+/// options for `navigator.credentials.get()`, without a username the
+/// browser offers the discoverable passkeys it has for this site
+#[post("/webauthn/login/start")]
+pub async fn login_start(form: web::Json<LoginStartForm>, app_data: web::Data<AppData>) -> HttpResponse {
+    if let Some(username) = &form.username {
+        if let Err(retry_after) = app_data.rate_limiter.acquire_user("/webauthn/login/start", username).await {
+            return too_many_requests(retry_after);
+        }
+    }
+
+    let rp = RelyingParty::from_env();
+    let challenge = webauthn::challenge();
+    db::surrealdb::webauthn_challenge_create(&challenge, LOGIN, None)
+        .await
+        .expect("err -> db::surrealdb::webauthn_challenge_create");
+
+    let mut allow_credentials = Vec::new();
+    if let Some(username) = &form.username {
+        let credentials = db::surrealdb::credentials(username)
+            .await
+            .expect("err -> db::surrealdb::credentials");
+
+        if let Some(credentials) = credentials {
+            allow_credentials = db::surrealdb::passkeys(&credentials.id)
+                .await
+                .expect("err -> db::surrealdb::passkeys")
+                .into_iter()
+                .map(|p| json!({ "type": "public-key", "id": p.credential_id }))
+                .collect();
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "challenge": challenge,
+        "rpId": rp.id,
+        "timeout": TIMEOUT_MS,
+        "allowCredentials": allow_credentials,
+        "userVerification": "required",
+    }))
+}
+
+/// a verified passkey stands in for both the password and the second factor
+#[post("/webauthn/login/finish")]
+pub async fn login_finish(
+    req: HttpRequest,
+    form: web::Json<AuthenticationCredential>,
+    app_data: web::Data<AppData>,
+) -> HttpResponse {
+    let response = &form.response;
+    let (Ok(client_data_json), Ok(authenticator_data), Ok(signature)) = (
+        webauthn::decode(&response.client_data_json),
+        webauthn::decode(&response.authenticator_data),
+        webauthn::decode(&response.signature),
+    ) else {
+        return HttpResponse::BadRequest().body("malformed credential");
+    };
+    let Ok(challenge) = webauthn::client_challenge(&client_data_json) else {
+        return HttpResponse::BadRequest().body("malformed credential");
+    };
+
+    if !db::surrealdb::webauthn_challenge_consume(&challenge, LOGIN, None)
+        .await
+        .expect("err -> db::surrealdb::webauthn_challenge_consume")
+    {
+        return HttpResponse::Unauthorized().body("login failed");
+    }
+
+    let passkey = db::surrealdb::passkey_by_credential(&form.id.trim_end_matches('=').to_string())
+        .await
+        .expect("err -> db::surrealdb::passkey_by_credential");
+    let Some(passkey) = passkey else {
+        return HttpResponse::Unauthorized().body("login failed");
+    };
+
+    if let Some(user_handle) = &response.user_handle {
+        if webauthn::decode(user_handle).ok().as_deref() != Some(passkey.user.as_bytes()) {
+            return HttpResponse::Unauthorized().body("login failed");
+        }
+    }
+
+    let Ok(public_key) = webauthn::decode(&passkey.public_key) else {
+        return HttpResponse::InternalServerError().body("stored passkey is corrupt");
+    };
+
+    let sign_count = match webauthn::verify_assertion(
+        &RelyingParty::from_env(),
+        &challenge,
+        &public_key,
+        passkey.sign_count as u32,
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+    ) {
+        Ok(sign_count) => sign_count,
+        Err(_) => {
+            audit::record(
+                NewAuditEvent::new("login")
+                    .target(&passkey.user)
+                    .failed()
+                    .detail("passkey")
+                    .from_request(&req),
+            )
+            .await;
+
+            return HttpResponse::Unauthorized().body("login failed");
+        }
+    };
+
+    db::surrealdb::passkey_used(&passkey.id, sign_count)
+        .await
+        .expect("err -> db::surrealdb::passkey_used");
+
+    complete_login(&req, &app_data.token_keys, passkey.user, "passkey").await
+}
+
+#[get("/webauthn/passkeys")]
+pub async fn passkeys(caller: Caller) -> HttpResponse {
+    let user_id = caller.user_id;
+
+    let list = db::surrealdb::passkeys(&user_id).await.expect("err -> db::surrealdb::passkeys");
+
+    HttpResponse::Ok().json(list)
+}
+
+#[post("/webauthn/passkeys/delete")]
+pub async fn passkey_delete(body: String, caller: Caller) -> Result<HttpResponse, Error> {
+    validation::record_id("passkey_id", &body)?;
+
+    let user_id = caller.user_id;
+
+    if db::surrealdb::passkey_delete(&user_id, &body)
+        .await
+        .expect("err -> db::surrealdb::passkey_delete")
+    {
+        Ok(HttpResponse::Ok().body("passkey deleted"))
+    } else {
+        Ok(HttpResponse::NotFound().body("passkey not found"))
+    }
+}
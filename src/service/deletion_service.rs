@@ -1,102 +1,323 @@
 use crate::db;
+use crate::model::deletion::DeletionRequest;
+use crate::model::erasure::{ErasureReport, ErasureStep, DELETED};
+use crate::service::images::{thumbnail_name, THUMBNAIL_DIR};
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::security::random_id;
 use chrono::{DateTime, Duration, Utc};
 use std::collections::HashMap;
-use std::fs;
+use std::env;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::fs;
 use tokio::time::sleep;
 
+#[derive(Clone, Debug)]
+pub struct DeletionConfig {
+    /// time between runs of the job
+    pub interval: Duration,
+    /// time between the request and the deletion
+    pub grace: Duration,
+    /// how long a claimed request is left to this instance before another may retry it
+    pub lease: Duration,
+    /// wait after the first failure, doubled with every one after it up to `retry_max`
+    pub retry_base: Duration,
+    pub retry_max: Duration,
+}
+
+impl Default for DeletionConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::hours(1),
+            grace: Duration::days(30),
+            lease: Duration::hours(1),
+            retry_base: Duration::minutes(5),
+            retry_max: Duration::hours(12),
+        }
+    }
+}
+
+impl DeletionConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(interval) = env::var("DELETION_INTERVAL_SECS") {
+            config.interval = Duration::seconds(interval.parse().expect("env err -> DELETION_INTERVAL_SECS"));
+        }
+        if let Ok(grace) = env::var("DELETION_GRACE_DAYS") {
+            config.grace = Duration::days(grace.parse().expect("env err -> DELETION_GRACE_DAYS"));
+        }
+        if let Ok(lease) = env::var("DELETION_LEASE_SECS") {
+            config.lease = Duration::seconds(lease.parse().expect("env err -> DELETION_LEASE_SECS"));
+        }
+        if let Ok(retry) = env::var("DELETION_RETRY_SECS") {
+            config.retry_base = Duration::seconds(retry.parse().expect("env err -> DELETION_RETRY_SECS"));
+        }
+        if let Ok(retry) = env::var("DELETION_RETRY_MAX_SECS") {
+            config.retry_max = Duration::seconds(retry.parse().expect("env err -> DELETION_RETRY_MAX_SECS"));
+        }
+
+        config
+    }
+
+    /// a deletion is never given up on, it keeps being retried every `retry_max`
+    fn retry_after(&self, attempts: i64) -> Duration {
+        let factor = 1i32.checked_shl(attempts.clamp(0, 30) as u32).unwrap_or(i32::MAX);
+
+        self.retry_base
+            .checked_mul(factor)
+            .map_or(self.retry_max, |wait| wait.min(self.retry_max))
+    }
+}
+
+/// what one run of the job did
+#[derive(Debug, Default, PartialEq)]
+pub struct Processed {
+    pub deleted: usize,
+    pub failed: usize,
+}
+
+/// pending deletions live in the `deletion_request` table, so they survive
+/// restarts and any instance can run the job
 #[derive(Clone)]
 pub struct DeletionService {
-    requests: Arc<Mutex<HashMap<String, chrono::DateTime<Utc>>>>,
+    instance: String,
+    config: DeletionConfig,
+    clock: Arc<dyn Clock>,
 }
 
 impl DeletionService {
-    pub fn new() -> Self {
+    pub fn new(config: DeletionConfig) -> Self {
         Self {
-            requests: Arc::new(Mutex::new(HashMap::new())),
+            instance: random_id(),
+            config,
+            clock: Arc::new(SystemClock),
         }
     }
 
-    pub fn from(requests: HashMap<String, DateTime<Utc>>) -> Self {
-        Self {
-            requests: Arc::new(Mutex::new(requests)),
-        }
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
+    /// schedules the account for deletion once the grace period is over
     pub async fn delete(&self, user_id: &String) -> Result<(), String> {
-        let mut requests = self.requests.lock().await;
+        let created = db::surrealdb::deletion_request_create(user_id, &(self.clock.now() + self.config.grace))
+            .await
+            .map_err(|e| e.to_string())?;
 
-        if requests.contains_key(user_id) {
-            return Err(format!("User already exists: {}", user_id));
+        if !created {
+            return Err(format!("deletion already requested: {}", user_id));
         }
 
-        requests.insert(user_id.clone(), Utc::now() + Duration::days(30)); // Duration::days(30)
-
-        println!("{:?}", requests);
-
         Ok(())
     }
 
     pub async fn cancel(&self, user_id: &String) -> Result<(), String> {
-        let mut requests = self.requests.lock().await;
+        db::surrealdb::deletion_request_delete(user_id)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn scheduled(&self, user_id: &String) -> Result<Option<DateTime<Utc>>, String> {
+        let request = db::surrealdb::deletion_request(user_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(request.map(|r| r.scheduled_at))
+    }
+
+    pub async fn pending(&self) -> Result<Vec<DeletionRequest>, String> {
+        db::surrealdb::deletion_requests().await.map_err(|e| e.to_string())
+    }
 
-        if requests.contains_key(user_id) {
-            requests.remove(user_id);
+    /// moves the requests `requests.json` used to hold into the database,
+    /// keeping a request already there
+    pub async fn import(&self, requests: HashMap<String, DateTime<Utc>>) -> Result<(), String> {
+        for (user_id, scheduled_at) in requests {
+            db::surrealdb::deletion_request_create(&user_id, &scheduled_at)
+                .await
+                .map_err(|e| e.to_string())?;
         }
+
         Ok(())
     }
 
-    async fn delete_account(&self, user_id: &String) -> Result<(), String> {
-        let posts = db::surrealdb::post_get_all(user_id)
+    /// erases everything tied to the account, then checks nothing is left and
+    /// keeps a report of it. safe to run again after it stopped half way, what
+    /// is gone already is skipped and the counts of the earlier run are kept
+    pub async fn erase(&self, user_id: &String) -> Result<ErasureReport, String> {
+        let username = db::surrealdb::username(user_id)
             .await
-            .expect("err -> db::surrealdb::post_get_all");
-        for post in posts.0 {
-            match fs::remove_file(format!("images/{}", post.image)) {
-                Ok(_) => println!("deleted image: {}", post.image),
-                Err(e) => return Err(format!("{}", e)),
+            .map_err(|e| e.to_string())?;
+
+        // the user record holds the posts, so they are only there while it is
+        let posts = match &username {
+            Some(_) => db::surrealdb::post_get_all(user_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .0,
+            None => Vec::new(),
+        };
+        let mut images = 0;
+        for post in &posts {
+            match fs::remove_file(format!("images/{}", post.image)).await {
+                Ok(_) => images += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("image {}: {}", post.image, e)),
             }
         }
-        db::surrealdb::user_delete(user_id)
+        let thumbnail = |image: &str| Path::new(THUMBNAIL_DIR).join(thumbnail_name(image));
+        let mut thumbnails = 0;
+        for post in &posts {
+            match fs::remove_file(thumbnail(&post.image)).await {
+                Ok(_) => thumbnails += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("thumbnail {}: {}", post.image, e)),
+            }
+        }
+
+        let exports = db::surrealdb::export_files(user_id)
             .await
-            .expect("err -> db::surrealdb::user_delete");
-        db::surrealdb::friend_delete(user_id)
+            .map_err(|e| e.to_string())?;
+        let mut archives = 0;
+        for file in &exports {
+            match fs::remove_file(file).await {
+                Ok(_) => archives += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("export {}: {}", file, e)),
+            }
+        }
+
+        let uploads = db::surrealdb::upload_files(user_id)
             .await
-            .expect("err -> db::surrealdb::friend_delete");
+            .map_err(|e| e.to_string())?;
+        let mut parts = 0;
+        for file in &uploads {
+            match fs::remove_file(file).await {
+                Ok(_) => parts += 1,
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("upload {}: {}", file, e)),
+            }
+        }
 
-        Ok(())
-    }
+        let mut steps = vec![
+            ErasureStep::new("posts", DELETED, posts.len() as i64),
+            ErasureStep::new("images", DELETED, images),
+            ErasureStep::new("thumbnails", DELETED, thumbnails),
+            ErasureStep::new("export archives", DELETED, archives),
+            ErasureStep::new("upload files", DELETED, parts),
+        ];
+        steps.extend(
+            db::surrealdb::erase_user_records(user_id, username.as_deref())
+                .await
+                .map_err(|e| e.to_string())?,
+        );
 
-    async fn process(&self) {
-        let mut deleting = Vec::<String>::new();
-        let mut requests = self.requests.lock().await;
+        let mut remaining = db::surrealdb::erase_user_remaining(user_id, username.as_deref())
+            .await
+            .map_err(|e| e.to_string())?;
+        let images_left = left(posts.iter().map(|post| Path::new("images").join(&post.image))).await;
+        remaining.insert("images".to_string(), images_left);
+        let thumbnails_left = left(posts.iter().map(|post| thumbnail(&post.image))).await;
+        remaining.insert("thumbnails".to_string(), thumbnails_left);
+        let archives_left = left(exports.iter().map(PathBuf::from)).await;
+        remaining.insert("export archives".to_string(), archives_left);
+        let parts_left = left(uploads.iter().map(PathBuf::from)).await;
+        remaining.insert("upload files".to_string(), parts_left);
 
-        requests.retain(|user_id, request| {
-            if *request > Utc::now() {
-                deleting.push(user_id.clone());
-                return false;
+        if let Some(earlier) = db::surrealdb::erasure_report(user_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            for step in &mut steps {
+                if let Some(before) = earlier.steps.iter().find(|s| s.data == step.data) {
+                    step.count += before.count;
+                }
             }
-            true
-        });
+        }
 
-        for user_id in deleting {
-            self.delete_account(&user_id)
-                .await
-                .expect("delete account error");
+        let remaining: Vec<String> = remaining
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(data, _)| data)
+            .collect();
+        let verified = remaining.is_empty();
+        let now = self.clock.now();
+        let report = ErasureReport {
+            user_id: user_id.clone(),
+            steps,
+            remaining,
+            verified,
+            completed_at: verified.then(|| now.to_rfc3339()),
+        };
+        db::surrealdb::erasure_report_save(&report, verified.then_some(&now))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !report.verified {
+            return Err(format!("left after erasure: {}", report.remaining.join(", ")));
         }
+
+        Ok(report)
     }
 
+    /// deletes the accounts that are due. one that fails is left for a later
+    /// run, the others go ahead
+    pub async fn process(&self) -> Result<Processed, String> {
+        let now = self.clock.now();
+        let due = db::surrealdb::deletion_requests_claim(&self.instance, &now, self.config.lease.num_seconds())
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut processed = Processed::default();
+
+        for request in due {
+            match self.finish(&request).await {
+                Ok(()) => processed.deleted += 1,
+                Err(e) => {
+                    let retry_at = now + self.config.retry_after(request.attempts);
+                    println!("deletion err -> {} {}, retrying at {}", request.user_id, e, retry_at);
+                    db::surrealdb::deletion_request_failed(&request.user_id, &e, &retry_at)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    processed.failed += 1;
+                }
+            }
+        }
+
+        Ok(processed)
+    }
+
+    async fn finish(&self, request: &DeletionRequest) -> Result<(), String> {
+        self.erase(&request.user_id).await?;
+        db::surrealdb::deletion_request_delete(&request.user_id)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// runs the job every `interval`, starting now so requests that fell due
+    /// while the server was down aren't left waiting
     pub async fn start(self) {
-        let this = self.clone();
+        let interval = self.config.interval.to_std().expect("deletion service err -> interval");
         tokio::spawn(async move {
             loop {
-                sleep(tokio::time::Duration::from_secs(12*60*60)).await;
-                this.process().await;
+                if let Err(e) = self.process().await {
+                    println!("deletion service err -> {}", e);
+                }
+                sleep(interval).await;
             }
         });
     }
+}
 
-    pub async fn get_requests(&self) -> Arc<Mutex<HashMap<String, DateTime<Utc>>>> {
-        self.requests.clone()
+/// how many of the files are still there, one that can't be checked counts as left
+async fn left(files: impl Iterator<Item = PathBuf>) -> i64 {
+    let mut left = 0;
+    for file in files {
+        if fs::try_exists(&file).await.unwrap_or(true) {
+            left += 1;
+        }
     }
+    left
 }
@@ -0,0 +1,169 @@
+use crate::db;
+use crate::model::export::ExportManifest;
+use crate::model::app::AppData;
+use crate::service::mailer::Mail;
+use crate::utils::security::{public_url, EXPORT_TOKEN};
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use tokio::time::sleep;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// builds archives of a user's gallery in `dir`. they are written to disk an
+/// image at a time and served from there, so a large gallery never has to fit in memory
+#[derive(Clone)]
+pub struct ExportService {
+    dir: PathBuf,
+}
+
+impl ExportService {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// builds the export in the background and mails the download link once it's ready
+    pub fn spawn(&self, app_data: web::Data<AppData>, user_id: String, id: String) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = this.build(&app_data, &user_id, &id).await {
+                println!("export err -> {} {}", id, e);
+                db::surrealdb::export_failed(&id, &e)
+                    .await
+                    .expect("err -> db::surrealdb::export_failed");
+            }
+        });
+    }
+
+    async fn build(&self, app_data: &AppData, user_id: &String, id: &str) -> Result<(), String> {
+        let manifest = manifest(user_id).await.map_err(|e| e.to_string())?;
+        let email = manifest.email.clone();
+        let path = self.dir.join(format!("{}.zip", id));
+
+        let file = path.clone();
+        let size = tokio::task::spawn_blocking(move || write(&file, &manifest))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+
+        let keys = &app_data.token_keys;
+        let expires_at = Utc::now() + keys.ttl(EXPORT_TOKEN);
+        db::surrealdb::export_ready(id, &path.to_string_lossy(), size, &expires_at)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // the archive is there either way, a mail that doesn't go out is only logged
+        if let Some(email) = email {
+            let mail = Mail {
+                to: email,
+                subject: "Your gallery export is ready".to_string(),
+                body: format!(
+                    "Your export is ready, the link below works until {}:\n\n{}/export/download?token={}\n",
+                    expires_at.format("%Y-%m-%d %H:%M UTC"),
+                    public_url(),
+                    keys.sign(EXPORT_TOKEN, user_id, id)
+                ),
+            };
+            if let Err(e) = app_data.mailer.send(mail).await {
+                println!("export mail err -> {} {}", id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// removes the exports whose link has expired by `now`
+    pub async fn purge(&self, now: &DateTime<Utc>) -> Result<usize, String> {
+        let files = db::surrealdb::exports_expire(now)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for file in &files {
+            match tokio::fs::remove_file(file).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => println!("export purge err -> {} {}", file, e),
+            }
+        }
+
+        Ok(files.len())
+    }
+
+    /// fails the exports a restart cut short and removes what they had written
+    pub async fn recover(&self) -> Result<usize, String> {
+        let ids = db::surrealdb::exports_interrupted()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for id in &ids {
+            let part = self.dir.join(format!("{}.zip.part", id));
+            match tokio::fs::remove_file(&part).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => println!("export recover err -> {} {}", id, e),
+            }
+        }
+
+        Ok(ids.len())
+    }
+
+    pub async fn start(self) {
+        if let Err(e) = self.recover().await {
+            println!("export recover err -> {}", e);
+        }
+        tokio::spawn(async move {
+            loop {
+                sleep(tokio::time::Duration::from_secs(60 * 60)).await;
+                if let Err(e) = self.purge(&Utc::now()).await {
+                    println!("export purge err -> {}", e);
+                }
+            }
+        });
+    }
+}
+
+async fn manifest(user_id: &String) -> surrealdb::Result<ExportManifest> {
+    let user = db::surrealdb::profile(user_id).await?;
+
+    Ok(ExportManifest {
+        username: user.username,
+        email: user.email,
+        posts: db::surrealdb::post_get_all(user_id).await?.0,
+        friends: db::surrealdb::friends(user_id).await?,
+        payments: db::surrealdb::payments(user_id).await?,
+        exported_at: Utc::now().to_rfc3339(),
+    })
+}
+
+/// images are already compressed, they are stored as they are. the archive
+/// only gets its name once it's complete
+fn write(path: &Path, manifest: &ExportManifest) -> io::Result<u64> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let part = path.with_extension("zip.part");
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    let mut zip = ZipWriter::new(File::create(&part)?);
+
+    zip.start_file("manifest.json", options)?;
+    serde_json::to_writer_pretty(&mut zip, manifest)?;
+
+    for post in &manifest.posts {
+        let mut image = match File::open(format!("images/{}", post.image)) {
+            Ok(image) => image,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        zip.start_file(format!("images/{}", post.image), options)?;
+        io::copy(&mut image, &mut zip)?;
+    }
+
+    zip.finish()?.sync_all()?;
+    fs::rename(&part, path)?;
+
+    Ok(fs::metadata(path)?.len())
+}
@@ -0,0 +1,158 @@
+use crate::ai::image_classification::check_safety;
+use crate::db;
+use crate::model::job::PROCESS_IMAGE;
+use crate::model::post::PROCESSING;
+use crate::utils::validation;
+use crate::ai::classifier::Classifier;
+use crate::ai::moderation::Verdict;
+use image::ImageReader;
+use sha2::{Digest, Sha512};
+use std::fmt;
+use std::io::{Cursor, ErrorKind};
+use std::path::Path;
+
+/// the largest image taken, larger ones are turned away rather than read into memory
+pub const MAX_IMAGE_BYTES: u64 = 50 * 1024 * 1024;
+
+/// why an image wasn't added
+#[derive(Debug, PartialEq)]
+pub enum Rejected {
+    /// no premium, or its uploads are used up
+    NoPremium,
+    /// the same image is stored already
+    Exists,
+    Nsfw,
+    /// over [`MAX_IMAGE_BYTES`]
+    TooLarge,
+    Invalid(String),
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejected::NoPremium => write!(f, "premium not found"),
+            Rejected::Exists => write!(f, "File already exist"),
+            Rejected::Nsfw => write!(f, "NSFW content"),
+            Rejected::TooLarge => write!(f, "File too large"),
+            Rejected::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// where [`JobService`](crate::service::job_service::JobService) puts thumbnails
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+
+/// what was stored, processing goes on in the job
+#[derive(Debug)]
+pub struct Added {
+    pub post_id: String,
+    pub image: String,
+    pub job_id: String,
+}
+
+/// the checks every new image goes through, uploaded or imported: premium with
+/// uploads left, not stored yet, not scored for rejection. then writes the file
+/// under its hash and adds the post, which is processing until its job has run
+/// and held for review after that if its scores call for it. for callers
+/// that are in the background already, the request handlers use [`queue_image`]
+pub async fn add_image(
+    classifier: &Classifier,
+    user_id: &String,
+    body: &[u8],
+    extension: &str,
+    ratio: String,
+) -> Result<Added, Rejected> {
+    let file_name = check(user_id, body, extension, &ratio).await?;
+
+    let scores = check_safety(classifier, body)
+        .await
+        .map_err(|e| Rejected::Invalid(format!("invalid image: {}", e)))?;
+    let verdict = classifier.moderation().verdict(&scores);
+    if verdict == Verdict::Reject {
+        return Err(Rejected::Nsfw);
+    }
+
+    let added = store(user_id, body, file_name, ratio, false).await;
+    db::surrealdb::post_scored(user_id, &added.post_id, &scores, verdict == Verdict::Review)
+        .await
+        .expect("err -> db::surrealdb::post_scored");
+
+    Ok(added)
+}
+
+/// like [`add_image`], leaving the nsfw check to the job so the request
+/// doesn't wait for it
+pub async fn queue_image(user_id: &String, body: &[u8], extension: &str, ratio: String) -> Result<Added, Rejected> {
+    let file_name = check(user_id, body, extension, &ratio).await?;
+
+    Ok(store(user_id, body, file_name, ratio, true).await)
+}
+
+/// the file name the image is stored under if it can be added
+async fn check(user_id: &String, body: &[u8], extension: &str, ratio: &str) -> Result<String, Rejected> {
+    validation::extension("file", extension).map_err(|e| Rejected::Invalid(e.to_string()))?;
+    validation::ratio("ratio", ratio).map_err(|e| Rejected::Invalid(e.to_string()))?;
+
+    if body.len() as u64 > MAX_IMAGE_BYTES {
+        return Err(Rejected::TooLarge);
+    }
+
+    if db::surrealdb::check_premium(user_id)
+        .await
+        .expect("err -> db::user::check_premium")
+        == 0
+    {
+        return Err(Rejected::NoPremium);
+    }
+
+    // only the header is read, decoding is left to the job
+    ImageReader::new(Cursor::new(body))
+        .with_guessed_format()
+        .map_err(|e| Rejected::Invalid(format!("invalid image: {}", e)))?
+        .into_dimensions()
+        .map_err(|e| Rejected::Invalid(format!("invalid image: {}", e)))?;
+
+    let file_name = format!("{}.{}", hex::encode(Sha512::digest(body)), extension);
+
+    if Path::new(&format!("images/{}", file_name)).exists() {
+        return Err(Rejected::Exists);
+    }
+
+    Ok(file_name)
+}
+
+async fn store(user_id: &String, body: &[u8], file_name: String, ratio: String, classify: bool) -> Added {
+    tokio::fs::write(format!("images/{}", file_name), body)
+        .await
+        .expect("images err -> write");
+    let post_id = db::surrealdb::post_add(ratio, &file_name, user_id, PROCESSING)
+        .await
+        .expect("db::surrealdb::err -> post_add");
+    let job_id = db::surrealdb::job_create(user_id, PROCESS_IMAGE, &post_id, &file_name, classify)
+        .await
+        .expect("err -> db::surrealdb::job_create");
+
+    Added {
+        post_id,
+        image: file_name,
+        job_id,
+    }
+}
+
+/// thumbnails are jpegs named after the image
+pub fn thumbnail_name(image: &str) -> String {
+    let stem = image.rsplit_once('.').map_or(image, |(stem, _)| stem);
+
+    format!("{}.jpg", stem)
+}
+
+/// removes the image's thumbnail, if it has one
+pub async fn remove_thumbnail(image: &str) {
+    let thumbnail = Path::new(THUMBNAIL_DIR).join(thumbnail_name(image));
+
+    match tokio::fs::remove_file(&thumbnail).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => println!("thumbnail err -> {} {}", thumbnail.display(), e),
+    }
+}
@@ -0,0 +1,241 @@
+use crate::db;
+use crate::model::app::AppData;
+use crate::model::export::ExportManifest;
+use crate::model::import::{ImportProgress, DONE, FAILED, RUNNING};
+use crate::service::images::{self, Rejected};
+use actix_web::web;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, ErrorKind, Read};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+/// the largest archive `POST /import` takes
+pub const MAX_ARCHIVE_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"];
+
+/// an image in the archive, with what the files next to it say about it
+struct Entry {
+    index: usize,
+    name: String,
+    extension: String,
+    ratio: Option<String>,
+    taken: Option<i64>,
+}
+
+/// the `.json` a Google Takeout style export has next to each photo
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sidecar {
+    title: Option<String>,
+    photo_taken_time: Option<Timestamp>,
+}
+
+#[derive(Deserialize)]
+struct Timestamp {
+    timestamp: String,
+}
+
+/// adds the images of an uploaded ZIP as posts in the background, each through
+/// the same checks as an upload. the archive is kept in `dir` until it's done
+#[derive(Clone)]
+pub struct ImportService {
+    dir: PathBuf,
+}
+
+impl ImportService {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.zip", id))
+    }
+
+    /// fails the imports a restart cut short and removes their archives
+    pub async fn recover(&self) -> Result<usize, String> {
+        let ids = db::surrealdb::imports_interrupted()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for id in &ids {
+            match tokio::fs::remove_file(self.path(id)).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => println!("import recover err -> {} {}", id, e),
+            }
+        }
+
+        Ok(ids.len())
+    }
+
+    pub fn spawn(&self, app_data: web::Data<AppData>, user_id: String, id: String) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err((progress, e)) = this.run(&app_data, &user_id, &id).await {
+                println!("import err -> {} {}", id, e);
+                db::surrealdb::import_update(&id, FAILED, &progress, Some(&e))
+                    .await
+                    .expect("err -> db::surrealdb::import_update");
+            }
+            if let Err(e) = tokio::fs::remove_file(this.path(&id)).await {
+                println!("import err -> {} {}", id, e);
+            }
+        });
+    }
+
+    /// on failure, how far the import got along with what stopped it
+    async fn run(&self, app_data: &AppData, user_id: &String, id: &str) -> Result<(), (ImportProgress, String)> {
+        let mut progress = ImportProgress::default();
+        self.import(app_data, user_id, id, &mut progress)
+            .await
+            .map_err(|e| (progress, e))
+    }
+
+    async fn import(&self, app_data: &AppData, user_id: &String, id: &str, progress: &mut ImportProgress) -> Result<(), String> {
+        let path = self.path(id);
+        let entries = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || entries(&path))
+                .await
+                .map_err(|e| e.to_string())??
+        };
+        progress.total = entries.len() as i64;
+        db::surrealdb::import_update(id, RUNNING, progress, None)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // one image at a time is read out of the archive while the one before is checked
+        let (tx, mut rx) = mpsc::channel(1);
+        let reader = tokio::task::spawn_blocking(move || -> Result<(), String> {
+            let mut archive = ZipArchive::new(File::open(&path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            for entry in entries {
+                let mut body = Vec::new();
+                // the size an entry declares isn't checked against what it holds,
+                // so no more than the limit is read whatever it says
+                let read = archive
+                    .by_index(entry.index)
+                    .map_err(|e| Rejected::Invalid(e.to_string()))
+                    .and_then(|file| {
+                        file.take(images::MAX_IMAGE_BYTES + 1)
+                            .read_to_end(&mut body)
+                            .map_err(|e| Rejected::Invalid(e.to_string()))
+                    });
+                let body = match read {
+                    Ok(read) if read as u64 > images::MAX_IMAGE_BYTES => Err(Rejected::TooLarge),
+                    Ok(_) => Ok(body),
+                    Err(e) => Err(e),
+                };
+                if tx.blocking_send((entry, body)).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        });
+
+        let mut error = None;
+        while let Some((entry, body)) = rx.recv().await {
+            let added = match body {
+                Ok(body) => match entry.ratio.clone().or_else(|| ratio(&body)) {
+                    Some(ratio) => images::add_image(&app_data.classifier, user_id, &body, &entry.extension, ratio).await,
+                    None => Err(Rejected::Invalid("invalid image".to_string())),
+                },
+                Err(e) => Err(e),
+            };
+
+            match added {
+                Ok(_) => progress.imported += 1,
+                Err(Rejected::Exists) => progress.duplicates += 1,
+                Err(Rejected::Nsfw) => progress.rejected += 1,
+                Err(Rejected::NoPremium) => {
+                    error = Some("upload limit reached".to_string());
+                    break;
+                }
+                Err(e) => {
+                    println!("import err -> {} {} {}", id, entry.name, e);
+                    progress.failed += 1;
+                }
+            }
+            progress.processed += 1;
+            db::surrealdb::import_update(id, RUNNING, progress, None)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        drop(rx);
+        reader.await.map_err(|e| e.to_string())??;
+
+        db::surrealdb::import_update(id, DONE, progress, error.as_deref())
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// the images in the archive, oldest first when sidecars say when they were taken.
+/// a `manifest.json` from `POST /export` gives their ratios
+fn entries(path: &PathBuf) -> Result<Vec<Entry>, String> {
+    let mut archive = ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let mut ratios = HashMap::new();
+    let mut taken = HashMap::new();
+    let mut images = Vec::new();
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(|e| e.to_string())?;
+        // names that would leave the archive's root are left out
+        let Some(name) = file.enclosed_name().and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string())) else {
+            continue;
+        };
+        if file.is_dir() || file.size() > images::MAX_IMAGE_BYTES {
+            continue;
+        }
+        let lower = name.to_lowercase();
+
+        if name == "manifest.json" {
+            if let Ok(manifest) = serde_json::from_reader::<_, ExportManifest>(file.take(images::MAX_IMAGE_BYTES)) {
+                ratios.extend(manifest.posts.into_iter().map(|post| (post.image, post.ratio)));
+            }
+        } else if lower.ends_with(".json") {
+            if let Ok(sidecar) = serde_json::from_reader::<_, Sidecar>(file.take(images::MAX_IMAGE_BYTES)) {
+                let image = sidecar.title.unwrap_or_else(|| {
+                    name[..name.len() - ".json".len()]
+                        .trim_end_matches(".supplemental-metadata")
+                        .to_string()
+                });
+                if let Some(at) = sidecar.photo_taken_time.and_then(|t| t.timestamp.parse::<i64>().ok()) {
+                    taken.insert(image, at);
+                }
+            }
+        } else if let Some((_, extension)) = lower.rsplit_once('.') {
+            if IMAGE_EXTENSIONS.contains(&extension) {
+                images.push(Entry {
+                    index,
+                    extension: extension.to_string(),
+                    name,
+                    ratio: None,
+                    taken: None,
+                });
+            }
+        }
+    }
+
+    for image in &mut images {
+        image.ratio = ratios.get(&image.name).cloned();
+        image.taken = taken.get(&image.name).copied();
+    }
+    images.sort_by_key(|image| (image.taken, image.index));
+
+    Ok(images)
+}
+
+/// `width:height`, for images that come without one
+fn ratio(body: &[u8]) -> Option<String> {
+    let (width, height) = image::ImageReader::new(Cursor::new(body))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+
+    Some(format!("{}:{}", width, height))
+}
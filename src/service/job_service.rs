@@ -0,0 +1,275 @@
+use crate::ai::classifier::{Classifier, Undecodable};
+use crate::ai::image_classification::check_safety;
+use crate::db;
+use crate::model::app::AppData;
+use crate::ai::moderation::Verdict;
+use crate::model::job::{Job, HELD, INVALID_IMAGE, NSFW, POST_DELETED, PROCESS_IMAGE};
+use crate::model::post::PostDetails;
+use crate::service::images::{self, THUMBNAIL_DIR};
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::exif;
+use actix_web::web;
+use chrono::Duration;
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageReader};
+use std::env;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::sleep;
+
+/// the longest side of a thumbnail
+const THUMBNAIL_SIZE: u32 = 320;
+
+#[derive(Clone, Debug)]
+pub struct JobConfig {
+    /// jobs run at once by this instance
+    pub workers: usize,
+    /// time between looks at the queue once it's empty
+    pub interval: Duration,
+    /// how long a claimed job is left to its worker before another may take it
+    pub lease: Duration,
+    /// wait after the first failure, doubled with every one after it
+    pub retry_base: Duration,
+    /// a job that failed this many times is given up on
+    pub max_attempts: i64,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            interval: Duration::seconds(2),
+            lease: Duration::minutes(10),
+            retry_base: Duration::seconds(30),
+            max_attempts: 5,
+        }
+    }
+}
+
+impl JobConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(workers) = env::var("JOB_WORKERS") {
+            config.workers = workers.parse().expect("env err -> JOB_WORKERS");
+        }
+        if let Ok(interval) = env::var("JOB_INTERVAL_SECS") {
+            config.interval = Duration::seconds(interval.parse().expect("env err -> JOB_INTERVAL_SECS"));
+        }
+        if let Ok(lease) = env::var("JOB_LEASE_SECS") {
+            config.lease = Duration::seconds(lease.parse().expect("env err -> JOB_LEASE_SECS"));
+        }
+        if let Ok(retry) = env::var("JOB_RETRY_SECS") {
+            config.retry_base = Duration::seconds(retry.parse().expect("env err -> JOB_RETRY_SECS"));
+        }
+        if let Ok(attempts) = env::var("JOB_MAX_ATTEMPTS") {
+            config.max_attempts = attempts.parse().expect("env err -> JOB_MAX_ATTEMPTS");
+        }
+
+        config
+    }
+
+    fn retry_after(&self, attempts: i64) -> Duration {
+        let factor = 1i32.checked_shl(attempts.clamp(0, 20) as u32).unwrap_or(i32::MAX);
+
+        self.retry_base.checked_mul(factor).unwrap_or(self.retry_base)
+    }
+}
+
+/// works through the `job` table. jobs are claimed one at a time, so any
+/// number of workers on any number of instances can share the queue
+#[derive(Clone)]
+pub struct JobService {
+    config: JobConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl JobService {
+    pub fn new(config: JobConfig) -> Self {
+        Self {
+            config,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// runs the jobs that are due until there are none left, returns how many ran
+    pub async fn process(&self, classifier: &Classifier) -> Result<usize, String> {
+        let mut ran = 0;
+
+        loop {
+            let now = self.clock.now();
+            let jobs = db::surrealdb::jobs_claim(&now, self.config.lease.num_seconds(), 1)
+                .await
+                .map_err(|e| e.to_string())?;
+            let Some(job) = jobs.into_iter().next() else {
+                return Ok(ran);
+            };
+
+            match self.run(classifier, &job).await {
+                Ok(result) => db::surrealdb::job_done(&job.id, result)
+                    .await
+                    .map_err(|e| e.to_string())?,
+                Err(e) if job.attempts + 1 >= self.config.max_attempts => {
+                    println!("job err -> {} {}, giving up", job.id, e);
+                    self.remove(&job).await?;
+                    db::surrealdb::job_failed(&job.id, &e)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                Err(e) => {
+                    let run_at = now + self.config.retry_after(job.attempts);
+                    println!("job err -> {} {}, retrying at {}", job.id, e, run_at);
+                    db::surrealdb::job_retry(&job.id, &e, &run_at)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            ran += 1;
+        }
+    }
+
+    /// what the job came to, if there's more to say than that it's done
+    async fn run(&self, classifier: &Classifier, job: &Job) -> Result<Option<&'static str>, String> {
+        match job.kind.as_str() {
+            PROCESS_IMAGE => self.process_image(classifier, job).await,
+            kind => Err(format!("unknown job kind {}", kind)),
+        }
+    }
+
+    /// classifies the image if that's still to be done, taking the post down if
+    /// it's rejected and holding it for review if it's close. then reads its exif
+    /// data, makes its thumbnail and marks it ready, unless it's held
+    async fn process_image(&self, classifier: &Classifier, job: &Job) -> Result<Option<&'static str>, String> {
+        if !db::surrealdb::post_exists(&job.user_id, &job.post_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(Some(POST_DELETED));
+        }
+
+        let body = tokio::fs::read(format!("images/{}", job.image))
+            .await
+            .map_err(|e| format!("image {}: {}", job.image, e))?;
+
+        if job.classify {
+            let scores = match check_safety(classifier, &body).await {
+                Ok(scores) => scores,
+                Err(e) if e.is::<Undecodable>() => return self.take_down(job, INVALID_IMAGE).await,
+                // the classifier failing isn't the image's fault, it's tried again
+                Err(e) => return Err(format!("classifier: {}", e)),
+            };
+            let verdict = classifier.moderation().verdict(&scores);
+            if verdict == Verdict::Reject {
+                return self.take_down(job, NSFW).await;
+            }
+
+            db::surrealdb::post_scored(&job.user_id, &job.post_id, &scores, verdict == Verdict::Review)
+                .await
+                .map_err(|e| e.to_string())?;
+            db::surrealdb::job_classified(&job.id)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let thumbnail = images::thumbnail_name(&job.image);
+        let path = Path::new(THUMBNAIL_DIR).join(&thumbnail);
+        let details = tokio::task::spawn_blocking(move || inspect(&body, path, thumbnail))
+            .await
+            .map_err(|e| e.to_string())??;
+
+        let held = db::surrealdb::post_processed(&job.user_id, &job.post_id, &details)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(held.then_some(HELD))
+    }
+
+    /// removes a post that didn't pass classification and gives back its upload
+    async fn take_down(&self, job: &Job, result: &'static str) -> Result<Option<&'static str>, String> {
+        self.remove(job).await?;
+
+        Ok(Some(result))
+    }
+
+    /// removes the job's post with its image and thumbnail and gives back its
+    /// upload, so the same image can be uploaded again
+    async fn remove(&self, job: &Job) -> Result<(), String> {
+        db::surrealdb::post_delete(&job.user_id, &job.post_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let thumbnail = Path::new(THUMBNAIL_DIR).join(images::thumbnail_name(&job.image));
+        for file in [Path::new("images").join(&job.image), thumbnail] {
+            match tokio::fs::remove_file(&file).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("{}: {}", file.display(), e)),
+            }
+        }
+        db::surrealdb::upload_refund(&job.user_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// `workers` loops each running jobs as they come, looking again every
+    /// `interval` once the queue is empty
+    pub async fn start(self, app_data: web::Data<AppData>) {
+        let interval = self.config.interval.to_std().expect("job service err -> interval");
+
+        for _ in 0..self.config.workers {
+            let this = self.clone();
+            let app_data = app_data.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = this.process(&app_data.classifier).await {
+                        println!("job service err -> {}", e);
+                    }
+                    sleep(interval).await;
+                }
+            });
+        }
+    }
+}
+
+/// decodes the image turned the way its exif data says, and writes its thumbnail to `path`
+fn inspect(body: &[u8], path: PathBuf, thumbnail: String) -> Result<PostDetails, String> {
+    let mut decoder = ImageReader::new(Cursor::new(body))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .into_decoder()
+        .map_err(|e| e.to_string())?;
+    let exif = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .map(|raw| exif::parse(&raw))
+        .unwrap_or_default();
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| e.to_string())?;
+    image.apply_orientation(orientation);
+    let (width, height) = image.dimensions();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgb8()
+        .save_with_format(&path, ImageFormat::Jpeg)
+        .map_err(|e| format!("thumbnail {}: {}", path.display(), e))?;
+
+    Ok(PostDetails {
+        width,
+        height,
+        taken_at: exif.taken_at,
+        camera: exif.camera,
+        thumbnail,
+    })
+}
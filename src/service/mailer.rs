@@ -0,0 +1,135 @@
+use crate::utils::security::random_id;
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()>;
+}
+
+/// `MAILER` picks the transport: `smtp`, `file` or `memory`. without it smtp is used
+/// when `SMTP_HOST` is set, otherwise mails are written to `MAIL_DIR` (`mail` by default).
+/// in production that fallback would drop every mail on disk, so it panics instead
+pub fn from_env(production: bool) -> Arc<dyn Mailer> {
+    let mailer = env::var("MAILER").unwrap_or_else(|_| {
+        if env::var("SMTP_HOST").is_ok() {
+            "smtp".to_string()
+        } else if production {
+            panic!("env err -> SMTP_HOST is unset, set MAILER=file to keep mails on disk");
+        } else {
+            "file".to_string()
+        }
+    });
+
+    match mailer.as_str() {
+        "smtp" => Arc::new(SmtpMailer::from_env()),
+        "file" => Arc::new(FileMailer::new(env::var("MAIL_DIR").unwrap_or_else(|_| "mail".to_string()))),
+        "memory" => Arc::new(MemoryMailer::new()),
+        other => panic!("env err -> MAILER {}", other),
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// `SMTP_HOST` with STARTTLS on `SMTP_PORT` (587), `SMTP_USERNAME`/`SMTP_PASSWORD`
+    /// if the relay needs them, `MAIL_FROM` is the sender address
+    pub fn from_env() -> Self {
+        let host = env::var("SMTP_HOST").expect("env err -> SMTP_HOST");
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host).expect("smtp err -> SMTP_HOST");
+
+        if let Ok(port) = env::var("SMTP_PORT") {
+            transport = transport.port(port.parse().expect("env err -> SMTP_PORT"));
+        }
+        if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+
+        Self {
+            transport: transport.build(),
+            from: env::var("MAIL_FROM")
+                .expect("env err -> MAIL_FROM")
+                .parse()
+                .expect("env err -> MAIL_FROM"),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(mail.to.parse()?)
+            .subject(mail.subject)
+            .body(mail.body)?;
+
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
+
+/// writes every mail to its own file, for development without a mail server
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let name = format!("{}-{}.eml", chrono::Utc::now().timestamp_millis(), random_id());
+        let content = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+        tokio::fs::write(self.dir.join(name), content).await?;
+
+        Ok(())
+    }
+}
+
+/// keeps sent mails so tests can read them back
+#[derive(Clone, Default)]
+pub struct MemoryMailer {
+    pub sent: Arc<Mutex<Vec<Mail>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last_to(&self, to: &str) -> Option<Mail> {
+        self.sent.lock().unwrap().iter().rev().find(|m| m.to == to).cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, mail: Mail) -> anyhow::Result<()> {
+        self.sent.lock().unwrap().push(mail);
+
+        Ok(())
+    }
+}
@@ -1 +1,10 @@
-pub mod deletion_service;
\ No newline at end of file
+pub mod deletion_service;
+pub mod rate_limiter;
+pub mod password_service;
+pub mod mailer;
+pub mod oidc;
+pub mod export_service;
+pub mod images;
+pub mod import_service;
+pub mod upload_service;
+pub mod job_service;
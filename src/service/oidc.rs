@@ -0,0 +1,261 @@
+use crate::utils::oidc::{self, Expected, IdTokenClaims, Jwks, ProviderMetadata};
+use crate::utils::security::public_url;
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Clone, Debug)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    pub providers: Vec<OidcProvider>,
+    /// the callback of provider `name` is `<redirect_base>/oidc/<name>/callback`
+    pub redirect_base: String,
+    /// after login the browser is sent here
+    pub frontend_url: String,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            providers: Vec::new(),
+            redirect_base: String::new(),
+            frontend_url: "/".to_string(),
+        }
+    }
+}
+
+impl OidcConfig {
+    /// `OIDC_PROVIDERS` is a comma separated list of names, each configured by
+    /// `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID`, `OIDC_<NAME>_CLIENT_SECRET`
+    /// (left out for public clients) and `OIDC_<NAME>_SCOPES` (`openid email profile`).
+    /// `OIDC_REDIRECT_BASE` and `OIDC_FRONTEND_URL` default to the public url
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(providers) = env::var("OIDC_PROVIDERS") {
+            config.providers = providers
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(|name| {
+                    let var = |key: &str| env::var(format!("OIDC_{}_{}", name.to_uppercase(), key));
+
+                    OidcProvider {
+                        name: name.to_lowercase(),
+                        issuer: var("ISSUER").unwrap_or_else(|_| panic!("env err -> OIDC_{}_ISSUER", name.to_uppercase())),
+                        client_id: var("CLIENT_ID")
+                            .unwrap_or_else(|_| panic!("env err -> OIDC_{}_CLIENT_ID", name.to_uppercase())),
+                        client_secret: var("CLIENT_SECRET").ok(),
+                        scopes: var("SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+                    }
+                })
+                .collect();
+        }
+
+        if !config.providers.is_empty() {
+            config.redirect_base = env::var("OIDC_REDIRECT_BASE").unwrap_or_else(|_| public_url());
+            config.frontend_url = env::var("OIDC_FRONTEND_URL").unwrap_or_else(|_| public_url());
+        }
+
+        config
+    }
+}
+
+#[derive(Clone)]
+struct Discovered {
+    metadata: ProviderMetadata,
+    jwks: Jwks,
+    jwks_fetched: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// authorization code flow with PKCE against the configured providers.
+/// discovery documents and key sets are fetched on first use and cached,
+/// the key set is fetched again when a token names a key it doesn't have
+#[derive(Clone)]
+pub struct OidcService {
+    config: OidcConfig,
+    client: reqwest::Client,
+    discovered: Arc<Mutex<HashMap<String, Discovered>>>,
+}
+
+impl OidcService {
+    pub fn new(config: OidcConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("reqwest client err");
+
+        Self {
+            config,
+            client,
+            discovered: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn providers(&self) -> &[OidcProvider] {
+        &self.config.providers
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&OidcProvider> {
+        self.config.providers.iter().find(|p| p.name == name)
+    }
+
+    pub fn redirect_uri(&self, provider: &OidcProvider) -> String {
+        format!("{}/oidc/{}/callback", self.config.redirect_base, provider.name)
+    }
+
+    pub fn frontend_url(&self) -> &str {
+        &self.config.frontend_url
+    }
+
+    /// where to send the browser, `verifier` stays here and is sent with the code
+    pub async fn authorization_url(
+        &self,
+        provider: &OidcProvider,
+        state: &str,
+        nonce: &str,
+        verifier: &str,
+    ) -> anyhow::Result<String> {
+        let metadata = self.discover(provider).await?.metadata;
+        let challenge = oidc::pkce_challenge(verifier);
+        let redirect_uri = self.redirect_uri(provider);
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.client_id.as_str()),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("scope", provider.scopes.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        Ok(url.to_string())
+    }
+
+    /// trades the code for tokens and returns the verified id token claims
+    pub async fn exchange(
+        &self,
+        provider: &OidcProvider,
+        code: &str,
+        verifier: &str,
+        nonce: &str,
+    ) -> anyhow::Result<IdTokenClaims> {
+        let discovered = self.discover(provider).await?;
+        let redirect_uri = self.redirect_uri(provider);
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", provider.client_id.as_str()),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(&discovered.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("token endpoint answered {}", response.status());
+        }
+        let tokens: TokenResponse = response.json().await?;
+
+        let mut jwks = discovered.jwks;
+        let kid = oidc::key_id(&tokens.id_token)?;
+        if kid.is_some() && !jwks.keys.iter().any(|k| k.kid == kid) {
+            jwks = self.refresh_jwks(provider).await?;
+        }
+
+        let expected = Expected {
+            issuer: &provider.issuer,
+            client_id: &provider.client_id,
+            nonce,
+            now: Utc::now().timestamp(),
+        };
+
+        Ok(oidc::verify_id_token(&tokens.id_token, &jwks, &expected)?)
+    }
+
+    async fn discover(&self, provider: &OidcProvider) -> anyhow::Result<Discovered> {
+        if let Some(discovered) = self.discovered.lock().await.get(&provider.name) {
+            return Ok(discovered.clone());
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", provider.issuer.trim_end_matches('/'));
+        let metadata: ProviderMetadata = self.client.get(url).send().await?.error_for_status()?.json().await?;
+        if metadata.issuer != provider.issuer {
+            bail!("discovery document names issuer {}", metadata.issuer);
+        }
+
+        let jwks = self.fetch_jwks(&metadata).await?;
+        let discovered = Discovered {
+            metadata,
+            jwks,
+            jwks_fetched: Utc::now(),
+        };
+        self.discovered
+            .lock()
+            .await
+            .insert(provider.name.clone(), discovered.clone());
+
+        Ok(discovered)
+    }
+
+    /// at most once a minute, an unknown `kid` shouldn't make us hammer the provider
+    async fn refresh_jwks(&self, provider: &OidcProvider) -> anyhow::Result<Jwks> {
+        let discovered = self.discover(provider).await?;
+        if Utc::now() - discovered.jwks_fetched < Duration::minutes(1) {
+            return Ok(discovered.jwks);
+        }
+
+        let jwks = self.fetch_jwks(&discovered.metadata).await?;
+        let mut cache = self.discovered.lock().await;
+        let entry = cache
+            .get_mut(&provider.name)
+            .ok_or_else(|| anyhow!("provider {} not discovered", provider.name))?;
+        entry.jwks = jwks.clone();
+        entry.jwks_fetched = Utc::now();
+
+        Ok(jwks)
+    }
+
+    async fn fetch_jwks(&self, metadata: &ProviderMetadata) -> anyhow::Result<Jwks> {
+        Ok(self
+            .client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
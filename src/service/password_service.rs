@@ -0,0 +1,211 @@
+use crate::utils::security::random_id;
+use crate::utils::validation::ValidationError;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::env;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug)]
+pub struct PasswordConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub min_length: usize,
+    pub min_classes: usize,
+    pub breached_dir: Option<PathBuf>,
+}
+
+impl Default for PasswordConfig {
+    /// argon2id with the OWASP minimum, same as what `crypto::argon2::generate` produced
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            min_length: 10,
+            min_classes: 2,
+            breached_dir: None,
+        }
+    }
+}
+
+impl PasswordConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(memory) = env::var("ARGON2_MEMORY_KIB") {
+            config.memory_kib = memory.parse().expect("env err -> ARGON2_MEMORY_KIB");
+        }
+        if let Ok(iterations) = env::var("ARGON2_ITERATIONS") {
+            config.iterations = iterations.parse().expect("env err -> ARGON2_ITERATIONS");
+        }
+        if let Ok(parallelism) = env::var("ARGON2_PARALLELISM") {
+            config.parallelism = parallelism.parse().expect("env err -> ARGON2_PARALLELISM");
+        }
+        if let Ok(min_length) = env::var("PASSWORD_MIN_LENGTH") {
+            config.min_length = min_length.parse().expect("env err -> PASSWORD_MIN_LENGTH");
+        }
+        if let Ok(min_classes) = env::var("PASSWORD_MIN_CLASSES") {
+            config.min_classes = min_classes.parse().expect("env err -> PASSWORD_MIN_CLASSES");
+        }
+        config.breached_dir = env::var("BREACHED_PASSWORDS_DIR").ok().map(PathBuf::from);
+
+        config
+    }
+}
+
+/// hashes and verifies passwords with argon2id and enforces the password policy.
+///
+/// the breached password list is a directory in the range format of
+/// haveibeenpwned: the upper case SHA-1 of a password is split after 5
+/// characters, `<prefix>.txt` holds one `<suffix>:<count>` line per hash.
+/// only the file of the prefix is read, the full list never has to be loaded.
+#[derive(Clone)]
+pub struct PasswordService {
+    config: PasswordConfig,
+    params: Params,
+    dummy: String,
+}
+
+impl PasswordService {
+    pub fn new(config: PasswordConfig) -> Self {
+        let params = Params::new(config.memory_kib, config.iterations, config.parallelism, None)
+            .expect("invalid argon2 parameters");
+
+        let dummy = hash_with(&argon(&params), &random_id());
+
+        Self { config, params, dummy }
+    }
+
+    pub async fn hash(&self, password: &str) -> String {
+        let argon2 = argon(&self.params);
+        let password = password.to_string();
+
+        tokio::task::spawn_blocking(move || hash_with(&argon2, &password))
+            .await
+            .expect("hash task err")
+    }
+
+    /// `hash` is `None` for unknown users, a throwaway hash is still checked
+    /// so the response time doesn't tell whether the username exists
+    pub async fn verify(&self, password: &str, hash: Option<&str>) -> bool {
+        let argon2 = argon(&self.params);
+        let password = password.to_string();
+        let (hash, known) = match hash {
+            Some(hash) => (hash.to_string(), true),
+            None => (self.dummy.clone(), false),
+        };
+
+        tokio::task::spawn_blocking(move || {
+            PasswordHash::new(&hash).is_ok_and(|parsed| {
+                argon2.verify_password(password.as_bytes(), &parsed).is_ok()
+            }) && known
+        })
+        .await
+        .expect("verify task err")
+    }
+
+    /// true when `hash` was made with another algorithm, version or parameters
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&parsed) else {
+            return true;
+        };
+
+        parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+
+    /// minimum strength for new passwords, `username` and `email` must not be part of it
+    pub async fn check_policy(
+        &self,
+        field: &'static str,
+        password: &str,
+        username: &str,
+        email: &str,
+    ) -> Result<(), ValidationError> {
+        let lower = password.to_lowercase();
+
+        if password.chars().count() < self.config.min_length {
+            return Err(ValidationError {
+                field,
+                message: format!("at least {} characters", self.config.min_length),
+            });
+        }
+
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_numeric()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|c| **c).count() < self.config.min_classes {
+            return Err(ValidationError {
+                field,
+                message: format!(
+                    "at least {} of lower case, upper case, digits and symbols",
+                    self.config.min_classes
+                ),
+            });
+        }
+
+        let local_part = email.split('@').next().unwrap_or_default();
+        for personal in [username, local_part] {
+            if personal.chars().count() >= 3 && lower.contains(&personal.to_lowercase()) {
+                return Err(ValidationError {
+                    field,
+                    message: "must not contain the username or email".to_string(),
+                });
+            }
+        }
+
+        if self.breached(password).await {
+            return Err(ValidationError {
+                field,
+                message: "appears in a known data breach".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub async fn breached(&self, password: &str) -> bool {
+        let Some(dir) = &self.config.breached_dir else {
+            return false;
+        };
+
+        let digest = hex::encode_upper(openssl::sha::sha1(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+
+        let range = match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+            Ok(range) => range,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return false,
+            Err(e) => panic!("err -> breached passwords {}", e),
+        };
+
+        range
+            .lines()
+            .filter_map(|line| line.split(':').next())
+            .any(|hash| hash.trim().eq_ignore_ascii_case(suffix))
+    }
+}
+
+fn argon(params: &Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+}
+
+fn hash_with(argon2: &Argon2, password: &str) -> String {
+    let mut salt = [0u8; 16];
+    openssl::rand::rand_bytes(&mut salt).expect("rand err");
+    let salt = SaltString::encode_b64(&salt).expect("salt err");
+
+    argon2
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2 err")
+        .to_string()
+}
@@ -0,0 +1,235 @@
+use crate::db;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[derive(Clone, Debug)]
+pub struct RateLimitRule {
+    pub path: String,
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub rules: Vec<RateLimitRule>,
+    pub max_failures: u32,
+    pub lockout: Duration,
+    pub persist: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            rules: [
+                ("/login", 10, Duration::minutes(1)),
+                ("/login/2fa", 10, Duration::minutes(1)),
+                ("/webauthn/login/start", 10, Duration::minutes(1)),
+                ("/webauthn/login/finish", 10, Duration::minutes(1)),
+                ("/webauthn/register/start", 10, Duration::minutes(1)),
+                ("/register", 5, Duration::hours(1)),
+                ("/password_reset/request", 5, Duration::hours(1)),
+                ("/verify_email/send", 5, Duration::hours(1)),
+                ("/export", 5, Duration::hours(1)),
+            ]
+            .into_iter()
+            .map(|(path, capacity, period)| RateLimitRule {
+                path: path.to_string(),
+                capacity,
+                period,
+            })
+            .collect(),
+            max_failures: 5,
+            lockout: Duration::minutes(15),
+            persist: false,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// `RATE_LIMITS` is a comma separated list of `path=capacity/period_secs`,
+    /// e.g. `/login=10/60,/register=5/3600`
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(rules) = env::var("RATE_LIMITS") {
+            config.rules = rules
+                .split(',')
+                .filter(|r| !r.is_empty())
+                .map(|r| {
+                    let (path, limit) = r.trim().split_once('=').expect("env err -> RATE_LIMITS");
+                    let (capacity, period) = limit.split_once('/').expect("env err -> RATE_LIMITS");
+
+                    RateLimitRule {
+                        path: path.to_string(),
+                        capacity: capacity.parse().expect("env err -> RATE_LIMITS"),
+                        period: Duration::seconds(period.parse().expect("env err -> RATE_LIMITS")),
+                    }
+                })
+                .collect();
+        }
+        if let Ok(max_failures) = env::var("LOGIN_MAX_FAILURES") {
+            config.max_failures = max_failures.parse().expect("env err -> LOGIN_MAX_FAILURES");
+        }
+        if let Ok(lockout) = env::var("LOGIN_LOCKOUT_SECS") {
+            config.lockout = Duration::seconds(lockout.parse().expect("env err -> LOGIN_LOCKOUT_SECS"));
+        }
+        config.persist = env::var("RATE_LIMIT_PERSIST").is_ok_and(|p| p == "1" || p == "true");
+
+        config
+    }
+}
+
+/// the rule's limits are kept with each bucket, it's pruned by its own
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+    capacity: f64,
+    per_second: f64,
+}
+
+impl Bucket {
+    /// refilled by `now`, dropping it changes nothing
+    fn full(&self, now: DateTime<Utc>) -> bool {
+        self.tokens + (now - self.updated).num_milliseconds() as f64 / 1000.0 * self.per_second >= self.capacity
+    }
+}
+
+#[derive(Clone, Default)]
+struct Failures {
+    count: u32,
+    last: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl Failures {
+    /// failures are forgotten `lockout` after the last one, a lockout once it's over
+    fn expired(&self, lockout: Duration, now: DateTime<Utc>) -> bool {
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => self.last + lockout <= now,
+        }
+    }
+}
+
+/// token buckets per route and client or account, plus failed login counting per username.
+/// with `persist` the failure counts live in the `lockout` table so they
+/// survive restarts and are shared between instances.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn rule(&self, path: &str) -> Option<&RateLimitRule> {
+        self.config.rules.iter().find(|r| r.path == path)
+    }
+
+    /// takes a token for `client` on the rule's route, or returns how long to wait
+    pub async fn acquire(&self, rule: &RateLimitRule, client: &str) -> Result<(), Duration> {
+        let now = Utc::now();
+        let capacity = rule.capacity as f64;
+        let per_second = capacity / rule.period.num_milliseconds().max(1) as f64 * 1000.0;
+        let mut buckets = self.buckets.lock().await;
+
+        if buckets.len() > 10_000 {
+            buckets.retain(|_, b| !b.full(now));
+        }
+
+        let bucket = buckets
+            .entry(format!("{}|{}", rule.path, client))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+                capacity,
+                per_second,
+            });
+
+        let elapsed = (now - bucket.updated).num_milliseconds().max(0) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = ((1.0 - bucket.tokens) / per_second * 1000.0).ceil() as i64;
+            Err(Duration::milliseconds(wait))
+        }
+    }
+
+    /// takes a token for `username` on the route's rule, if it has one. the
+    /// account is held to the limit however many addresses it's tried from
+    pub async fn acquire_user(&self, path: &str, username: &str) -> Result<(), Duration> {
+        match self.rule(path) {
+            Some(rule) => self.acquire(rule, &format!("user={}", username.to_lowercase())).await,
+            None => Ok(()),
+        }
+    }
+
+    /// remaining lockout for `username`, if any
+    pub async fn locked(&self, username: &str) -> surrealdb::Result<Option<Duration>> {
+        let until = if self.config.persist {
+            db::surrealdb::lockout_until(username).await?
+        } else {
+            self.failures
+                .lock()
+                .await
+                .get(username)
+                .and_then(|f| f.locked_until)
+        };
+
+        Ok(until.map(|u| u - Utc::now()).filter(|d| *d > Duration::zero()))
+    }
+
+    pub async fn fail(&self, username: &str) -> surrealdb::Result<()> {
+        if self.config.persist {
+            return db::surrealdb::lockout_fail(username, self.config.max_failures, self.config.lockout).await;
+        }
+
+        let now = Utc::now();
+        let lockout = self.config.lockout;
+        let mut failures = self.failures.lock().await;
+
+        // usernames are whatever is tried, so the ones done with are dropped
+        if failures.len() > 10_000 {
+            failures.retain(|_, f| !f.expired(lockout, now));
+        }
+
+        let entry = failures.entry(username.to_string()).or_default();
+
+        if entry.expired(lockout, now) {
+            *entry = Failures::default();
+        }
+
+        entry.count += 1;
+        entry.last = now;
+        if entry.count >= self.config.max_failures {
+            entry.locked_until = Some(now + lockout);
+        }
+
+        Ok(())
+    }
+
+    pub async fn succeed(&self, username: &str) -> surrealdb::Result<()> {
+        if self.config.persist {
+            return db::surrealdb::lockout_clear(username).await;
+        }
+
+        self.failures.lock().await.remove(username);
+
+        Ok(())
+    }
+}
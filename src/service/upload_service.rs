@@ -0,0 +1,151 @@
+use crate::db;
+use crate::model::upload::Upload;
+use crate::service::images::{self, Added, Rejected};
+use crate::utils::security::random_id;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::time::sleep;
+
+/// resumable uploads, kept in `dir` while they arrive a part at a time. one
+/// that isn't finished in `ttl` is removed, a user has at most `max_open` at once
+#[derive(Clone)]
+pub struct UploadService {
+    dir: PathBuf,
+    ttl: Duration,
+    max_open: usize,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+/// while it's held no other request appends to the upload
+pub struct Busy {
+    id: String,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.busy.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl UploadService {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Duration::hours(24),
+            max_open: 5,
+            busy: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// an empty upload of `length` bytes, `None` while the user has `max_open` already
+    pub async fn create(&self, user_id: &String, name: String, ratio: String, length: u64) -> Result<Option<Upload>, String> {
+        let id = random_id();
+        let file = self.dir.join(format!("{}.part", id));
+
+        let upload = Upload {
+            id,
+            name,
+            ratio,
+            length: length as i64,
+            file: file.to_string_lossy().to_string(),
+            expires_at: Utc::now() + self.ttl,
+        };
+        let created = db::surrealdb::upload_create(user_id, &upload, self.max_open)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !created {
+            return Ok(None);
+        }
+
+        let file = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::File::create(&file).await
+        };
+        if let Err(e) = file.await {
+            self.remove(&upload).await?;
+            return Err(e.to_string());
+        }
+
+        Ok(Some(upload))
+    }
+
+    /// `None` while another request has the upload
+    pub fn lock(&self, id: &str) -> Option<Busy> {
+        if !self.busy.lock().unwrap().insert(id.to_string()) {
+            return None;
+        }
+
+        Some(Busy {
+            id: id.to_string(),
+            busy: self.busy.clone(),
+        })
+    }
+
+    /// the bytes received so far
+    pub async fn offset(&self, upload: &Upload) -> Result<u64, String> {
+        let metadata = tokio::fs::metadata(&upload.file).await.map_err(|e| e.to_string())?;
+
+        Ok(metadata.len())
+    }
+
+    /// queues the complete upload as a post, through the same checks as `POST /upload`.
+    /// the upload is removed whatever the outcome, sending it again wouldn't change it
+    pub async fn finish(&self, user_id: &String, upload: &Upload) -> Result<Added, Rejected> {
+        let body = tokio::fs::read(&upload.file)
+            .await
+            .map_err(|e| Rejected::Invalid(e.to_string()));
+        let extension = upload.name.rsplit_once('.').map_or("", |(_, extension)| extension);
+        let added = match body {
+            Ok(body) => images::queue_image(user_id, &body, extension, upload.ratio.clone()).await,
+            Err(rejected) => Err(rejected),
+        };
+
+        self.remove(upload).await.map_err(Rejected::Invalid)?;
+
+        added
+    }
+
+    pub async fn remove(&self, upload: &Upload) -> Result<(), String> {
+        db::surrealdb::upload_delete(&upload.id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match tokio::fs::remove_file(&upload.file).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// removes the uploads that expired by `now`
+    pub async fn purge(&self, now: &DateTime<Utc>) -> Result<usize, String> {
+        let files = db::surrealdb::uploads_expire(now)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for file in &files {
+            match tokio::fs::remove_file(file).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => println!("upload purge err -> {} {}", file, e),
+            }
+        }
+
+        Ok(files.len())
+    }
+
+    pub async fn start(self) {
+        tokio::spawn(async move {
+            loop {
+                sleep(tokio::time::Duration::from_secs(60 * 60)).await;
+                if let Err(e) = self.purge(&Utc::now()).await {
+                    println!("upload purge err -> {}", e);
+                }
+            }
+        });
+    }
+}
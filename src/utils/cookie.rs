@@ -0,0 +1,37 @@
+use crate::config::Config;
+use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, CookieBuilder, SameSite};
+use std::borrow::Cow;
+use std::env;
+
+pub const TOKEN: &str = "token";
+pub const REFRESH: &str = "refresh";
+pub const LOGGED: &str = "logged";
+pub const PREMIUM: &str = "premium";
+pub const PRE_AUTH: &str = "pre_auth";
+pub const CSRF: &str = "csrf";
+
+/// `COOKIE_SECURE`, by default only when the server runs https
+pub fn secure() -> bool {
+    match env::var("COOKIE_SECURE") {
+        Ok(secure) => secure != "false" && secure != "0",
+        Err(_) => Config::from_env().tls(),
+    }
+}
+
+/// the attributes every cookie of this backend starts with: the site's domain,
+/// path `/`, `Secure`, `HttpOnly` and `SameSite=Strict`.
+/// flags the frontend has to read turn `http_only` off
+pub fn build<'c, V: Into<Cow<'c, str>>>(name: &'c str, value: V) -> CookieBuilder<'c> {
+    Cookie::build(name, value)
+        .domain(env::var("DOMAIN").expect("env err -> DOMAIN"))
+        .path("/")
+        .secure(secure())
+        .http_only(true)
+        .same_site(SameSite::Strict)
+}
+
+/// expires the cookie, the path has to match the one it was set with
+pub fn removal(name: &str) -> CookieBuilder<'_> {
+    build(name, "").max_age(Duration::ZERO)
+}
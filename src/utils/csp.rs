@@ -0,0 +1,88 @@
+/// a `Content-Security-Policy` value, built one directive at a time so a
+/// policy can't end up with stray separators
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Csp {
+    directives: Vec<(String, Vec<String>)>,
+}
+
+/// the group name `report-to` refers to, declared in `Reporting-Endpoints`
+pub const REPORT_GROUP: &str = "csp-endpoint";
+
+impl Csp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets `name`, replacing its sources when it was set before.
+    /// directives like `sandbox` take no sources
+    pub fn directive(mut self, name: &str, sources: &[&str]) -> Self {
+        let sources = sources.iter().map(|s| s.to_string()).collect();
+
+        match self.directives.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = sources,
+            None => self.directives.push((name.to_string(), sources)),
+        }
+
+        self
+    }
+
+    /// `report-uri` for browsers without the reporting api, `report-to` for the rest
+    pub fn report(self, uri: &str) -> Self {
+        self.directive("report-uri", &[uri]).directive("report-to", &[REPORT_GROUP])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.directives
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, sources)| sources.as_slice())
+    }
+
+    /// the single page app and everything without a more specific policy
+    pub fn app() -> Self {
+        Self::new()
+            .directive("default-src", &["'self'"])
+            .directive("script-src", &["'self'"])
+            .directive("style-src", &["'self'", "'unsafe-inline'"])
+            .directive("img-src", &["'self'", "data:", "blob:", "https:"])
+            .directive("connect-src", &["'self'", "ws:", "wss:", "https:"])
+            .directive("font-src", &["'self'", "data:", "https:"])
+            .directive("media-src", &["'self'", "blob:"])
+            .directive("worker-src", &["'self'", "blob:"])
+            .directive("manifest-src", &["'self'"])
+            .directive("frame-src", &["'none'"])
+            .directive("object-src", &["'none'"])
+            .directive("base-uri", &["'self'"])
+            .directive("form-action", &["'self'"])
+            .directive("frame-ancestors", &["'none'"])
+    }
+
+    /// uploaded images. opened directly they are a document of their own,
+    /// which may show the image and nothing else
+    pub fn file() -> Self {
+        Self::new()
+            .directive("default-src", &["'none'"])
+            .directive("img-src", &["'self'"])
+            .directive("style-src", &["'unsafe-inline'"])
+            .directive("frame-ancestors", &["'none'"])
+            .directive("sandbox", &[])
+    }
+}
+
+impl std::fmt::Display for Csp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let directives: Vec<String> = self
+            .directives
+            .iter()
+            .map(|(name, sources)| {
+                if sources.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{} {}", name, sources.join(" "))
+                }
+            })
+            .collect();
+
+        write!(f, "{}", directives.join("; "))
+    }
+}
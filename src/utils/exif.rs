@@ -0,0 +1,112 @@
+use chrono::NaiveDateTime;
+
+const MAKE: u16 = 0x010f;
+const MODEL: u16 = 0x0110;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const ASCII: u16 = 2;
+
+/// what the gallery keeps of a photo's exif data
+#[derive(Debug, Default, PartialEq)]
+pub struct Exif {
+    /// `2024-05-01T12:30:00`, the camera's local time
+    pub taken_at: Option<String>,
+    pub camera: Option<String>,
+}
+
+/// reads the raw exif block the image decoders hand out, a tiff header and its
+/// directories. anything it can't make sense of is left out rather than failing
+pub fn parse(raw: &[u8]) -> Exif {
+    let tiff = raw.strip_prefix(b"Exif\0\0").unwrap_or(raw);
+    let Some(reader) = Reader::new(tiff) else {
+        return Exif::default();
+    };
+    let Some(ifd0) = reader.u32(4).map(|offset| offset as usize) else {
+        return Exif::default();
+    };
+    let texts = reader.ascii_entries(ifd0).unwrap_or_default();
+    let find = |tag| texts.iter().find(|(t, _)| *t == tag).map(|(_, value)| value.clone());
+
+    let original = reader
+        .entry(ifd0, EXIF_IFD)
+        .and_then(|offset| reader.ascii_entries(offset as usize))
+        .and_then(|exif| exif.into_iter().find(|(tag, _)| *tag == DATE_TIME_ORIGINAL))
+        .map(|(_, value)| value);
+    let taken_at = original
+        .or_else(|| find(DATE_TIME))
+        .and_then(|taken| NaiveDateTime::parse_from_str(&taken, "%Y:%m:%d %H:%M:%S").ok())
+        .map(|taken| taken.format("%Y-%m-%dT%H:%M:%S").to_string());
+
+    // models often repeat the make, "Canon" "Canon EOS R6"
+    let camera = match (find(MAKE), find(MODEL)) {
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+
+    Exif { taken_at, camera }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let reader = Self { data, little_endian };
+
+        (reader.u16(2)? == 42).then_some(reader)
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// the 12 byte entries of the directory at `offset`: tag, type, count, value
+    fn entries(&self, offset: usize) -> Option<impl Iterator<Item = usize> + '_> {
+        let count = self.u16(offset)? as usize;
+
+        Some((0..count).map(move |i| offset + 2 + i * 12).filter(|at| at + 12 <= self.data.len()))
+    }
+
+    /// the value of a `LONG` entry, e.g. the offset of a sub directory
+    fn entry(&self, offset: usize, tag: u16) -> Option<u32> {
+        let at = self.entries(offset)?.find(|at| self.u16(*at) == Some(tag))?;
+
+        self.u32(at + 8)
+    }
+
+    /// the text entries of the directory at `offset`
+    fn ascii_entries(&self, offset: usize) -> Option<Vec<(u16, String)>> {
+        let entries = self
+            .entries(offset)?
+            .filter(|at| self.u16(at + 2) == Some(ASCII))
+            .filter_map(|at| {
+                let count = self.u32(at + 4)? as usize;
+                // values of up to four bytes are in the entry itself
+                let start = if count <= 4 { at + 8 } else { self.u32(at + 8)? as usize };
+                let bytes = self.data.get(start..start.checked_add(count)?)?;
+                let value = String::from_utf8_lossy(bytes).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+
+                Some((self.u16(at)?, value)).filter(|(_, value)| !value.is_empty())
+            })
+            .collect();
+
+        Some(entries)
+    }
+}
@@ -1 +1,9 @@
-pub mod security;
\ No newline at end of file
+pub mod security;
+pub mod validation;
+pub mod totp;
+pub mod webauthn;
+pub mod oidc;
+pub mod cookie;
+pub mod csp;
+pub mod clock;
+pub mod exif;
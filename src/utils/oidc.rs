@@ -0,0 +1,267 @@
+use data_encoding::BASE64URL_NOPAD;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// clock skew allowed on `exp` and `iat`
+pub const LEEWAY_SECS: i64 = 60;
+
+#[derive(Debug, PartialEq)]
+pub enum OidcError {
+    Encoding,
+    UnsupportedAlgorithm,
+    UnknownKey,
+    Signature,
+    Expired,
+    WrongIssuer,
+    WrongAudience,
+    WrongNonce,
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Encoding => write!(f, "malformed id token"),
+            OidcError::UnsupportedAlgorithm => write!(f, "unsupported id token algorithm"),
+            OidcError::UnknownKey => write!(f, "id token signed with an unknown key"),
+            OidcError::Signature => write!(f, "id token signature mismatch"),
+            OidcError::Expired => write!(f, "id token expired"),
+            OidcError::WrongIssuer => write!(f, "id token from the wrong issuer"),
+            OidcError::WrongAudience => write!(f, "id token for another client"),
+            OidcError::WrongNonce => write!(f, "id token nonce mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+/// the parts of `/.well-known/openid-configuration` the code flow needs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// RSA (`n`, `e`) or P-256 (`x`, `y`) public key
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// `aud` is a string or an array of strings
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    pub fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(aud) => aud.iter().any(|a| a == client_id),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Audience::One(_) => 1,
+            Audience::Many(aud) => aud.len(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// what an id token is checked against, besides the provider's keys
+pub struct Expected<'a> {
+    pub issuer: &'a str,
+    pub client_id: &'a str,
+    pub nonce: &'a str,
+    pub now: i64,
+}
+
+/// 32 random bytes, used for `state`, `nonce` and the PKCE verifier
+pub fn random_token() -> String {
+    let mut buf = [0u8; 32];
+    openssl::rand::rand_bytes(&mut buf).expect("rand err");
+
+    BASE64URL_NOPAD.encode(&buf)
+}
+
+/// RFC 7636 S256 code challenge
+pub fn pkce_challenge(verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&openssl::sha::sha256(verifier.as_bytes()))
+}
+
+/// the `kid` an id token was signed with, so a missing key can trigger a jwks refresh
+pub fn key_id(id_token: &str) -> Result<Option<String>, OidcError> {
+    let header = id_token.split('.').next().ok_or(OidcError::Encoding)?;
+
+    Ok(decode_json::<Header>(header)?.kid)
+}
+
+/// RS256 or ES256 signature, issuer, audience, expiry and nonce.
+/// the token came straight from the token endpoint over TLS, the signature is checked anyway
+pub fn verify_id_token(id_token: &str, jwks: &Jwks, expected: &Expected) -> Result<IdTokenClaims, OidcError> {
+    let mut parts = id_token.split('.');
+    let (Some(header_b64), Some(claims_b64), Some(signature_b64), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(OidcError::Encoding);
+    };
+
+    let header: Header = decode_json(header_b64)?;
+    let signature = BASE64URL_NOPAD
+        .decode(signature_b64.as_bytes())
+        .map_err(|_| OidcError::Encoding)?;
+
+    let (kty, signature) = match header.alg.as_str() {
+        "RS256" => ("RSA", signature),
+        "ES256" => ("EC", es256_signature_to_der(&signature)?),
+        _ => return Err(OidcError::UnsupportedAlgorithm),
+    };
+
+    let jwk = jwks
+        .keys
+        .iter()
+        .filter(|k| k.kty == kty && k.usage.as_deref().unwrap_or("sig") == "sig")
+        .filter(|k| k.alg.as_deref().is_none_or(|alg| alg == header.alg))
+        .find(|k| header.kid.is_none() || k.kid == header.kid)
+        .ok_or(OidcError::UnknownKey)?;
+    let key = public_key(jwk)?;
+
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key).map_err(|_| OidcError::UnknownKey)?;
+    verifier.update(header_b64.as_bytes()).map_err(|_| OidcError::Signature)?;
+    verifier.update(b".").map_err(|_| OidcError::Signature)?;
+    verifier.update(claims_b64.as_bytes()).map_err(|_| OidcError::Signature)?;
+    if !verifier.verify(&signature).unwrap_or(false) {
+        return Err(OidcError::Signature);
+    }
+
+    let claims: IdTokenClaims = decode_json(claims_b64)?;
+
+    if claims.iss != expected.issuer {
+        return Err(OidcError::WrongIssuer);
+    }
+    if !claims.aud.contains(expected.client_id) {
+        return Err(OidcError::WrongAudience);
+    }
+    if claims.aud.len() > 1 && claims.azp.as_deref() != Some(expected.client_id) {
+        return Err(OidcError::WrongAudience);
+    }
+    if claims.exp + LEEWAY_SECS <= expected.now || claims.iat - LEEWAY_SECS > expected.now {
+        return Err(OidcError::Expired);
+    }
+
+    let nonce = claims.nonce.as_deref().unwrap_or_default();
+    if nonce.len() != expected.nonce.len() || !openssl::memcmp::eq(nonce.as_bytes(), expected.nonce.as_bytes()) {
+        return Err(OidcError::WrongNonce);
+    }
+
+    Ok(claims)
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(part: &str) -> Result<T, OidcError> {
+    let bytes = BASE64URL_NOPAD.decode(part.as_bytes()).map_err(|_| OidcError::Encoding)?;
+
+    serde_json::from_slice(&bytes).map_err(|_| OidcError::Encoding)
+}
+
+fn public_key(jwk: &Jwk) -> Result<PKey<Public>, OidcError> {
+    let number = |value: &Option<String>| {
+        let bytes = BASE64URL_NOPAD
+            .decode(value.as_deref().ok_or(OidcError::UnknownKey)?.as_bytes())
+            .map_err(|_| OidcError::UnknownKey)?;
+
+        BigNum::from_slice(&bytes).map_err(|_| OidcError::UnknownKey)
+    };
+
+    match jwk.kty.as_str() {
+        "RSA" => {
+            let key = Rsa::from_public_components(number(&jwk.n)?, number(&jwk.e)?).map_err(|_| OidcError::UnknownKey)?;
+
+            PKey::from_rsa(key).map_err(|_| OidcError::UnknownKey)
+        }
+        "EC" if jwk.crv.as_deref() == Some("P-256") => {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(|_| OidcError::UnknownKey)?;
+            let (x, y) = (number(&jwk.x)?, number(&jwk.y)?);
+            let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                .map_err(|_| OidcError::UnknownKey)?;
+            key.check_key().map_err(|_| OidcError::UnknownKey)?;
+
+            PKey::from_ec_key(key).map_err(|_| OidcError::UnknownKey)
+        }
+        _ => Err(OidcError::UnknownKey),
+    }
+}
+
+/// JWS carries ES256 signatures as raw `r || s`, openssl wants DER
+fn es256_signature_to_der(signature: &[u8]) -> Result<Vec<u8>, OidcError> {
+    if signature.len() != 64 {
+        return Err(OidcError::Signature);
+    }
+
+    let r = BigNum::from_slice(&signature[..32]).map_err(|_| OidcError::Signature)?;
+    let s = BigNum::from_slice(&signature[32..]).map_err(|_| OidcError::Signature)?;
+
+    EcdsaSig::from_private_components(r, s)
+        .and_then(|sig| sig.to_der())
+        .map_err(|_| OidcError::Signature)
+}
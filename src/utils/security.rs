@@ -1,11 +1,10 @@
+use chrono::{DateTime, Duration, Utc};
 use hmac::{Hmac, Mac};
-use jwt::{Error, SignWithKey, VerifyWithKey};
+use jwt::{AlgorithmType, Error, Header, SignWithKey, Token, VerifyWithStore};
+use serde::{Deserialize, Serialize};
 use sha2::Sha384;
 use std::collections::BTreeMap;
-use actix_web::body::{BoxBody};
-use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::http::header::{HeaderName, HeaderValue};
-use actix_web::middleware::Next;
+use std::fmt;
 use regex::Regex;
 
 pub fn check_mail_invalid(input: &str) -> bool {
@@ -18,97 +17,6 @@ pub fn check_mail_invalid(input: &str) -> bool {
     true
 }
 
-
-// pub async fn add_csp(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
-//     let re = Regex::new(r"^/file/([^/]+)\.(jpe?g|png)$").unwrap();
-//
-//     let path = req.path().to_owned();
-//     let mut res = next.call(req).await?;
-//
-//
-//     if re.is_match(&path) {
-//         res.headers_mut().insert(
-//             header::CONTENT_SECURITY_POLICY,
-//             header::HeaderValue::from_static("default-src 'self'; img-src 'self'; script-src 'none'; style-src 'self' 'unsafe-inline';"),
-//         );
-//     } else {
-//         res.headers_mut().insert(
-//             header::CONTENT_SECURITY_POLICY,
-//             header::HeaderValue::from_static("default-src 'self' data:; img-src 'self' data: blob:; style-src 'self'; script-src 'self';"),
-//         );
-//     }
-//
-//     Ok(res)
-// }
-
-pub async fn add_csp(
-    req: ServiceRequest, next: Next<BoxBody>
-) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
-    let mut response = next.call(req).await?;
-
-    let csp_policy = [
-        "default-src 'self'",
-        "script-src 'self'",
-        "style-src 'self' 'unsafe-inline'",
-        "img-src 'self' data: blob: https:",
-        "connect-src 'self' ws: wss: https:",
-        "font-src 'self' data: https:",
-        "frame-src 'none'",
-        "object-src 'none'",
-        "base-uri 'self'",
-        "form-action 'self'",
-        "media-src 'self' blob:; ",
-        "worker-src 'self' blob:; ",
-        "manifest-src 'self'"
-    ];
-    
-    response.headers_mut().insert(
-        HeaderName::from_static("content-security-policy"),
-        HeaderValue::from_str(&csp_policy.join("; "))?
-    );
-    
-    response.headers_mut().insert(
-        HeaderName::from_static("x-content-type-options"),
-        HeaderValue::from_static("nosniff")
-    );
-
-    response.headers_mut().insert(
-        HeaderName::from_static("x-frame-options"),
-        HeaderValue::from_static("DENY")
-    );
-
-    response.headers_mut().insert(
-        HeaderName::from_static("referrer-policy"),
-        HeaderValue::from_static("strict-origin-when-cross-origin")
-    );
-
-    Ok(response)
-}
-
-pub async fn add_cors(
-    req: ServiceRequest, next: Next<BoxBody>
-) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
-    let mut response = next.call(req).await?;
-
-    response.headers_mut().insert(
-        HeaderName::from_static("access-control-allow-origin"),
-        HeaderValue::from_static("https://xn--hatradefteri-34b.com")
-    );
-
-    // // CORS için ek başlıklar ekleyebilirsiniz:
-    // response.headers_mut().insert(
-    //     HeaderName::from_static("access-control-allow-methods"),
-    //     HeaderValue::from_static("GET, POST, PUT, DELETE, OPTIONS")
-    // );
-    //
-    // response.headers_mut().insert(
-    //     HeaderName::from_static("access-control-allow-headers"),
-    //     HeaderValue::from_static("Content-Type, Authorization")
-    // );
-
-    Ok(response)
-}
-
 pub fn compare_string(val1: &String, val2: &String) -> bool {
     val1 == val2
 }
@@ -137,21 +45,223 @@ pub fn check_xss(inputs: Vec<&String>) -> bool {
     false
 }
 
-pub fn sign(key: &str, value: &String) -> String {
-    let secret_key = std::env::var("SECRET_KEY").expect("env err -> SECRET_KEY");
-    let sign_key: Hmac<Sha384> = Hmac::new_from_slice(&secret_key.into_bytes()).unwrap();
-    let mut claims = BTreeMap::new();
-    claims.insert(key, value);
+pub const ACCESS_TOKEN: &str = "access";
+pub const REFRESH_TOKEN: &str = "refresh";
+pub const VERIFY_EMAIL_TOKEN: &str = "verify_email";
+pub const RESET_PASSWORD_TOKEN: &str = "reset_password";
+pub const PRE_AUTH_TOKEN: &str = "pre_auth";
+pub const EXPORT_TOKEN: &str = "export";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub sid: String,
+    pub typ: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Jwt(Error),
+    Expired,
+    WrongIssuer,
+    WrongType,
+}
+
+impl From<Error> for TokenError {
+    fn from(e: Error) -> Self {
+        TokenError::Jwt(e)
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Jwt(e) => write!(f, "{}", e),
+            TokenError::Expired => write!(f, "token expired"),
+            TokenError::WrongIssuer => write!(f, "wrong issuer"),
+            TokenError::WrongType => write!(f, "wrong token type"),
+        }
+    }
+}
+
+/// a key that was replaced by `SECRET_KEY`, still accepted for verification until `until`
+pub struct RetiredKey {
+    pub kid: String,
+    pub secret: String,
+    pub until: DateTime<Utc>,
+}
+
+pub struct TokenKeys {
+    pub kid: String,
+    pub secret: String,
+    pub retired: Vec<RetiredKey>,
+    pub issuer: String,
+    pub access_ttl: Duration,
+    pub refresh_ttl: Duration,
+    pub verify_email_ttl: Duration,
+    pub reset_password_ttl: Duration,
+    pub pre_auth_ttl: Duration,
+    /// how long the download link of a data export works
+    pub export_ttl: Duration,
+}
+
+impl TokenKeys {
+    pub fn new(kid: &str, secret: &str) -> Self {
+        Self {
+            kid: kid.to_string(),
+            secret: secret.to_string(),
+            retired: Vec::new(),
+            issuer: "gallery-backend".to_string(),
+            access_ttl: Duration::minutes(15),
+            refresh_ttl: Duration::days(30),
+            verify_email_ttl: Duration::days(1),
+            reset_password_ttl: Duration::hours(1),
+            pre_auth_ttl: Duration::minutes(5),
+            export_ttl: Duration::days(7),
+        }
+    }
+
+    /// `SECRET_KEY` signs, `SECRET_KEY_ID` is its kid,
+    /// `RETIRED_SECRET_KEYS` is a comma separated list of `kid:until_unix:secret`
+    pub fn from_env() -> Self {
+        let secret_key = std::env::var("SECRET_KEY").expect("env err -> SECRET_KEY");
+        let kid = std::env::var("SECRET_KEY_ID").unwrap_or_else(|_| "default".to_string());
+        let mut keys = Self::new(&kid, &secret_key);
+
+        if let Ok(retired) = std::env::var("RETIRED_SECRET_KEYS") {
+            keys.retired = retired
+                .split(',')
+                .filter(|k| !k.is_empty())
+                .map(|k| {
+                    let mut parts = k.trim().splitn(3, ':');
+                    let kid = parts.next().unwrap_or_default();
+                    let until = parts
+                        .next()
+                        .and_then(|u| u.parse().ok())
+                        .and_then(|u| DateTime::from_timestamp(u, 0))
+                        .expect("env err -> RETIRED_SECRET_KEYS");
+                    let secret = parts.next().expect("env err -> RETIRED_SECRET_KEYS");
+
+                    RetiredKey {
+                        kid: kid.to_string(),
+                        secret: secret.to_string(),
+                        until,
+                    }
+                })
+                .collect();
+        }
+
+        if let Ok(issuer) = std::env::var("JWT_ISSUER") {
+            keys.issuer = issuer;
+        }
+        if let Some(ttl) = ttl_from_env("ACCESS_TOKEN_TTL") {
+            keys.access_ttl = ttl;
+        }
+        if let Some(ttl) = ttl_from_env("REFRESH_TOKEN_TTL") {
+            keys.refresh_ttl = ttl;
+        }
+        if let Some(ttl) = ttl_from_env("VERIFY_EMAIL_TOKEN_TTL") {
+            keys.verify_email_ttl = ttl;
+        }
+        if let Some(ttl) = ttl_from_env("RESET_PASSWORD_TOKEN_TTL") {
+            keys.reset_password_ttl = ttl;
+        }
+        if let Some(ttl) = ttl_from_env("PRE_AUTH_TOKEN_TTL") {
+            keys.pre_auth_ttl = ttl;
+        }
+        if let Some(ttl) = ttl_from_env("EXPORT_TOKEN_TTL") {
+            keys.export_ttl = ttl;
+        }
+
+        keys
+    }
+
+    pub fn ttl(&self, typ: &str) -> Duration {
+        match typ {
+            REFRESH_TOKEN => self.refresh_ttl,
+            VERIFY_EMAIL_TOKEN => self.verify_email_ttl,
+            RESET_PASSWORD_TOKEN => self.reset_password_ttl,
+            PRE_AUTH_TOKEN => self.pre_auth_ttl,
+            EXPORT_TOKEN => self.export_ttl,
+            _ => self.access_ttl,
+        }
+    }
+
+    pub fn sign(&self, typ: &str, sub: &str, sid: &str) -> String {
+        self.sign_with_id(typ, sub, sid, &random_id())
+    }
 
+    /// for tokens whose `jti` is also stored, so they can be used only once
+    pub fn sign_with_id(&self, typ: &str, sub: &str, sid: &str, jti: &str) -> String {
+        let now = Utc::now();
+        let sign_key: Hmac<Sha384> = Hmac::new_from_slice(self.secret.as_bytes()).unwrap();
+        let header = Header {
+            algorithm: AlgorithmType::Hs384,
+            key_id: Some(self.kid.clone()),
+            ..Default::default()
+        };
+        let claims = Claims {
+            iss: self.issuer.clone(),
+            sub: sub.to_string(),
+            sid: sid.to_string(),
+            typ: typ.to_string(),
+            iat: now.timestamp(),
+            exp: (now + self.ttl(typ)).timestamp(),
+            jti: jti.to_string(),
+        };
 
-    claims.sign_with_key(&sign_key).unwrap()
+        Token::new(header, claims)
+            .sign_with_key(&sign_key)
+            .unwrap()
+            .as_str()
+            .to_string()
+    }
+
+    pub fn verify(&self, token_str: &str, typ: &str) -> Result<Claims, TokenError> {
+        let now = Utc::now();
+        let mut store: BTreeMap<&str, Hmac<Sha384>> = BTreeMap::new();
+        store.insert(&self.kid, Hmac::new_from_slice(self.secret.as_bytes()).unwrap());
+        for key in self.retired.iter().filter(|k| k.until > now) {
+            store.insert(&key.kid, Hmac::new_from_slice(key.secret.as_bytes()).unwrap());
+        }
+
+        let token: Token<Header, Claims, _> = token_str.verify_with_store(&store)?;
+        let claims = token.claims();
+
+        if claims.exp <= now.timestamp() {
+            return Err(TokenError::Expired);
+        }
+        if claims.iss != self.issuer {
+            return Err(TokenError::WrongIssuer);
+        }
+        if claims.typ != typ {
+            return Err(TokenError::WrongType);
+        }
+
+        Ok(claims.clone())
+    }
 }
 
-pub fn verify(token_str: &str, key: &str) -> Result<String, Error> {
-    let secret_key = std::env::var("SECRET_KEY").expect("env err -> SECRET_KEY");
-    let sign_key: Hmac<Sha384> = Hmac::new_from_slice(&secret_key.into_bytes()).unwrap();
+fn ttl_from_env(key: &str) -> Option<Duration> {
+    std::env::var(key)
+        .ok()
+        .map(|v| Duration::seconds(v.parse().unwrap_or_else(|_| panic!("env err -> {}", key))))
+}
 
-    let verified_claims: BTreeMap<String, String> = token_str.verify_with_key(&sign_key)?;
+pub fn random_id() -> String {
+    let mut buf = [0u8; 16];
+    openssl::rand::rand_bytes(&mut buf).expect("rand err");
 
-    Ok(verified_claims[key].to_string())
-}
\ No newline at end of file
+    hex::encode(buf)
+}
+
+/// base of links to this site in mails and redirects, `PUBLIC_URL` or `https://DOMAIN`
+pub fn public_url() -> String {
+    std::env::var("PUBLIC_URL").unwrap_or_else(|_| {
+        format!("https://{}", std::env::var("DOMAIN").expect("env err -> DOMAIN"))
+    })
+}
@@ -0,0 +1,96 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// RFC 6238 defaults, what authenticator apps assume when the uri doesn't say otherwise
+pub const STEP_SECS: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// steps accepted before and after the current one, for clock drift
+pub const SKEW: i64 = 1;
+
+pub fn generate_secret() -> String {
+    let mut buf = [0u8; 20];
+    openssl::rand::rand_bytes(&mut buf).expect("rand err");
+
+    BASE32_NOPAD.encode(&buf)
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = encode_uri_component(issuer);
+
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        encode_uri_component(account),
+        secret,
+        issuer,
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+pub fn step(unix: i64) -> i64 {
+    unix.div_euclid(STEP_SECS)
+}
+
+/// RFC 4226 HOTP of `secret` (base32) at `counter`
+pub fn code(secret: &str, counter: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let mut mac: Hmac<Sha1> = Hmac::new_from_slice(&key).ok()?;
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+
+    Some(format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize))
+}
+
+/// a totp code rather than a recovery code
+pub fn is_code(input: &str) -> bool {
+    let input = input.trim();
+
+    input.len() == DIGITS as usize && input.chars().all(|c| c.is_ascii_digit())
+}
+
+/// the step `input` matches around `unix`, so the caller can refuse a step that was already used
+pub fn verify(secret: &str, input: &str, unix: i64) -> Option<i64> {
+    let input = input.trim();
+    if !is_code(input) {
+        return None;
+    }
+
+    let now = step(unix);
+    (now - SKEW..=now + SKEW).find(|s| code(secret, *s).is_some_and(|c| openssl::memcmp::eq(c.as_bytes(), input.as_bytes())))
+}
+
+/// ten `xxxxx-xxxxx` codes, shown once and stored as [`hash_recovery_code`]
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..10)
+        .map(|_| {
+            let mut buf = [0u8; 5];
+            openssl::rand::rand_bytes(&mut buf).expect("rand err");
+            let code = hex::encode(buf);
+
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// recovery codes are random, a fast hash is enough to keep them unreadable in the database
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.trim().to_lowercase().chars().filter(|c| *c != '-' && *c != ' ').collect();
+
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
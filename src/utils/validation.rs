@@ -0,0 +1,93 @@
+use crate::utils::security::check_mail_invalid;
+use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+static USERNAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_][A-Za-z0-9_.]{1,30}[A-Za-z0-9_]$").unwrap());
+static USERNAME_PREFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_.]{1,32}$").unwrap());
+static RECORD_ID: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]{1,64}$").unwrap());
+static FILE_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]{1,128}\.[A-Za-z0-9]{1,8}$").unwrap());
+static EXTENSION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z0-9]{1,8}$").unwrap());
+static ONE_TIME_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9A-Za-z -]{6,16}$").unwrap());
+static RATIO: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d{1,5}([.:/]\d{1,5})?$").unwrap());
+
+#[derive(Debug, PartialEq)]
+pub struct ValidationError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl From<ValidationError> for actix_web::Error {
+    fn from(e: ValidationError) -> Self {
+        actix_web::error::ErrorBadRequest(e.to_string())
+    }
+}
+
+/// rejects input that doesn't fit the form instead of rewriting it,
+/// values are passed to the database as bound parameters and encoded by the client
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationError>;
+}
+
+fn check(ok: bool, field: &'static str, message: &'static str) -> Result<(), ValidationError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(ValidationError {
+            field,
+            message: message.to_string(),
+        })
+    }
+}
+
+pub fn username(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(
+        USERNAME.is_match(value) && !value.contains(".."),
+        field,
+        "3-32 letters, digits, '_' or '.'",
+    )
+}
+
+pub fn username_prefix(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(USERNAME_PREFIX.is_match(value), field, "letters, digits, '_' or '.'")
+}
+
+pub fn email(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(value.len() <= 254 && !check_mail_invalid(value), field, "invalid email")
+}
+
+pub fn password(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(!value.is_empty() && value.chars().count() <= 128, field, "1-128 characters")
+}
+
+pub fn new_password(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check((8..=128).contains(&value.chars().count()), field, "8-128 characters")
+}
+
+pub fn record_id(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(RECORD_ID.is_match(value), field, "invalid id")
+}
+
+/// a totp code or a recovery code
+pub fn one_time_code(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(ONE_TIME_CODE.is_match(value), field, "invalid code")
+}
+
+pub fn ratio(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(RATIO.is_match(value), field, "invalid ratio")
+}
+
+pub fn file_name(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(FILE_NAME.is_match(value), field, "invalid file name")
+}
+
+pub fn extension(field: &'static str, value: &str) -> Result<(), ValidationError> {
+    check(EXTENSION.is_match(value), field, "invalid file extension")
+}
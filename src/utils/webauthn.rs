@@ -0,0 +1,303 @@
+use ciborium::Value;
+use data_encoding::BASE64URL_NOPAD;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use serde::Deserialize;
+use std::fmt;
+
+/// COSE algorithm ids we offer in `pubKeyCredParams`
+pub const ES256: i64 = -7;
+pub const RS256: i64 = -257;
+
+const FLAG_UP: u8 = 0x01;
+const FLAG_UV: u8 = 0x04;
+const FLAG_AT: u8 = 0x40;
+
+#[derive(Debug, PartialEq)]
+pub enum WebauthnError {
+    Encoding,
+    ClientData(&'static str),
+    AuthenticatorData(&'static str),
+    Attestation(&'static str),
+    UnsupportedKey,
+    Signature,
+    Counter,
+}
+
+impl fmt::Display for WebauthnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebauthnError::Encoding => write!(f, "malformed credential"),
+            WebauthnError::ClientData(e) => write!(f, "client data: {}", e),
+            WebauthnError::AuthenticatorData(e) => write!(f, "authenticator data: {}", e),
+            WebauthnError::Attestation(e) => write!(f, "attestation: {}", e),
+            WebauthnError::UnsupportedKey => write!(f, "unsupported public key"),
+            WebauthnError::Signature => write!(f, "signature mismatch"),
+            WebauthnError::Counter => write!(f, "signature counter went backwards"),
+        }
+    }
+}
+
+/// the relying party is this server, `origin` is where the frontend runs
+#[derive(Clone, Debug)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    /// `WEBAUTHN_RP_ID` (the domain by default), `WEBAUTHN_RP_NAME`, `WEBAUTHN_ORIGIN` (`https://DOMAIN` by default)
+    pub fn from_env() -> Self {
+        let domain = std::env::var("DOMAIN").expect("env err -> DOMAIN");
+
+        Self {
+            id: std::env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| domain.clone()),
+            name: std::env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| domain.clone()),
+            origin: std::env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| format!("https://{}", domain)),
+        }
+    }
+}
+
+/// a new credential, the public key is kept as SubjectPublicKeyInfo DER
+#[derive(Debug)]
+pub struct RegisteredKey {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub alg: i64,
+    pub sign_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    typ: String,
+    challenge: String,
+    origin: String,
+}
+
+pub fn challenge() -> String {
+    let mut buf = [0u8; 32];
+    openssl::rand::rand_bytes(&mut buf).expect("rand err");
+
+    BASE64URL_NOPAD.encode(&buf)
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    BASE64URL_NOPAD
+        .decode(value.trim_end_matches('=').as_bytes())
+        .map_err(|_| WebauthnError::Encoding)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    BASE64URL_NOPAD.encode(bytes)
+}
+
+/// the challenge the browser signed, used to find the stored one before verifying
+pub fn client_challenge(client_data_json: &[u8]) -> Result<String, WebauthnError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json).map_err(|_| WebauthnError::Encoding)?;
+
+    Ok(client_data.challenge)
+}
+
+fn check_client_data(
+    rp: &RelyingParty,
+    typ: &str,
+    challenge: &str,
+    client_data_json: &[u8],
+) -> Result<Vec<u8>, WebauthnError> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json).map_err(|_| WebauthnError::Encoding)?;
+
+    if client_data.typ != typ {
+        return Err(WebauthnError::ClientData("wrong type"));
+    }
+    if client_data.challenge.len() != challenge.len()
+        || !openssl::memcmp::eq(client_data.challenge.as_bytes(), challenge.as_bytes())
+    {
+        return Err(WebauthnError::ClientData("wrong challenge"));
+    }
+    if client_data.origin != rp.origin {
+        return Err(WebauthnError::ClientData("wrong origin"));
+    }
+
+    Ok(openssl::sha::sha256(client_data_json).to_vec())
+}
+
+/// rp id hash, user present and verified flags, returns flags and counter
+fn check_authenticator_data(rp: &RelyingParty, auth_data: &[u8]) -> Result<(u8, u32), WebauthnError> {
+    if auth_data.len() < 37 {
+        return Err(WebauthnError::AuthenticatorData("too short"));
+    }
+    if auth_data[..32] != openssl::sha::sha256(rp.id.as_bytes()) {
+        return Err(WebauthnError::AuthenticatorData("wrong rp id"));
+    }
+
+    let flags = auth_data[32];
+    if flags & FLAG_UP == 0 {
+        return Err(WebauthnError::AuthenticatorData("user not present"));
+    }
+    if flags & FLAG_UV == 0 {
+        return Err(WebauthnError::AuthenticatorData("user not verified"));
+    }
+
+    let sign_count = u32::from_be_bytes([auth_data[33], auth_data[34], auth_data[35], auth_data[36]]);
+
+    Ok((flags, sign_count))
+}
+
+/// attestation `none`, or `packed` self attestation signed by the new key.
+/// certificate chains aren't checked, we don't restrict authenticator models
+pub fn verify_registration(
+    rp: &RelyingParty,
+    challenge: &str,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<RegisteredKey, WebauthnError> {
+    let client_data_hash = check_client_data(rp, "webauthn.create", challenge, client_data_json)?;
+
+    let attestation: Value = ciborium::de::from_reader(attestation_object).map_err(|_| WebauthnError::Encoding)?;
+    let fmt = map_get(&attestation, "fmt").and_then(Value::as_text).ok_or(WebauthnError::Encoding)?;
+    let auth_data = map_get(&attestation, "authData")
+        .and_then(Value::as_bytes)
+        .ok_or(WebauthnError::Encoding)?;
+    let statement = map_get(&attestation, "attStmt").ok_or(WebauthnError::Encoding)?;
+
+    let (flags, sign_count) = check_authenticator_data(rp, auth_data)?;
+    if flags & FLAG_AT == 0 {
+        return Err(WebauthnError::AuthenticatorData("no attested credential"));
+    }
+
+    // aaguid (16) | credential id length (2) | credential id | COSE key
+    let rest = &auth_data[37..];
+    if rest.len() < 18 {
+        return Err(WebauthnError::AuthenticatorData("too short"));
+    }
+    let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+    let credential_id = rest
+        .get(18..18 + id_len)
+        .ok_or(WebauthnError::AuthenticatorData("too short"))?
+        .to_vec();
+    let cose_key: Value = ciborium::de::from_reader(&rest[18 + id_len..]).map_err(|_| WebauthnError::Encoding)?;
+    let (alg, key) = public_key(&cose_key)?;
+
+    match fmt {
+        "none" => {}
+        "packed" => {
+            if map_get(statement, "x5c").is_some() {
+                return Err(WebauthnError::Attestation("certificate attestation not supported"));
+            }
+            let statement_alg = map_get(statement, "alg").and_then(as_i64);
+            let signature = map_get(statement, "sig")
+                .and_then(Value::as_bytes)
+                .ok_or(WebauthnError::Encoding)?;
+
+            if statement_alg != Some(alg) {
+                return Err(WebauthnError::Attestation("algorithm mismatch"));
+            }
+            verify_signature(&key, auth_data, &client_data_hash, signature)?;
+        }
+        _ => return Err(WebauthnError::Attestation("unsupported format")),
+    }
+
+    Ok(RegisteredKey {
+        credential_id,
+        public_key: key.public_key_to_der().map_err(|_| WebauthnError::UnsupportedKey)?,
+        alg,
+        sign_count,
+    })
+}
+
+/// checks a login assertion against the stored key, returns the new signature counter
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    challenge: &str,
+    public_key_der: &[u8],
+    stored_count: u32,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> Result<u32, WebauthnError> {
+    let client_data_hash = check_client_data(rp, "webauthn.get", challenge, client_data_json)?;
+    let (_, sign_count) = check_authenticator_data(rp, authenticator_data)?;
+
+    let key = PKey::public_key_from_der(public_key_der).map_err(|_| WebauthnError::UnsupportedKey)?;
+    verify_signature(&key, authenticator_data, &client_data_hash, signature)?;
+
+    // authenticators without a counter always send 0
+    if (sign_count != 0 || stored_count != 0) && sign_count <= stored_count {
+        return Err(WebauthnError::Counter);
+    }
+
+    Ok(sign_count)
+}
+
+fn verify_signature(
+    key: &PKey<Public>,
+    auth_data: &[u8],
+    client_data_hash: &[u8],
+    signature: &[u8],
+) -> Result<(), WebauthnError> {
+    let mut verifier = Verifier::new(MessageDigest::sha256(), key).map_err(|_| WebauthnError::UnsupportedKey)?;
+    verifier.update(auth_data).map_err(|_| WebauthnError::Signature)?;
+    verifier.update(client_data_hash).map_err(|_| WebauthnError::Signature)?;
+
+    match verifier.verify(signature) {
+        Ok(true) => Ok(()),
+        _ => Err(WebauthnError::Signature),
+    }
+}
+
+/// EC2 P-256 with ES256 or RSA with RS256
+fn public_key(cose_key: &Value) -> Result<(i64, PKey<Public>), WebauthnError> {
+    let param = |label: i64| {
+        cose_key
+            .as_map()
+            .and_then(|m| m.iter().find(|(k, _)| as_i64(k) == Some(label)))
+            .map(|(_, v)| v)
+    };
+    let bytes = |label: i64| param(label).and_then(Value::as_bytes).ok_or(WebauthnError::UnsupportedKey);
+
+    let kty = param(1).and_then(as_i64);
+    let alg = param(3).and_then(as_i64);
+
+    match (kty, alg) {
+        (Some(2), Some(ES256)) => {
+            if param(-1).and_then(as_i64) != Some(1) {
+                return Err(WebauthnError::UnsupportedKey);
+            }
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(|_| WebauthnError::UnsupportedKey)?;
+            let x = BigNum::from_slice(bytes(-2)?).map_err(|_| WebauthnError::UnsupportedKey)?;
+            let y = BigNum::from_slice(bytes(-3)?).map_err(|_| WebauthnError::UnsupportedKey)?;
+            let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+                .map_err(|_| WebauthnError::UnsupportedKey)?;
+            key.check_key().map_err(|_| WebauthnError::UnsupportedKey)?;
+
+            Ok((ES256, PKey::from_ec_key(key).map_err(|_| WebauthnError::UnsupportedKey)?))
+        }
+        (Some(3), Some(RS256)) => {
+            let n = BigNum::from_slice(bytes(-1)?).map_err(|_| WebauthnError::UnsupportedKey)?;
+            let e = BigNum::from_slice(bytes(-2)?).map_err(|_| WebauthnError::UnsupportedKey)?;
+            let key = Rsa::from_public_components(n, e).map_err(|_| WebauthnError::UnsupportedKey)?;
+
+            Ok((RS256, PKey::from_rsa(key).map_err(|_| WebauthnError::UnsupportedKey)?))
+        }
+        _ => Err(WebauthnError::UnsupportedKey),
+    }
+}
+
+fn map_get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value
+        .as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn as_i64(value: &Value) -> Option<i64> {
+    value.as_integer().and_then(|i| i64::try_from(i).ok())
+}
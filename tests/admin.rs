@@ -0,0 +1,296 @@
+mod common;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{test, Error};
+use common::{app_data, finish_job, multipart, png, post_request, profile, register, setup, status, unique, PASSWORD};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use serde_json::{json, Value};
+
+/// registers a user and gives them the admin role
+async fn admin<S, B>(app: &S) -> Cookie<'static>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let username = unique("admin");
+    let token = register(app, &username).await;
+    assert!(db::surrealdb::set_role_by_username(&username, "admin").await.unwrap());
+
+    token
+}
+
+async fn login_status<S, B>(app: &S, username: &str) -> StatusCode
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn admin_routes_need_the_admin_role() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("mehmet")).await;
+
+    let req = test::TestRequest::get().uri("/admin/users").to_request();
+    let res = test::try_call_service(&app, req).await;
+    assert_eq!(res.err().unwrap().as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get().uri("/admin/users").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let req = post_request().uri("/admin/users/someone/suspend").cookie(token).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let admin = admin(&app).await;
+    let req = test::TestRequest::get().uri("/admin/users").cookie(admin).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn users_are_listed_and_searched() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+    let username = unique("Zeynep");
+    register(&app, &username).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/users?q={}", username.to_lowercase()))
+        .cookie(admin.clone())
+        .to_request();
+    let users: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0]["username"], username.as_str());
+    assert_eq!(users[0]["role"], "user");
+    assert_eq!(users[0]["suspended"], false);
+    assert_eq!(users[0]["premium_until"], Value::Null);
+    assert_eq!(users[0]["posts"], 0);
+
+    let req = test::TestRequest::get()
+        .uri("/admin/users?limit=1000")
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn suspension_ends_sessions_and_blocks_login() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+    let username = unique("kerem");
+    let token = register(&app, &username).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/suspend", id))
+        .cookie(admin.clone())
+        .set_json(json!({"reason": "spam"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    assert_eq!(status(&app, &token).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login_status(&app, &username).await, StatusCode::FORBIDDEN);
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/unsuspend", id))
+        .cookie(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(login_status(&app, &username).await, StatusCode::OK);
+
+    let req = post_request()
+        .uri("/admin/users/nobody/suspend")
+        .cookie(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/admin/audit").cookie(admin).to_request();
+    let events: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let suspend = events
+        .iter()
+        .find(|e| e["action"] == "admin.suspend" && e["target"] == id.as_str())
+        .expect("suspend event");
+    assert_eq!(suspend["detail"], "spam");
+    assert!(events.iter().any(|e| e["action"] == "admin.unsuspend" && e["target"] == id.as_str()));
+}
+
+#[actix_web::test]
+async fn premium_is_granted_and_revoked() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+    let token = register(&app, &unique("selin")).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+
+    let premium = |token: Cookie<'static>| test::TestRequest::get().uri("/premium").cookie(token).to_request();
+    assert_eq!(test::call_service(&app, premium(token.clone())).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/premium/grant", id))
+        .cookie(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, premium(token.clone())).await.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/premium/revoke", id))
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, premium(token)).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn posts_are_taken_down() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+    let username = unique("deniz");
+    let token = register(&app, &username).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+    db::surrealdb::premium_grant(&format!("user:{}", id)).await.unwrap();
+
+    let boundary = "gallery-boundary";
+    let seed = (username.len() as u8).wrapping_mul(11);
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+        .set_payload(multipart(boundary, "1", "photo.png", &png([0, seed, 96])))
+        .to_request();
+    let post: Value = test::call_and_read_body_json(&app, req).await;
+    let image = post["image"].as_str().unwrap().to_string();
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/posts/{}/takedown", id, post["id"].as_str().unwrap()))
+        .cookie(admin.clone())
+        .set_json(json!({"reason": "copyright"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert!(!std::path::Path::new(&format!("images/{}", image)).exists());
+
+    let req = test::TestRequest::get().uri("/post").cookie(token).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(posts.is_empty());
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/posts/{}/takedown", id, post["id"].as_str().unwrap()))
+        .cookie(admin)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn held_posts_wait_for_an_admin() {
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let admin = admin(&app).await;
+    let username = unique("incele");
+    let token = register(&app, &username).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+    db::surrealdb::premium_grant(&format!("user:{}", id)).await.unwrap();
+
+    // scored between the review and reject thresholds
+    let upload = |shade: u8| {
+        let boundary = "gallery-boundary";
+        let image = [png([170, shade, 0]), username.as_bytes().to_vec()].concat();
+        post_request()
+            .uri("/upload")
+            .cookie(token.clone())
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(multipart(boundary, "1", "photo.png", &image))
+            .to_request()
+    };
+    let held: Value = test::call_and_read_body_json(&app, upload(0)).await;
+    let job = finish_job(&app, &token, &data, held["job"].as_str().unwrap()).await;
+    assert_eq!(job["result"], "held for review");
+    let other: Value = test::call_and_read_body_json(&app, upload(1)).await;
+    finish_job(&app, &token, &data, other["job"].as_str().unwrap()).await;
+
+    let req = test::TestRequest::get().uri("/post").cookie(token.clone()).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(posts.iter().all(|post| post["status"] == "review"));
+    assert!(posts[0]["scores"]["nsfw"].as_f64().unwrap() > 0.5);
+
+    let file = format!("/file/{}", held["image"].as_str().unwrap());
+    let req = test::TestRequest::get().uri(&file).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/admin/reviews").cookie(admin.clone()).to_request();
+    let reviews: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let review = reviews.iter().find(|r| r["post_id"] == held["id"]).expect("held post");
+    assert_eq!(review["user_id"], id.as_str());
+    assert_eq!(review["username"], username.as_str());
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/reviews/{}", held["image"].as_str().unwrap()))
+        .cookie(admin.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let decide = |post: &Value, decision: &str| {
+        post_request()
+            .uri(&format!("/admin/users/{}/posts/{}/{}", id, post["id"].as_str().unwrap(), decision))
+            .cookie(admin.clone())
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, decide(&held, "approve")).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, decide(&held, "approve")).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(test::call_service(&app, decide(&held, "reject")).await.status(), StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get().uri(&file).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    assert_eq!(test::call_service(&app, decide(&other, "reject")).await.status(), StatusCode::OK);
+    assert!(!std::path::Path::new(&format!("images/{}", other["image"].as_str().unwrap())).exists());
+
+    let req = test::TestRequest::get().uri("/post").cookie(token.clone()).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0]["status"], "ready");
+
+    let req = test::TestRequest::get().uri("/upload_limit").cookie(token).to_request();
+    assert_eq!(test::call_and_read_body(&app, req).await, "19");
+}
+
+#[actix_web::test]
+async fn inference_metrics_are_reported() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+
+    let req = test::TestRequest::get().uri("/admin/inference").cookie(admin).to_request();
+    let metrics: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(metrics["queue_depth"], 0);
+    assert_eq!(metrics["batches"], 0);
+    assert_eq!(metrics["wait_ms"], 0.0);
+}
+
+#[actix_web::test]
+async fn pending_deletions_are_listed() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin = admin(&app).await;
+    let token = register(&app, &unique("ayla")).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+
+    let req = post_request().uri("/delete").cookie(token).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/admin/deletions").cookie(admin).to_request();
+    let pending: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let entry = pending.iter().find(|p| p["user_id"] == id.as_str()).expect("pending deletion");
+    assert!(entry["scheduled_at"].as_str().unwrap() > chrono::Utc::now().to_rfc3339().as_str());
+}
@@ -0,0 +1,673 @@
+mod common;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{test, Error};
+use common::{
+    app_data, app_data_with_passwords, cookie, finish_job, multipart, png, post_request, profile, register, setup, status, unique,
+    CSRF_TOKEN, PASSWORD,
+};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::service::password_service::PasswordConfig;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn register_login_and_profile() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("alice");
+
+    let token = register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": "wrong"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(cookie(&res, "token").is_some());
+    assert_eq!(cookie(&res, "logged").unwrap().value(), "1");
+
+    let user = profile(&app, &token).await;
+    assert_eq!(user["username"], username.as_str());
+    assert_eq!(user["email"], format!("{}@example.com", username));
+}
+
+#[actix_web::test]
+async fn protected_routes_require_token() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let req = test::TestRequest::get().uri("/profile").to_request();
+    let res = test::try_call_service(&app, req).await;
+    assert_eq!(res.err().unwrap().as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri("/follow/someone")
+        .cookie(Cookie::new("token", "forged"))
+        .to_request();
+    let res = test::try_call_service(&app, req).await;
+    assert_eq!(res.err().unwrap().as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn requests_never_act_as_an_earlier_caller() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("quinn")).await;
+    let revoked = register(&app, &unique("rupert")).await;
+    let req = test::TestRequest::get().uri("/logout").cookie(revoked.clone()).to_request();
+    test::call_service(&app, req).await;
+
+    // the first user was the last one let through before each of these
+    for uri in ["/upload_limit", "/friends", "/premium", "/follow/requests"] {
+        for cookie in [None, Some(revoked.clone()), Some(Cookie::new("token", "forged"))] {
+            assert_eq!(status(&app, &token).await, StatusCode::OK);
+
+            let mut req = test::TestRequest::get().uri(uri);
+            if let Some(cookie) = cookie {
+                req = req.cookie(cookie);
+            }
+            let res = test::call_service(&app, req.to_request()).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn upload_list_and_delete_post() {
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("bob");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+
+    let boundary = "gallery-boundary";
+    let content_type = format!("multipart/form-data; boundary={}", boundary);
+    let seed = (username.len() as u8).wrapping_mul(7);
+    let safe_image = png([0, seed, 64]);
+
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", content_type.as_str()))
+        .set_payload(multipart(boundary, "1", "photo.png", &safe_image))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", content_type.as_str()))
+        .set_payload(multipart(boundary, "1", "photo.png", &safe_image))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let post: Value = test::read_body_json(res).await;
+    let post_id = post["id"].as_str().unwrap().to_string();
+    let image = post["image"].as_str().unwrap().to_string();
+    assert_eq!(post["status"], "processing");
+    assert!(image.ends_with(".png"));
+    assert!(std::path::Path::new(&format!("images/{}", image)).exists());
+    let job = finish_job(&app, &token, &data, post["job"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "done");
+
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", content_type.as_str()))
+        .set_payload(multipart(boundary, "1", "photo.png", &safe_image))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", content_type.as_str()))
+        .set_payload(multipart(boundary, "1", "red.png", &png([255, seed, 0])))
+        .to_request();
+    let red: Value = test::call_and_read_body_json(&app, req).await;
+    let job = finish_job(&app, &token, &data, red["job"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "done");
+    assert_eq!(job["result"], "nsfw");
+    assert!(!std::path::Path::new(&format!("images/{}", red["image"].as_str().unwrap())).exists());
+
+    let req = test::TestRequest::get()
+        .uri("/post")
+        .cookie(token.clone())
+        .to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0]["id"], post_id.as_str());
+    assert_eq!(posts[0]["status"], "ready");
+
+    let req = test::TestRequest::get()
+        .uri("/upload_limit")
+        .cookie(token.clone())
+        .to_request();
+    let limit = test::call_and_read_body(&app, req).await;
+    assert_eq!(limit, "19");
+
+    let req = post_request()
+        .uri("/post/delete")
+        .cookie(token.clone())
+        .set_payload(post_id)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!std::path::Path::new(&format!("images/{}", image)).exists());
+}
+
+#[actix_web::test]
+async fn follow_and_accept() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let alice = unique("carol");
+    let bob = unique("dave");
+    let alice_token = register(&app, &alice).await;
+    let bob_token = register(&app, &bob).await;
+    let alice_id = profile(&app, &alice_token).await["id"].as_str().unwrap().to_string();
+    let bob_id = profile(&app, &bob_token).await["id"].as_str().unwrap().to_string();
+
+    let req = post_request()
+        .uri(&format!("/follow/{}", bob_id))
+        .cookie(alice_token.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/follow/requests")
+        .cookie(alice_token.clone())
+        .to_request();
+    let requests: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(requests[0]["username"], bob.as_str());
+
+    let req = test::TestRequest::get()
+        .uri("/follow/pendings")
+        .cookie(bob_token.clone())
+        .to_request();
+    let pendings: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(pendings[0]["id"], alice_id.as_str());
+
+    let req = post_request()
+        .uri("/follow/accept")
+        .cookie(bob_token.clone())
+        .set_payload(alice_id.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/friends")
+        .cookie(alice_token.clone())
+        .to_request();
+    let friends: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(friends.len(), 1);
+    assert_eq!(friends[0]["username"], bob.as_str());
+
+    let req = post_request()
+        .uri("/unfollow")
+        .cookie(alice_token.clone())
+        .set_payload(bob_id)
+        .to_request();
+    test::call_service(&app, req).await;
+
+    let req = test::TestRequest::get()
+        .uri("/friends")
+        .cookie(bob_token.clone())
+        .to_request();
+    let friends: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(friends.is_empty());
+}
+
+#[actix_web::test]
+async fn delete_deactivates_until_cancelled() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let (username, friend) = (unique("erin"), unique("ferhat"));
+    let token = register(&app, &username).await;
+    let friend_token = register(&app, &friend).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+    let friend_id = profile(&app, &friend_token).await["id"].as_str().unwrap().to_string();
+
+    let req = post_request().uri(&format!("/follow/{}", friend_id)).cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = post_request()
+        .uri("/follow/accept")
+        .cookie(friend_token.clone())
+        .set_payload(id.clone())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = post_request().uri("/delete").cookie(token.clone()).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(cookie(&res, "token").unwrap().value(), "");
+
+    assert!(!friend_names(&app, friend_token.clone()).await.contains(&username));
+
+    // logging in again leaves the deletion scheduled
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let token = cookie(&res, "token").unwrap();
+
+    let req = test::TestRequest::get().uri("/delete/status").cookie(token.clone()).to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["pending"], true);
+    assert!(status["scheduled_at"].as_str().unwrap() > chrono::Utc::now().to_rfc3339().as_str());
+
+    let req = post_request().uri("/delete").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+    let req = post_request().uri("/delete/cancel").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = post_request().uri("/delete/cancel").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/delete/status").cookie(token).to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status, json!({"pending": false, "scheduled_at": null}));
+    assert!(friend_names(&app, friend_token).await.contains(&username));
+}
+
+#[actix_web::test]
+async fn deactivated_accounts_are_not_found_and_their_files_not_served() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("hale");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let stranger = register(&app, &unique("gizem")).await;
+    let image = format!("{}.png", unique("foto"));
+    std::fs::write(format!("images/{}", image), png([0, 0, 64])).unwrap();
+    db::surrealdb::post_add("1".to_string(), &image, &user_id, "ready").await.unwrap();
+
+    let file = |token: Cookie<'static>| test::TestRequest::get().uri(&format!("/file/{}", image)).cookie(token).to_request();
+    assert_eq!(search(&app, stranger.clone(), &username).await.len(), 1);
+    assert_eq!(test::call_service(&app, file(stranger.clone())).await.status(), StatusCode::OK);
+
+    let req = post_request().uri("/delete").cookie(token).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    assert!(search(&app, stranger.clone(), &username).await.is_empty());
+    assert_eq!(test::call_service(&app, file(stranger)).await.status(), StatusCode::NOT_FOUND);
+}
+
+async fn friend_names<S, B>(app: &S, token: Cookie<'static>) -> Vec<String>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get().uri("/friends").cookie(token).to_request();
+    let friends: Vec<Value> = test::call_and_read_body_json(app, req).await;
+
+    friends.iter().map(|f| f["username"].as_str().unwrap().to_string()).collect()
+}
+
+async fn search<S, B>(app: &S, token: Cookie<'static>, username: &str) -> Vec<Value>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = post_request().uri("/users").cookie(token).set_payload(username.to_string()).to_request();
+
+    test::call_and_read_body_json(app, req).await
+}
+
+#[actix_web::test]
+async fn refresh_issues_new_access_token() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("frank");
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    let refresh = cookie(&res, "refresh").expect("refresh cookie");
+    assert_eq!(refresh.path(), Some("/refresh"));
+    assert_eq!(refresh.http_only(), Some(true));
+
+    let req = post_request().uri("/refresh").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri("/refresh")
+        .cookie(refresh)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let token = cookie(&res, "token").expect("token cookie");
+
+    let user = profile(&app, &token).await;
+    assert_eq!(user["username"], username.as_str());
+}
+
+async fn login<S, B>(app: &S, username: &str, user_agent: &str) -> Cookie<'static>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = post_request()
+        .uri("/login")
+        .insert_header(("user-agent", user_agent))
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    cookie(&res, "token").expect("token cookie")
+}
+
+#[actix_web::test]
+async fn sessions_are_listed_and_revocable() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("grace");
+    register(&app, &username).await;
+    let phone = login(&app, &username, "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0) Mobile").await;
+    let laptop = login(&app, &username, "Mozilla/5.0 (X11; Linux x86_64)").await;
+
+    let req = test::TestRequest::get()
+        .uri("/sessions")
+        .cookie(laptop.clone())
+        .to_request();
+    let sessions: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(sessions.len(), 3);
+    let current: Vec<&Value> = sessions.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["device"], "desktop");
+    let phone_session = sessions.iter().find(|s| s["device"] == "mobile").unwrap();
+
+    let req = post_request()
+        .uri("/sessions/revoke")
+        .cookie(laptop.clone())
+        .set_payload(phone_session["id"].as_str().unwrap().to_string())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(status(&app, &phone).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status(&app, &laptop).await, StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/logout")
+        .cookie(laptop.clone())
+        .to_request();
+    test::call_service(&app, req).await;
+    assert_eq!(status(&app, &laptop).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn revoke_all_and_change_password_end_other_sessions() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("heidi");
+    let first = register(&app, &username).await;
+    let second = login(&app, &username, "agent").await;
+
+    let req = post_request()
+        .uri("/change_password")
+        .cookie(second.clone())
+        .set_json(json!({"old": PASSWORD, "new": "Another horse battery"}))
+        .to_request();
+    test::call_service(&app, req).await;
+    assert_eq!(status(&app, &first).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(status(&app, &second).await, StatusCode::OK);
+
+    let req = post_request()
+        .uri("/sessions/revoke_all")
+        .cookie(second.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(cookie(&res, "token").unwrap().value(), "");
+    assert_eq!(status(&app, &second).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn passwords_are_stored_verbatim() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("oscar");
+    let password = "it's \"Dinner; Tom & Jerry\" 1=1 <b>..";
+
+    let req = post_request()
+        .uri("/register")
+        .set_json(json!({
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": password,
+        }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": password}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let token = cookie(&res, "token").unwrap();
+
+    let new_password = "SELECT * FROM user; --'";
+    let req = post_request()
+        .uri("/change_password")
+        .cookie(token)
+        .set_json(json!({"old": password, "new": new_password}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": new_password}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn invalid_input_is_rejected_not_rewritten() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let req = post_request()
+        .uri("/register")
+        .set_json(json!({
+            "username": "tom & jerry",
+            "email": "tom@example.com",
+            "password": PASSWORD,
+        }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::read_body(res).await, "username: 3-32 letters, digits, '_' or '.'");
+
+    let token = register(&app, &unique("peggy")).await;
+    let req = post_request()
+        .uri("/follow/x;DELETE%20user")
+        .cookie(token.clone())
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = post_request()
+        .uri("/users")
+        .cookie(token)
+        .set_payload("a' OR 1=1")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn change_password_requires_the_old_password() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("quinn");
+    let token = register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/change_password")
+        .cookie(token.clone())
+        .set_json(json!({"old": "not the password", "new": "Another horse battery"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = post_request()
+        .uri("/change_password")
+        .cookie(token)
+        .set_json(json!({"old": PASSWORD, "new": format!("{}-horse", username)}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn weak_passwords_are_rejected_on_register() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    for password in ["short", "alllowercaseletters", "0123456789"] {
+        let username = unique("ruth");
+        let req = post_request()
+            .uri("/register")
+            .set_json(json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": password,
+            }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", password);
+    }
+}
+
+#[actix_web::test]
+async fn login_rehashes_with_new_parameters() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("sybil");
+    register(&app, &username).await;
+
+    let old = db::surrealdb::credentials(&username).await.unwrap().unwrap().password;
+    assert!(old.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+
+    let stronger = PasswordConfig {
+        iterations: 3,
+        ..Default::default()
+    };
+    let app = test::init_service(build_app(&config, app_data_with_passwords(stronger))).await;
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let new = db::surrealdb::credentials(&username).await.unwrap().unwrap().password;
+    assert!(new.starts_with("$argon2id$v=19$m=19456,t=3,p=1$"));
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn cookie_authenticated_posts_need_the_csrf_token() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("nesrin")).await;
+
+    let search = |req: test::TestRequest| req.uri("/users").cookie(token.clone()).set_payload("nes").to_request();
+
+    let res = test::call_service(&app, search(test::TestRequest::post())).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let forged = test::TestRequest::post()
+        .cookie(Cookie::new("csrf", CSRF_TOKEN))
+        .insert_header(("X-CSRF-Token", "guessed"));
+    let res = test::call_service(&app, search(forged)).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(&app, search(post_request())).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // without credentials there is nothing to forge, the first visit gets its token
+    let req = test::TestRequest::get().uri("/premium").to_request();
+    let res = test::call_service(&app, req).await;
+    let csrf = cookie(&res, "csrf").expect("csrf cookie");
+    assert!(!csrf.http_only().unwrap_or(false));
+    assert_eq!(csrf.path(), Some("/"));
+}
+
+#[actix_web::test]
+async fn login_cookies_share_one_set_of_attributes() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("oguz");
+    register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    for (name, http_only) in [("token", true), ("refresh", true), ("logged", false), ("csrf", false)] {
+        let c = cookie(&res, name).unwrap_or_else(|| panic!("{} cookie", name));
+        assert_eq!(c.secure(), Some(true), "{}", name);
+        assert_eq!(c.http_only().unwrap_or(false), http_only, "{}", name);
+        assert_eq!(c.same_site(), Some(SameSite::Strict), "{}", name);
+        assert_eq!(c.domain(), Some("localhost"), "{}", name);
+    }
+    // a new csrf token, not the one sent before logging in
+    assert_ne!(cookie(&res, "csrf").unwrap().value(), CSRF_TOKEN);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{app_data, post_request, profile, register, setup, unique, PASSWORD};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+async fn security_history_shows_logins_and_password_changes() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("lale");
+    let token = register(&app, &username).await;
+
+    let login = |password: &str| {
+        post_request()
+            .uri("/login")
+            .insert_header(("User-Agent", "audit-test/1.0"))
+            .set_json(json!({"username": username, "password": password}))
+            .to_request()
+    };
+    let res = test::call_service(&app, login("wrong horse battery")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = test::call_service(&app, login(PASSWORD)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/change_password")
+        .cookie(token.clone())
+        .set_json(json!({"old": PASSWORD, "new": "another correct horse"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/security/history").cookie(token).to_request();
+    let events: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    let actions: Vec<(&str, &str)> = events
+        .iter()
+        .map(|e| (e["action"].as_str().unwrap(), e["outcome"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        actions,
+        [("password_change", "success"), ("login", "success"), ("login", "failure")]
+    );
+
+    let failed = &events[2];
+    assert_eq!(failed["user_agent"], "audit-test/1.0");
+    assert_eq!(failed["by_admin"], false);
+    assert_eq!(events[1]["detail"], "password");
+}
+
+#[actix_web::test]
+async fn admin_actions_appear_without_the_admins_address() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let admin_name = unique("root");
+    let admin = register(&app, &admin_name).await;
+    db::surrealdb::set_role_by_username(&admin_name, "admin").await.unwrap();
+    let token = register(&app, &unique("cem")).await;
+    let id = profile(&app, &token).await["id"].as_str().unwrap().to_string();
+
+    let req = post_request()
+        .uri(&format!("/admin/users/{}/premium/grant", id))
+        .cookie(admin)
+        .insert_header(("User-Agent", "admin-browser"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/security/history").cookie(token).to_request();
+    let events: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["action"], "admin.premium_grant");
+    assert_eq!(events[0]["by_admin"], true);
+    assert_eq!(events[0]["user_agent"], Value::Null);
+    assert_eq!(events[0]["ip"], Value::Null);
+}
+
+#[actix_web::test]
+async fn audit_events_cannot_be_rewritten() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("nobody");
+    let agent = unique("agent");
+
+    let req = post_request()
+        .uri("/login")
+        .insert_header(("User-Agent", agent.clone()))
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let updated = db::surrealdb::DB
+        .query("UPDATE audit_event SET outcome = 'success' WHERE user_agent = $agent;")
+        .bind(("agent", agent.clone()))
+        .await
+        .unwrap()
+        .check();
+    assert!(updated.is_err());
+
+    let deleted = db::surrealdb::DB
+        .query("DELETE audit_event WHERE user_agent = $agent;")
+        .bind(("agent", agent.clone()))
+        .await
+        .unwrap()
+        .check();
+    assert!(deleted.is_err());
+
+    let mut result = db::surrealdb::DB
+        .query("SELECT outcome, detail FROM audit_event WHERE user_agent = $agent;")
+        .bind(("agent", agent.clone()))
+        .await
+        .unwrap();
+    let events: Vec<Value> = result.take(0).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["outcome"], "failure");
+    assert!(!events[0]["detail"].as_str().unwrap().contains(&username));
+
+    // who and where can't be filled in or changed, only cleared
+    for set in ["ip = '10.0.0.1'", "target = user:someone"] {
+        let rewritten = db::surrealdb::DB
+            .query(format!("UPDATE audit_event SET {} WHERE user_agent = $agent;", set))
+            .bind(("agent", agent.clone()))
+            .await
+            .unwrap()
+            .check();
+        assert!(rewritten.is_err(), "{}", set);
+    }
+}
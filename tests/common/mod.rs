@@ -0,0 +1,301 @@
+#![allow(dead_code)]
+
+pub mod oidc;
+pub mod webauthn;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{test, web, Error};
+use gallery_backend::ai::classifier::{Classifier, ClassifierConfig};
+use gallery_backend::ai::image_classification::optimize;
+use gallery_backend::config::Config;
+use gallery_backend::db;
+use gallery_backend::model::app::AppData;
+use gallery_backend::service::deletion_service::{DeletionConfig, DeletionService};
+use gallery_backend::service::job_service::{JobConfig, JobService};
+use gallery_backend::service::mailer::MemoryMailer;
+use gallery_backend::service::oidc::{OidcConfig, OidcService};
+use gallery_backend::service::password_service::{PasswordConfig, PasswordService};
+use gallery_backend::service::rate_limiter::{RateLimitConfig, RateLimiter};
+use gallery_backend::AiModel;
+use image::{ImageFormat, Rgb, RgbImage};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::sync::{mpsc, Arc, Once};
+use tract_onnx::pb;
+use tract_onnx::prelude::*;
+use web3::Web3;
+
+pub const PASSWORD: &str = "correct horse battery";
+pub const CSRF_TOKEN: &str = "test-csrf-token";
+
+static INIT: Once = Once::new();
+
+/// connects the global DB to an in-memory engine on a runtime that outlives the test runtimes
+pub fn setup() -> Config {
+    INIT.call_once(|| {
+        std::env::set_var("SECRET_KEY", "test-secret");
+        std::env::set_var("DOMAIN", "localhost");
+        std::fs::create_dir_all("images").expect("images dir");
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().expect("runtime");
+            rt.block_on(async {
+                let config = Config::development();
+                db::surrealdb::connect(&config.database).await.expect("db connect");
+                tx.send(()).unwrap();
+                std::future::pending::<()>().await;
+            });
+        });
+        rx.recv().expect("db setup");
+    });
+
+    Config::development()
+}
+
+pub fn app_data() -> web::Data<AppData> {
+    app_data_with(no_rate_limits())
+}
+
+pub fn app_data_with(rate_limit: RateLimitConfig) -> web::Data<AppData> {
+    app_data_from(rate_limit, PasswordConfig::default(), MemoryMailer::new(), OidcConfig::default())
+}
+
+pub fn app_data_with_passwords(passwords: PasswordConfig) -> web::Data<AppData> {
+    app_data_from(no_rate_limits(), passwords, MemoryMailer::new(), OidcConfig::default())
+}
+
+pub fn app_data_with_mailer(mailer: MemoryMailer) -> web::Data<AppData> {
+    app_data_from(no_rate_limits(), PasswordConfig::default(), mailer, OidcConfig::default())
+}
+
+pub fn app_data_with_oidc(oidc: OidcConfig) -> web::Data<AppData> {
+    app_data_from(no_rate_limits(), PasswordConfig::default(), MemoryMailer::new(), oidc)
+}
+
+fn no_rate_limits() -> RateLimitConfig {
+    RateLimitConfig {
+        rules: vec![],
+        ..Default::default()
+    }
+}
+
+fn app_data_from(
+    rate_limit: RateLimitConfig,
+    passwords: PasswordConfig,
+    mailer: MemoryMailer,
+    oidc: OidcConfig,
+) -> web::Data<AppData> {
+    let transport = web3::transports::Http::new("http://127.0.0.1:1").expect("transport err");
+
+    web::Data::new(AppData::new(
+        Classifier::new(stand_in_model(1), ClassifierConfig::default()),
+        Web3::new(transport),
+        DeletionService::new(DeletionConfig::default()),
+        RateLimiter::new(rate_limit),
+        PasswordService::new(passwords),
+        Arc::new(mailer),
+        OidcService::new(oidc),
+    ))
+}
+
+/// GlobalAveragePool -> Flatten -> MatMul giving `[-red, red]`,
+/// so images brighter than mid-grey in the red channel are classified as nsfw
+pub fn stand_in_model(batch: usize) -> AiModel {
+    let node = |op: &str, input: &[&str], output: &str| pb::NodeProto {
+        op_type: op.to_string(),
+        input: input.iter().map(|i| i.to_string()).collect(),
+        output: vec![output.to_string()],
+        name: output.to_string(),
+        ..Default::default()
+    };
+
+    let graph = pb::GraphProto {
+        name: "stand-in".to_string(),
+        node: vec![
+            node("GlobalAveragePool", &["input"], "pooled"),
+            node("Flatten", &["pooled"], "flat"),
+            node("MatMul", &["flat", "weights"], "output"),
+        ],
+        initializer: vec![pb::TensorProto {
+            name: "weights".to_string(),
+            dims: vec![3, 2],
+            data_type: pb::tensor_proto::DataType::Float as i32,
+            float_data: vec![-1.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            ..Default::default()
+        }],
+        input: vec![pb::ValueInfoProto {
+            name: "input".to_string(),
+            r#type: Some(pb::TypeProto {
+                value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                    elem_type: pb::tensor_proto::DataType::Float as i32,
+                    shape: Some(pb::TensorShapeProto {
+                        dim: [1, 3, 224, 224]
+                            .into_iter()
+                            .map(|d| pb::tensor_shape_proto::Dimension {
+                                value: Some(pb::tensor_shape_proto::dimension::Value::DimValue(d)),
+                                ..Default::default()
+                            })
+                            .collect(),
+                    }),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }],
+        output: vec![pb::ValueInfoProto {
+            name: "output".to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let proto = pb::ModelProto {
+        ir_version: 8,
+        opset_import: vec![pb::OperatorSetIdProto {
+            domain: "".to_string(),
+            version: 13,
+        }],
+        graph: Some(graph),
+        ..Default::default()
+    };
+
+    optimize(onnx().model_for_proto_model(&proto).expect("stand-in model"), batch).expect("optimize")
+}
+
+/// runs queued jobs until `job` is done or has failed, and gives its status.
+/// other tests share the queue, so it may be one of theirs that runs it
+pub async fn finish_job<S, B>(app: &S, token: &Cookie<'static>, app_data: &web::Data<AppData>, job: &str) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let jobs = JobService::new(JobConfig::default());
+
+    for _ in 0..200 {
+        jobs.process(&app_data.classifier).await.expect("jobs");
+        let req = test::TestRequest::get().uri(&format!("/jobs/{}", job)).cookie(token.clone()).to_request();
+        let status: Value = test::call_and_read_body_json(app, req).await;
+        if status["status"] == "done" || status["status"] == "failed" {
+            return status;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+
+    panic!("job {} didn't finish", job);
+}
+
+pub fn png(color: [u8; 3]) -> Vec<u8> {
+    let img = RgbImage::from_pixel(8, 8, Rgb(color));
+    let mut buf = Cursor::new(Vec::new());
+    img.write_to(&mut buf, ImageFormat::Png).expect("png");
+
+    buf.into_inner()
+}
+
+pub fn multipart(boundary: &str, ratio: &str, file_name: &str, file: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"ratio\"\r\n\r\n{ratio}\r\n"
+    ).as_bytes());
+    body.extend_from_slice(format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: image/png\r\n\r\n"
+    ).as_bytes());
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    body
+}
+
+pub fn cookie<B>(res: &ServiceResponse<B>, name: &str) -> Option<Cookie<'static>> {
+    res.response()
+        .cookies()
+        .find(|c| c.name() == name)
+        .map(|c| c.into_owned())
+}
+
+/// unique per test run so parallel tests don't collide on the unique indexes
+pub fn unique(prefix: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos();
+
+    format!("{}{}", prefix, nanos)
+}
+
+/// the token of the last link in the last mail sent to `to`
+pub fn mailed_token(mailer: &MemoryMailer, to: &str) -> Option<String> {
+    let mail = mailer.last_to(to)?;
+    let (_, rest) = mail.body.rsplit_once("token=")?;
+
+    rest.split_whitespace().next().map(|t| t.to_string())
+}
+
+/// path and query of the last link in the last mail sent to `to`
+pub fn mailed_link(mailer: &MemoryMailer, to: &str) -> Option<String> {
+    let mail = mailer.last_to(to)?;
+    let link = mail.body.split_whitespace().rfind(|word| word.contains("://"))?;
+    let (_, rest) = link.split_once("://")?;
+
+    rest.find('/').map(|path| rest[path..].to_string())
+}
+
+/// a POST with the csrf cookie and the header the frontend copies it into
+pub fn post_request() -> test::TestRequest {
+    test::TestRequest::post()
+        .cookie(Cookie::new("csrf", CSRF_TOKEN))
+        .insert_header(("X-CSRF-Token", CSRF_TOKEN))
+}
+
+pub async fn register<S, B>(app: &S, username: &str) -> Cookie<'static>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = post_request()
+        .uri("/register")
+        .set_json(json!({
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": PASSWORD,
+        }))
+        .to_request();
+    let res = test::call_service(app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    cookie(&res, "token").expect("token cookie")
+}
+
+pub async fn profile<S, B>(app: &S, token: &Cookie<'static>) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get()
+        .uri("/profile")
+        .cookie(token.clone())
+        .to_request();
+
+    test::call_and_read_body_json(app, req).await
+}
+
+pub async fn status<S, B>(app: &S, token: &Cookie<'static>) -> StatusCode
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get()
+        .uri("/profile")
+        .cookie(token.clone())
+        .to_request();
+
+    match test::try_call_service(app, req).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
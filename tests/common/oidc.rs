@@ -0,0 +1,210 @@
+use actix_web::{get, post, web, App, HttpResponse, HttpServer};
+use data_encoding::BASE64URL_NOPAD;
+use gallery_backend::utils::oidc::pkce_challenge;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// an RS256 and an ES256 signing key with their jwks
+pub struct Keys {
+    pub rsa: PKey<Private>,
+    pub ec: PKey<Private>,
+}
+
+impl Keys {
+    pub fn new() -> Self {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+
+        Self {
+            rsa: PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(),
+            ec: PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap(),
+        }
+    }
+
+    pub fn jwks(&self) -> Value {
+        let rsa = self.rsa.rsa().unwrap();
+        let ec = self.ec.ec_key().unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        ec.public_key().affine_coordinates(ec.group(), &mut x, &mut y, &mut ctx).unwrap();
+
+        json!({
+            "keys": [
+                {
+                    "kty": "RSA", "kid": "rsa-1", "alg": "RS256", "use": "sig",
+                    "n": b64(&rsa.n().to_vec()), "e": b64(&rsa.e().to_vec()),
+                },
+                {
+                    "kty": "EC", "kid": "ec-1", "alg": "ES256", "use": "sig", "crv": "P-256",
+                    "x": b64(&x.to_vec_padded(32).unwrap()), "y": b64(&y.to_vec_padded(32).unwrap()),
+                },
+            ]
+        })
+    }
+
+    pub fn sign_rs256(&self, claims: &Value) -> String {
+        let input = signing_input("RS256", "rsa-1", claims);
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.rsa).unwrap();
+        signer.update(input.as_bytes()).unwrap();
+
+        format!("{}.{}", input, b64(&signer.sign_to_vec().unwrap()))
+    }
+
+    pub fn sign_es256(&self, claims: &Value) -> String {
+        let input = signing_input("ES256", "ec-1", claims);
+        let digest = openssl::sha::sha256(input.as_bytes());
+        let signature = EcdsaSig::sign(&digest, &self.ec.ec_key().unwrap()).unwrap();
+        let mut raw = signature.r().to_vec_padded(32).unwrap();
+        raw.extend(signature.s().to_vec_padded(32).unwrap());
+
+        format!("{}.{}", input, b64(&raw))
+    }
+}
+
+fn signing_input(alg: &str, kid: &str, claims: &Value) -> String {
+    let header = json!({ "alg": alg, "kid": kid, "typ": "JWT" });
+
+    format!("{}.{}", b64(header.to_string().as_bytes()), b64(claims.to_string().as_bytes()))
+}
+
+pub fn b64(bytes: &[u8]) -> String {
+    BASE64URL_NOPAD.encode(bytes)
+}
+
+struct Grant {
+    code_challenge: String,
+    claims: Value,
+}
+
+struct Issuer {
+    url: String,
+    keys: Keys,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+/// an OpenID provider on a local port: discovery, jwks and a token endpoint
+/// that checks PKCE. the test plays the browser and calls [`MockIssuer::authorize`]
+/// instead of going through a login page
+pub struct MockIssuer {
+    pub url: String,
+    inner: Arc<Issuer>,
+}
+
+impl MockIssuer {
+    pub async fn start(client_id: &str) -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let inner = Arc::new(Issuer {
+            url: url.clone(),
+            keys: Keys::new(),
+            grants: Mutex::new(HashMap::new()),
+        });
+
+        let data = web::Data::from(inner.clone());
+        let client_id = client_id.to_string();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .app_data(web::Data::new(client_id.clone()))
+                .service(discovery)
+                .service(jwks)
+                .service(token)
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        Self { url, inner }
+    }
+
+    /// approves the authorization request in `location`, returning the
+    /// state and the code the provider would redirect back with.
+    /// `claims` are added to the id token, `nonce` is taken from the request
+    pub fn authorize(&self, location: &str, claims: Value) -> (String, String) {
+        let query: HashMap<String, String> = reqwest::Url::parse(location)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["code_challenge_method"], "S256");
+
+        let now = chrono::Utc::now().timestamp();
+        let mut id_token = json!({
+            "iss": self.url,
+            "aud": query["client_id"],
+            "iat": now,
+            "exp": now + 300,
+            "nonce": query["nonce"],
+        });
+        for (k, v) in claims.as_object().unwrap() {
+            id_token[k] = v.clone();
+        }
+
+        let code = b64(&openssl::sha::sha256(query["state"].as_bytes()));
+        self.inner.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                code_challenge: query["code_challenge"].clone(),
+                claims: id_token,
+            },
+        );
+
+        (query["state"].clone(), code)
+    }
+}
+
+#[get("/.well-known/openid-configuration")]
+async fn discovery(issuer: web::Data<Issuer>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer.url,
+        "authorization_endpoint": format!("{}/authorize", issuer.url),
+        "token_endpoint": format!("{}/token", issuer.url),
+        "jwks_uri": format!("{}/jwks", issuer.url),
+    }))
+}
+
+#[get("/jwks")]
+async fn jwks(issuer: web::Data<Issuer>) -> HttpResponse {
+    HttpResponse::Ok().json(issuer.keys.jwks())
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    code: String,
+    client_id: String,
+    code_verifier: String,
+}
+
+#[post("/token")]
+async fn token(issuer: web::Data<Issuer>, client_id: web::Data<String>, form: web::Form<TokenForm>) -> HttpResponse {
+    let grant = issuer.grants.lock().unwrap().remove(&form.code);
+
+    match grant {
+        Some(grant)
+            if form.grant_type == "authorization_code"
+                && form.client_id == *client_id.get_ref()
+                && pkce_challenge(&form.code_verifier) == grant.code_challenge =>
+        {
+            HttpResponse::Ok().json(json!({
+                "access_token": "unused",
+                "token_type": "Bearer",
+                "id_token": issuer.keys.sign_rs256(&grant.claims),
+            }))
+        }
+        _ => HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+    }
+}
@@ -0,0 +1,152 @@
+use ciborium::Value as Cbor;
+use gallery_backend::utils::webauthn::{decode, encode};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use serde_json::{json, Value};
+
+/// a software authenticator with one P-256 passkey, enough to drive both ceremonies
+pub struct Authenticator {
+    pub key: PKey<Private>,
+    pub credential_id: Vec<u8>,
+    pub sign_count: u32,
+    pub rp_id: String,
+    pub origin: String,
+    pub attestation: &'static str,
+}
+
+impl Authenticator {
+    pub fn new() -> Self {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let mut credential_id = vec![0u8; 32];
+        openssl::rand::rand_bytes(&mut credential_id).unwrap();
+
+        Self {
+            key: PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap(),
+            credential_id,
+            sign_count: 0,
+            rp_id: "localhost".to_string(),
+            origin: "https://localhost".to_string(),
+            attestation: "packed",
+        }
+    }
+
+    pub fn credential_id(&self) -> String {
+        encode(&self.credential_id)
+    }
+
+    fn cose_key(&self) -> Cbor {
+        let ec = self.key.ec_key().unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        ec.public_key().affine_coordinates(ec.group(), &mut x, &mut y, &mut ctx).unwrap();
+
+        Cbor::Map(vec![
+            (Cbor::from(1), Cbor::from(2)),
+            (Cbor::from(3), Cbor::from(-7)),
+            (Cbor::from(-1), Cbor::from(1)),
+            (Cbor::from(-2), Cbor::Bytes(x.to_vec_padded(32).unwrap())),
+            (Cbor::from(-3), Cbor::Bytes(y.to_vec_padded(32).unwrap())),
+        ])
+    }
+
+    fn client_data(&self, typ: &str, challenge: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "type": typ,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        }))
+        .unwrap()
+    }
+
+    fn auth_data(&self, flags: u8, attested: bool) -> Vec<u8> {
+        let mut data = openssl::sha::sha256(self.rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend(self.sign_count.to_be_bytes());
+
+        if attested {
+            data.extend([0u8; 16]);
+            data.extend((self.credential_id.len() as u16).to_be_bytes());
+            data.extend(&self.credential_id);
+            ciborium::ser::into_writer(&self.cose_key(), &mut data).unwrap();
+        }
+
+        data
+    }
+
+    fn sign(&self, auth_data: &[u8], client_data: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+        signer.update(auth_data).unwrap();
+        signer.update(&openssl::sha::sha256(client_data)).unwrap();
+
+        signer.sign_to_vec().unwrap()
+    }
+
+    /// answers the options of `/webauthn/register/start` with a `RegisterFinishForm` body
+    pub fn register(&mut self, options: &Value) -> Value {
+        let client_data = self.client_data("webauthn.create", options["challenge"].as_str().unwrap());
+        let auth_data = self.auth_data(0x45, true);
+
+        let statement = match self.attestation {
+            "packed" => Cbor::Map(vec![
+                (Cbor::from("alg"), Cbor::from(-7)),
+                (Cbor::from("sig"), Cbor::Bytes(self.sign(&auth_data, &client_data))),
+            ]),
+            _ => Cbor::Map(vec![]),
+        };
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(
+            &Cbor::Map(vec![
+                (Cbor::from("fmt"), Cbor::from(self.attestation)),
+                (Cbor::from("attStmt"), statement),
+                (Cbor::from("authData"), Cbor::Bytes(auth_data)),
+            ]),
+            &mut attestation_object,
+        )
+        .unwrap();
+
+        json!({
+            "credential": {
+                "id": self.credential_id(),
+                "rawId": self.credential_id(),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": encode(&client_data),
+                    "attestationObject": encode(&attestation_object),
+                },
+            },
+            "name": "test key",
+        })
+    }
+
+    /// answers the options of `/webauthn/login/start`, `user_id` is the user handle
+    pub fn login(&mut self, options: &Value, user_id: Option<&str>) -> Value {
+        self.sign_count += 1;
+
+        let client_data = self.client_data("webauthn.get", options["challenge"].as_str().unwrap());
+        let auth_data = self.auth_data(0x05, false);
+        let signature = self.sign(&auth_data, &client_data);
+
+        json!({
+            "id": self.credential_id(),
+            "rawId": self.credential_id(),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": encode(&client_data),
+                "authenticatorData": encode(&auth_data),
+                "signature": encode(&signature),
+                "userHandle": user_id.map(|u| encode(u.as_bytes())),
+            },
+        })
+    }
+}
+
+/// the user id the server put into the creation options
+pub fn user_handle(options: &Value) -> String {
+    String::from_utf8(decode(options["user"]["id"].as_str().unwrap()).unwrap()).unwrap()
+}
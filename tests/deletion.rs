@@ -0,0 +1,222 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{setup, unique};
+use gallery_backend::db;
+use gallery_backend::model::audit::NewAuditEvent;
+use gallery_backend::service::deletion_service::{DeletionConfig, DeletionService, Processed};
+use gallery_backend::utils::clock::FakeClock;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// the tests share one database, and a run of the job processes every due
+/// request in it, so they take turns
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+async fn user() -> String {
+    let username = unique("silinecek");
+
+    db::surrealdb::register(&username, &format!("{}@example.com", username), &"hash".to_string())
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn requests_outlive_the_service() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let user_id = user().await;
+
+    DeletionService::new(DeletionConfig::default()).delete(&user_id).await.unwrap();
+
+    // a restarted process has a new service and nothing in memory
+    let restarted = DeletionService::new(DeletionConfig::default());
+    let scheduled = restarted.scheduled(&user_id).await.unwrap().expect("pending deletion");
+    assert!(scheduled > Utc::now() + Duration::days(29));
+    assert!(restarted.delete(&user_id).await.is_err());
+    assert!(restarted.pending().await.unwrap().iter().any(|r| r.user_id == user_id));
+
+    restarted.cancel(&user_id).await.unwrap();
+    assert!(restarted.scheduled(&user_id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn import_keeps_requests_already_in_the_database() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let (kept, imported) = (user().await, user().await);
+    let service = DeletionService::new(DeletionConfig::default());
+    service.delete(&kept).await.unwrap();
+    let original = service.scheduled(&kept).await.unwrap().unwrap();
+
+    let at = Utc::now() + Duration::days(3);
+    service
+        .import(HashMap::from([(kept.clone(), at), (imported.clone(), at)]))
+        .await
+        .unwrap();
+
+    assert_eq!(service.scheduled(&kept).await.unwrap(), Some(original));
+    assert_eq!(
+        service.scheduled(&imported).await.unwrap().map(|s| s.timestamp()),
+        Some(at.timestamp())
+    );
+}
+
+#[actix_web::test]
+async fn a_due_request_is_claimed_by_one_instance() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let (due, later) = (user().await, user().await);
+    let service = DeletionService::new(DeletionConfig::default());
+    service
+        .import(HashMap::from([
+            (due.clone(), Utc::now() - Duration::minutes(1)),
+            (later.clone(), Utc::now() + Duration::days(1)),
+        ]))
+        .await
+        .unwrap();
+
+    let now = Utc::now();
+    let first = db::surrealdb::deletion_requests_claim("instance-a", &now, 60).await.unwrap();
+    let second = db::surrealdb::deletion_requests_claim("instance-b", &now, 60).await.unwrap();
+
+    assert!(first.iter().any(|r| r.user_id == due));
+    assert!(!first.iter().any(|r| r.user_id == later));
+    assert!(!second.iter().any(|r| r.user_id == due));
+}
+
+fn service(clock: &FakeClock) -> DeletionService {
+    DeletionService::new(DeletionConfig::default()).with_clock(Arc::new(clock.clone()))
+}
+
+async fn exists(user_id: &String) -> bool {
+    db::surrealdb::user_exists(user_id).await.unwrap()
+}
+
+#[actix_web::test]
+async fn accounts_are_deleted_once_the_grace_period_is_over() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let user_id = user().await;
+    let clock = FakeClock::new(Utc::now());
+    let service = service(&clock);
+    service.delete(&user_id).await.unwrap();
+
+    service.process().await.unwrap();
+    assert!(exists(&user_id).await);
+
+    clock.advance(Duration::days(29));
+    service.process().await.unwrap();
+    assert!(exists(&user_id).await);
+
+    clock.advance(Duration::days(1));
+    let processed = service.process().await.unwrap();
+    assert!(processed.deleted >= 1);
+    assert!(!exists(&user_id).await);
+    assert!(service.scheduled(&user_id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn a_failed_deletion_is_retried_with_backoff() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let user_id = user().await;
+    // a directory where the image should be can't be removed like a file
+    let image = format!("{}.png", unique("engel"));
+    std::fs::create_dir_all(format!("images/{}", image)).unwrap();
+    db::surrealdb::post_add("1".to_string(), &image, &user_id, "ready").await.unwrap();
+
+    let clock = FakeClock::new(Utc::now());
+    let service = service(&clock);
+    service.delete(&user_id).await.unwrap();
+    clock.advance(Duration::days(30));
+
+    let processed = service.process().await.unwrap();
+    assert_eq!(processed, Processed { deleted: processed.deleted, failed: 1 });
+    assert!(exists(&user_id).await);
+    let request = db::surrealdb::deletion_request(&user_id).await.unwrap().unwrap();
+    assert_eq!(request.attempts, 1);
+    assert!(request.last_error.unwrap().contains(&image));
+
+    // not again before the backoff is over, even though it's due
+    std::fs::remove_dir(format!("images/{}", image)).unwrap();
+    clock.advance(Duration::minutes(4));
+    assert_eq!(service.process().await.unwrap(), Processed::default());
+    assert!(exists(&user_id).await);
+
+    clock.advance(Duration::minutes(1));
+    assert_eq!(service.process().await.unwrap(), Processed { deleted: 1, failed: 0 });
+    assert!(!exists(&user_id).await);
+}
+
+#[actix_web::test]
+async fn erasure_removes_everything_and_reports_it() {
+    setup();
+    let _serial = SERIAL.lock().await;
+    let (user_id, friend_id) = (user().await, user().await);
+    let image = format!("{}.png", unique("resim"));
+    std::fs::write(format!("images/{}", image), b"png").unwrap();
+    db::surrealdb::post_add("1".to_string(), &image, &user_id, "ready").await.unwrap();
+    db::surrealdb::follow(&user_id, &friend_id.trim_start_matches("user:").to_string()).await.unwrap();
+    db::surrealdb::session_create(&user_id, &unique("sid"), "phone", "127.0.0.1", "test").await.unwrap();
+    let transaction = unique("0xabc");
+    db::surrealdb::add_transaction(&user_id, &transaction).await.unwrap();
+    let admin_id = user().await;
+    let detail = unique("erased");
+    for (actor, ip) in [(&user_id, "10.9.0.1"), (&admin_id, "10.9.0.2")] {
+        let event = NewAuditEvent {
+            ip: Some(ip.to_string()),
+            user_agent: Some("agent".to_string()),
+            ..NewAuditEvent::new("login").actor(actor).target(&user_id).detail(detail.clone())
+        };
+        db::surrealdb::audit_add(&event).await.unwrap();
+    }
+
+    let service = DeletionService::new(DeletionConfig::default());
+    let report = service.erase(&user_id).await.unwrap();
+
+    assert!(report.verified);
+    assert!(report.remaining.is_empty());
+    assert!(report.completed_at.is_some());
+    let count = |data: &str| report.steps.iter().find(|s| s.data == data).unwrap().count;
+    assert_eq!(
+        [count("posts"), count("images"), count("friends"), count("sessions"), count("payments"), count("account")],
+        [1, 1, 1, 1, 1, 1]
+    );
+    assert!(!std::path::Path::new(&format!("images/{}", image)).exists());
+    assert!(!exists(&user_id).await);
+    assert!(exists(&friend_id).await);
+
+    // the payment stays for the books, without saying whose it was
+    let mut result = db::surrealdb::DB
+        .query("SELECT VALUE user_id FROM payment WHERE transaction = $transaction;")
+        .bind(("transaction", transaction))
+        .await
+        .unwrap();
+    let owners: Vec<Option<String>> = result.take(0).unwrap();
+    assert_eq!(owners, [None]);
+
+    // the events stay, without who it was or where they were
+    let mut result = db::surrealdb::DB
+        .query("SELECT IF actor THEN type::string(actor) END AS actor, target, ip, user_agent FROM audit_event WHERE detail = $detail;")
+        .bind(("detail", detail))
+        .await
+        .unwrap();
+    let events: Vec<Value> = result.take(0).unwrap();
+    assert_eq!(events.len(), 2);
+    let by_user = events.iter().find(|e| e["actor"].is_null()).unwrap();
+    assert!(by_user["target"].is_null() && by_user["ip"].is_null() && by_user["user_agent"].is_null());
+    let by_admin = events.iter().find(|e| e["actor"] == admin_id.as_str()).unwrap();
+    assert!(by_admin["target"].is_null());
+    assert_eq!(by_admin["ip"], "10.9.0.2");
+    assert_eq!(report.steps.iter().find(|s| s.data == "audit log").unwrap().count, 2);
+
+    // running it again finds nothing more and keeps what the first run removed
+    let again = service.erase(&user_id).await.unwrap();
+    assert!(again.verified);
+    assert_eq!(again.steps, report.steps);
+    let saved = db::surrealdb::erasure_report(&user_id).await.unwrap().unwrap();
+    assert_eq!(saved.steps, report.steps);
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{app_data_with_mailer, mailed_link, mailed_token, post_request, profile, register, setup, status, unique, PASSWORD};
+use gallery_backend::build_app;
+use gallery_backend::service::mailer::{self, MemoryMailer};
+use serde_json::json;
+
+#[actix_web::test]
+async fn register_sends_single_use_verification() {
+    let config = setup();
+    let mailer = MemoryMailer::new();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(mailer.clone()))).await;
+    let username = unique("trent");
+    let email = format!("{}@example.com", username);
+
+    let token = register(&app, &username).await;
+    assert_eq!(profile(&app, &token).await["email_verified"], false);
+
+    let req = post_request()
+        .uri("/payment")
+        .cookie(token.clone())
+        .set_payload(format!("0x{}", "ab".repeat(32)))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let verification = mailed_token(&mailer, &email).expect("verification mail");
+
+    let req = post_request()
+        .uri("/verify_email")
+        .set_json(json!({"token": verification}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(profile(&app, &token).await["email_verified"], true);
+
+    let req = post_request()
+        .uri("/verify_email")
+        .set_json(json!({"token": verification}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = post_request()
+        .uri("/verify_email/send")
+        .cookie(token)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn resending_verification_replaces_the_old_link() {
+    let config = setup();
+    let mailer = MemoryMailer::new();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(mailer.clone()))).await;
+    let username = unique("uma");
+    let email = format!("{}@example.com", username);
+
+    let token = register(&app, &username).await;
+    let first = mailed_token(&mailer, &email).unwrap();
+
+    let req = post_request()
+        .uri("/verify_email/send")
+        .cookie(token)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let second = mailed_token(&mailer, &email).unwrap();
+
+    for (verification, status) in [(first, StatusCode::BAD_REQUEST), (second, StatusCode::OK)] {
+        let req = post_request()
+            .uri("/verify_email")
+            .set_json(json!({"token": verification}))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), status);
+    }
+}
+
+#[actix_web::test]
+async fn password_reset_changes_password_and_ends_sessions() {
+    let config = setup();
+    let mailer = MemoryMailer::new();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(mailer.clone()))).await;
+    let username = unique("victor");
+    let email = format!("{}@example.com", username);
+    let token = register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/password_reset/request")
+        .set_json(json!({"email": format!("nobody-{}", email)}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(mailer.last_to(&format!("nobody-{}", email)).is_none());
+
+    let req = post_request()
+        .uri("/password_reset/request")
+        .set_json(json!({"email": email}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let reset = mailer
+        .last_to(&email)
+        .filter(|m| m.subject == "Reset your password")
+        .and_then(|_| mailed_token(&mailer, &email))
+        .expect("reset mail");
+
+    let req = post_request()
+        .uri("/password_reset")
+        .set_json(json!({"token": reset, "password": "weakpassword"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let new_password = "A brand new horse";
+    let req = post_request()
+        .uri("/password_reset")
+        .set_json(json!({"token": reset, "password": new_password}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/password_reset")
+        .set_json(json!({"token": reset, "password": "Yet another horse"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(status(&app, &token).await, StatusCode::UNAUTHORIZED);
+
+    for (password, status) in [(PASSWORD, StatusCode::UNAUTHORIZED), (new_password, StatusCode::OK)] {
+        let req = post_request()
+            .uri("/login")
+            .set_json(json!({"username": username, "password": password}))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), status);
+    }
+}
+
+#[actix_web::test]
+async fn mailed_links_open_in_a_browser() {
+    let config = setup();
+    let mailer = MemoryMailer::new();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(mailer.clone()))).await;
+    let username = unique("wendy");
+    let email = format!("{}@example.com", username);
+    let token = register(&app, &username).await;
+
+    let link = mailed_link(&mailer, &email).expect("verification link");
+    let req = test::TestRequest::get().uri(&link).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(profile(&app, &token).await["email_verified"], true);
+
+    let req = post_request()
+        .uri("/password_reset/request")
+        .set_json(json!({"email": email}))
+        .to_request();
+    test::call_service(&app, req).await;
+    let link = mailed_link(&mailer, &email).expect("reset link");
+    let req = test::TestRequest::get().uri(&link).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let page = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(page.contains(r#"action="/password_reset""#));
+
+    // the form, sent by a browser that's still logged in, without the csrf header
+    let reset = mailed_token(&mailer, &email).unwrap();
+    let new_password = "A fresh horse battery";
+    let req = test::TestRequest::post()
+        .uri("/password_reset")
+        .cookie(token)
+        .set_form([("token", reset.as_str()), ("password", new_password)])
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": new_password}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/password_reset?token=forged").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+#[should_panic(expected = "SMTP_HOST")]
+async fn production_needs_a_mail_server_or_an_explicit_file_mailer() {
+    mailer::from_env(true);
+}
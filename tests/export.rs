@@ -0,0 +1,89 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::{Duration, Utc};
+use common::{app_data_with_mailer, png, post_request, profile, register, setup, unique};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::service::export_service::ExportService;
+use gallery_backend::service::mailer::MemoryMailer;
+use serde_json::Value;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+#[actix_web::test]
+async fn export_is_mailed_and_downloaded_until_it_expires() {
+    let config = setup();
+    let mailer = MemoryMailer::new();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(mailer.clone()))).await;
+    let username = unique("arsiv");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let image = format!("{}.png", unique("foto"));
+    let content = png([0, 0, 64]);
+    std::fs::write(format!("images/{}", image), &content).unwrap();
+    db::surrealdb::post_add("1".to_string(), &image, &user_id, "ready").await.unwrap();
+    db::surrealdb::add_transaction(&user_id, &unique("0x")).await.unwrap();
+
+    let req = post_request().uri("/export").cookie(token.clone()).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        let req = test::TestRequest::get().uri("/export").cookie(token.clone()).to_request();
+        status = test::call_and_read_body_json(&app, req).await;
+        if status["status"] != "pending" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(status["status"], "ready");
+    assert!(status["size"].as_i64().unwrap() > content.len() as i64);
+
+    let req = post_request().uri("/export").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+    let mail = mailer.last_to(&format!("{}@example.com", username)).expect("export mail");
+    let link = mail.body.split_whitespace().find(|w| w.contains("/export/download?token=")).unwrap();
+    let download_token = link.split("token=").nth(1).unwrap().to_string();
+    let download = |token: &str| test::TestRequest::get().uri(&format!("/export/download?token={}", token)).to_request();
+
+    let res = test::call_service(&app, download(&download_token)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let disposition = res.headers().get("content-disposition").unwrap().to_str().unwrap().to_string();
+    assert!(disposition.starts_with("attachment"));
+    let body = test::read_body(res).await;
+
+    let mut archive = ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+    let manifest: Value = serde_json::from_reader(archive.by_name("manifest.json").unwrap()).unwrap();
+    assert_eq!(manifest["username"], username.as_str());
+    assert_eq!(manifest["posts"][0]["image"], image.as_str());
+    assert_eq!(manifest["payments"].as_array().unwrap().len(), 1);
+    let mut stored = Vec::new();
+    archive.by_name(&format!("images/{}", image)).unwrap().read_to_end(&mut stored).unwrap();
+    assert_eq!(stored, content);
+
+    let res = test::call_service(&app, download(&format!("{}x", download_token))).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let exports = ExportService::new(&config.export_dir);
+    assert!(exports.purge(&(Utc::now() + Duration::days(8))).await.unwrap() >= 1);
+    let res = test::call_service(&app, download(&download_token)).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let req = post_request().uri("/export").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::ACCEPTED);
+}
+
+#[actix_web::test]
+async fn only_one_export_is_started_when_asked_twice_at_once() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data_with_mailer(MemoryMailer::new()))).await;
+    let token = register(&app, &unique("ikiz")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+
+    let (a, b) = tokio::join!(db::surrealdb::export_create(&user_id), db::surrealdb::export_create(&user_id));
+    assert_eq!([a.unwrap(), b.unwrap()].iter().filter(|id| id.is_some()).count(), 1);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use actix_web::http::header::{
+    HeaderMap, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_MAX_AGE, CONTENT_SECURITY_POLICY, STRICT_TRANSPORT_SECURITY,
+};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{app_data, setup};
+use gallery_backend::build_app;
+use gallery_backend::utils::csp::Csp;
+use serde_json::json;
+
+#[actix_web::test]
+async fn preflight_is_answered_for_configured_origins() {
+    let mut config = setup();
+    config.cors.origins = vec!["https://app.example".to_string()];
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let preflight = |origin: &str| {
+        test::TestRequest::default()
+            .method(actix_web::http::Method::OPTIONS)
+            .uri("/login")
+            .insert_header(("Origin", origin))
+            .insert_header(("Access-Control-Request-Method", "POST"))
+            .insert_header(("Access-Control-Request-Headers", "content-type, x-csrf-token"))
+            .to_request()
+    };
+
+    let res = test::call_service(&app, preflight("https://app.example")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let headers = res.headers();
+    assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://app.example");
+    assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+    assert_eq!(headers.get(ACCESS_CONTROL_MAX_AGE).unwrap(), "3600");
+    let allowed = headers.get(ACCESS_CONTROL_ALLOW_HEADERS).unwrap().to_str().unwrap();
+    assert!(allowed.contains("x-csrf-token"), "{}", allowed);
+
+    let res = test::call_service(&app, preflight("https://evil.example")).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+    // a simple request from an unknown origin goes through but the browser won't share the answer
+    let req = test::TestRequest::get()
+        .uri("/premium")
+        .insert_header(("Origin", "https://evil.example"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+}
+
+#[actix_web::test]
+async fn files_get_a_stricter_policy_than_the_app() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let csp = |headers: &HeaderMap| headers.get(CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap().to_string();
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/premium").to_request()).await;
+    let app_policy = csp(res.headers());
+    let res = test::call_service(&app, test::TestRequest::get().uri("/file/missing.png").to_request()).await;
+    let file_policy = csp(res.headers());
+
+    for policy in [&app_policy, &file_policy] {
+        assert!(!policy.contains(";;") && !policy.contains("; ;") && !policy.ends_with(';'), "{}", policy);
+        assert!(policy.contains("report-uri /csp-report; report-to csp-endpoint"), "{}", policy);
+    }
+    assert!(app_policy.contains("media-src 'self' blob:; worker-src 'self' blob:"), "{}", app_policy);
+    assert!(app_policy.starts_with("default-src 'self'"), "{}", app_policy);
+    assert!(file_policy.starts_with("default-src 'none'"), "{}", file_policy);
+    assert!(file_policy.contains("; sandbox;"), "{}", file_policy);
+
+    assert_eq!(res.headers().get("reporting-endpoints").unwrap(), "csp-endpoint=\"/csp-report\"");
+    assert_eq!(res.headers().get("x-content-type-options").unwrap(), "nosniff");
+    // plain http in development
+    assert!(res.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+}
+
+#[actix_web::test]
+async fn hsts_is_sent_when_configured() {
+    let mut config = setup();
+    config.headers.hsts_max_age = Some(600);
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let res = test::call_service(&app, test::TestRequest::get().uri("/premium").to_request()).await;
+    assert_eq!(
+        res.headers().get(STRICT_TRANSPORT_SECURITY).unwrap(),
+        "max-age=600; includeSubDomains"
+    );
+}
+
+#[actix_web::test]
+async fn violation_reports_are_accepted_without_a_session() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+
+    let legacy = json!({"csp-report": {
+        "document-uri": "https://localhost/",
+        "violated-directive": "script-src",
+        "blocked-uri": "https://cdn.example/x.js",
+    }});
+    let req = test::TestRequest::post()
+        .uri("/csp-report")
+        .insert_header(("Content-Type", "application/csp-report"))
+        .set_payload(legacy.to_string())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+    let reports = json!([{"type": "csp-violation", "body": {
+        "documentURL": "https://localhost/",
+        "effectiveDirective": "img-src",
+        "blockedURL": "https://cdn.example/x.png",
+    }}]);
+    let req = test::TestRequest::post()
+        .uri("/csp-report")
+        .insert_header(("Content-Type", "application/reports+json"))
+        .cookie(actix_web::cookie::Cookie::new("token", "stale"))
+        .set_payload(reports.to_string())
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::post().uri("/csp-report").set_payload("{").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn policies_are_built_without_stray_separators() {
+    let csp = Csp::new()
+        .directive("default-src", &["'none'"])
+        .directive("img-src", &["'self'"])
+        .directive("img-src", &["'self'", "data:"])
+        .directive("sandbox", &[]);
+
+    assert_eq!(csp.to_string(), "default-src 'none'; img-src 'self' data:; sandbox");
+    assert_eq!(csp.get("img-src").unwrap(), ["'self'", "data:"]);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::oidc::Keys;
+use gallery_backend::utils::oidc::{pkce_challenge, verify_id_token, Expected, Jwks, OidcError};
+use serde_json::{json, Value};
+
+const ISSUER: &str = "https://issuer.example";
+const NOW: i64 = 1_700_000_000;
+
+fn expected() -> Expected<'static> {
+    Expected {
+        issuer: ISSUER,
+        client_id: "gallery",
+        nonce: "n-0S6_WzA2Mj",
+        now: NOW,
+    }
+}
+
+fn base_claims() -> Value {
+    json!({
+        "iss": ISSUER,
+        "sub": "248289761001",
+        "aud": "gallery",
+        "iat": NOW - 10,
+        "exp": NOW + 300,
+        "nonce": "n-0S6_WzA2Mj",
+        "email": "jane@example.com",
+        "email_verified": true,
+    })
+}
+
+fn with(change: &[(&str, Value)]) -> Value {
+    let mut claims = base_claims();
+    for (k, v) in change {
+        claims[*k] = v.clone();
+    }
+
+    claims
+}
+
+#[test]
+fn accepts_rs256_and_es256() {
+    let keys = Keys::new();
+    let jwks: Jwks = serde_json::from_value(keys.jwks()).unwrap();
+
+    let claims = verify_id_token(&keys.sign_rs256(&base_claims()), &jwks, &expected()).unwrap();
+    assert_eq!(claims.sub, "248289761001");
+    assert_eq!(claims.email.as_deref(), Some("jane@example.com"));
+
+    let claims = verify_id_token(&keys.sign_es256(&base_claims()), &jwks, &expected()).unwrap();
+    assert_eq!(claims.email_verified, Some(true));
+}
+
+#[test]
+fn rejects_bad_tokens() {
+    let keys = Keys::new();
+    let jwks: Jwks = serde_json::from_value(keys.jwks()).unwrap();
+    let verify = |claims: &Value| verify_id_token(&keys.sign_rs256(claims), &jwks, &expected());
+
+    assert_eq!(verify(&with(&[("iss", json!("https://evil.example"))])).unwrap_err(), OidcError::WrongIssuer);
+    assert_eq!(verify(&with(&[("aud", json!("other"))])).unwrap_err(), OidcError::WrongAudience);
+    assert_eq!(verify(&with(&[("aud", json!(["gallery", "other"]))])).unwrap_err(), OidcError::WrongAudience);
+    assert!(verify(&with(&[("aud", json!(["gallery", "other"])), ("azp", json!("gallery"))])).is_ok());
+    assert_eq!(verify(&with(&[("exp", json!(NOW - 120))])).unwrap_err(), OidcError::Expired);
+    assert_eq!(verify(&with(&[("nonce", json!("replayed"))])).unwrap_err(), OidcError::WrongNonce);
+
+    let mut claims = base_claims();
+    claims.as_object_mut().unwrap().remove("nonce");
+    assert_eq!(verify(&claims).unwrap_err(), OidcError::WrongNonce);
+
+    // payload swapped after signing
+    let token = keys.sign_rs256(&base_claims());
+    let parts: Vec<&str> = token.split('.').collect();
+    let forged = common::oidc::b64(with(&[("sub", json!("admin"))]).to_string().as_bytes());
+    let tampered = format!("{}.{}.{}", parts[0], forged, parts[2]);
+    assert_eq!(verify_id_token(&tampered, &jwks, &expected()).unwrap_err(), OidcError::Signature);
+
+    // signed by a key the provider doesn't publish
+    let other: Jwks = serde_json::from_value(Keys::new().jwks()).unwrap();
+    assert_eq!(
+        verify_id_token(&keys.sign_rs256(&base_claims()), &other, &expected()).unwrap_err(),
+        OidcError::Signature
+    );
+
+    let unsigned = format!("{}.{}.", common::oidc::b64(br#"{"alg":"none"}"#), parts[1]);
+    assert_eq!(verify_id_token(&unsigned, &jwks, &expected()).unwrap_err(), OidcError::UnsupportedAlgorithm);
+}
+
+/// RFC 7636 appendix B
+#[test]
+fn pkce_matches_rfc_example() {
+    assert_eq!(
+        pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{app_data, multipart, png, post_request, profile, register, setup, unique};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::service::images::MAX_IMAGE_BYTES;
+use serde_json::{json, Value};
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+fn archive(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+
+    zip.finish().unwrap().into_inner()
+}
+
+#[actix_web::test]
+async fn takeout_archive_is_imported_through_the_upload_checks() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("aktarim");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+
+    // trailing bytes keep the hashes apart from images earlier runs left behind
+    let image = |color: [u8; 3]| [png(color), username.as_bytes().to_vec()].concat();
+    let (older, newer) = (image([0, 0, 10]), image([0, 0, 20]));
+    let sidecar = |title: &str, at: i64| {
+        serde_json::to_vec(&json!({"title": title, "photoTakenTime": {"timestamp": at.to_string()}})).unwrap()
+    };
+    let zip = archive(&[
+        ("Takeout/Photos/newer.png", newer.clone()),
+        ("Takeout/Photos/newer.png.json", sidecar("newer.png", 1_700_000_000)),
+        ("Takeout/Photos/older.png", older.clone()),
+        ("Takeout/Photos/older.png.supplemental-metadata.json", sidecar("older.png", 1_600_000_000)),
+        ("Takeout/Photos/copy.png", older.clone()),
+        ("Takeout/Photos/red.png", image([255, 0, 0])),
+        ("Takeout/Photos/notes.txt", b"not an image".to_vec()),
+    ]);
+
+    let boundary = "import-boundary";
+    let request = || {
+        post_request()
+            .uri("/import")
+            .cookie(token.clone())
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(multipart(boundary, "1", "takeout.zip", &zip))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, request()).await.status(), StatusCode::BAD_REQUEST);
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+    assert_eq!(test::call_service(&app, request()).await.status(), StatusCode::ACCEPTED);
+
+    let mut import = Value::Null;
+    for _ in 0..100 {
+        let req = test::TestRequest::get().uri("/import").cookie(token.clone()).to_request();
+        import = test::call_and_read_body_json(&app, req).await;
+        if import["status"] == "done" || import["status"] == "failed" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(import["status"], "done", "{}", import);
+    assert_eq!(
+        import["progress"],
+        json!({"total": 4, "processed": 4, "imported": 2, "duplicates": 1, "rejected": 1, "failed": 0})
+    );
+
+    // oldest first, whatever the order in the archive
+    let req = test::TestRequest::get().uri("/post").cookie(token).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts.len(), 2);
+    let stored = std::fs::read(format!("images/{}", posts[0]["image"].as_str().unwrap())).unwrap();
+    assert_eq!(stored, older);
+    assert_eq!(posts[0]["ratio"], "8:8");
+}
+
+#[actix_web::test]
+async fn entry_is_read_no_further_than_the_image_limit_whatever_size_it_declares() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("sisik")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    // the entry says it holds 16 bytes, in the local header and in the central directory
+    let mut zip = archive(&[("big.png", vec![0; MAX_IMAGE_BYTES as usize + 1024])]);
+    let central = zip.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+    zip[22..26].copy_from_slice(&16u32.to_le_bytes());
+    zip[central + 24..central + 28].copy_from_slice(&16u32.to_le_bytes());
+
+    let boundary = "import-boundary";
+    let req = post_request()
+        .uri("/import")
+        .cookie(token.clone())
+        .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+        .set_payload(multipart(boundary, "1", "big.zip", &zip))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::ACCEPTED);
+
+    let mut import = Value::Null;
+    for _ in 0..100 {
+        let req = test::TestRequest::get().uri("/import").cookie(token.clone()).to_request();
+        import = test::call_and_read_body_json(&app, req).await;
+        if import["status"] == "done" || import["status"] == "failed" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(import["status"], "done", "{}", import);
+    assert_eq!(
+        import["progress"],
+        json!({"total": 1, "processed": 1, "imported": 0, "duplicates": 0, "rejected": 0, "failed": 1})
+    );
+}
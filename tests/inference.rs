@@ -0,0 +1,48 @@
+mod common;
+
+use common::{png, stand_in_model};
+use gallery_backend::ai::classifier::{Classifier, ClassifierConfig};
+use std::time::Duration;
+
+#[actix_web::test]
+async fn images_arriving_together_are_classified_in_one_batch() {
+    let config = ClassifierConfig {
+        threads: 1,
+        queue: 8,
+        batch_size: 4,
+        batch_wait: Duration::from_millis(500),
+        ..Default::default()
+    };
+    let classifier = Classifier::new(stand_in_model(config.batch_size), config);
+
+    let (safe, red, invalid, dark) = tokio::join!(
+        classifier.check(png([0, 0, 90])),
+        classifier.check(png([255, 0, 0])),
+        classifier.check(b"not an image".to_vec()),
+        classifier.check(png([10, 10, 10])),
+    );
+    assert!(safe.unwrap()["nsfw"] < 0.2);
+    assert!(red.unwrap()["nsfw"] > 0.8);
+    assert!(invalid.is_err());
+    assert!(dark.unwrap()["nsfw"] < 0.2);
+
+    let metrics = classifier.metrics();
+    assert_eq!(metrics.queue_depth, 0);
+    assert_eq!(metrics.images, 4);
+    assert_eq!(metrics.batches, 1);
+}
+
+#[actix_web::test]
+async fn a_batch_of_one_runs_without_waiting_for_more() {
+    let classifier = Classifier::new(stand_in_model(1), ClassifierConfig::default());
+
+    let scores = classifier.check(png([0, 90, 0])).await.unwrap();
+    assert_eq!(scores.keys().collect::<Vec<_>>(), ["nsfw", "safe"]);
+    assert!((scores["safe"] + scores["nsfw"] - 1.0).abs() < 1e-5);
+    assert!(scores["safe"] > scores["nsfw"]);
+    assert!(classifier.check(png([255, 0, 0])).await.unwrap()["nsfw"] > 0.8);
+
+    let metrics = classifier.metrics();
+    assert_eq!(metrics.images, 2);
+    assert_eq!(metrics.batches, 2);
+}
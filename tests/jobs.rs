@@ -0,0 +1,276 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::{Duration, Utc};
+use common::{app_data, finish_job, multipart, png, post_request, profile, register, setup, stand_in_model, unique};
+use gallery_backend::ai::classifier::{Classifier, ClassifierConfig};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::service::job_service::{JobConfig, JobService};
+use gallery_backend::utils::clock::FakeClock;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// both tests run the queue and look at a job between runs, one at a time so
+/// neither runs the other's job in between
+static QUEUE: Mutex<()> = Mutex::const_new(());
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// a little endian tiff with Make, Model and DateTimeOriginal in its Exif directory
+fn exif() -> Vec<u8> {
+    let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+        [tag.to_le_bytes().as_slice(), &kind.to_le_bytes(), &count.to_le_bytes(), &value.to_le_bytes()].concat()
+    };
+    let (make, model, taken) = (b"Canon\0".as_slice(), b"Canon EOS R6\0".as_slice(), b"2024:05:01 12:30:00\0".as_slice());
+    // ifd0 at 8 is 42 bytes, the exif ifd after it 18, the strings after that
+    let (exif_ifd, data) = (50u32, 68u32);
+
+    let mut tiff = [b"II".as_slice(), &42u16.to_le_bytes(), &8u32.to_le_bytes()].concat();
+    tiff.extend(3u16.to_le_bytes());
+    tiff.extend(entry(0x010f, 2, make.len() as u32, data));
+    tiff.extend(entry(0x0110, 2, model.len() as u32, data + make.len() as u32));
+    tiff.extend(entry(0x8769, 4, 1, exif_ifd));
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend(1u16.to_le_bytes());
+    tiff.extend(entry(0x9003, 2, taken.len() as u32, data + (make.len() + model.len()) as u32));
+    tiff.extend(0u32.to_le_bytes());
+    tiff.extend([make, model, taken].concat());
+
+    tiff
+}
+
+/// `png` with an eXIf chunk after its header
+fn photo(color: [u8; 3]) -> Vec<u8> {
+    let png = png(color);
+    let exif = exif();
+    let mut chunk = (exif.len() as u32).to_be_bytes().to_vec();
+    let body = [b"eXIf".as_slice(), &exif].concat();
+    chunk.extend(&body);
+    chunk.extend(crc32(&body).to_be_bytes());
+
+    // the signature and the IHDR chunk are 33 bytes
+    [&png[..33], &chunk, &png[33..]].concat()
+}
+
+#[actix_web::test]
+async fn uploads_are_processed_in_the_background() {
+    let _queue = QUEUE.lock().await;
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("islem");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let image = [photo([0, 0, 50]), username.as_bytes().to_vec()].concat();
+    let boundary = "jobs-boundary";
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+        .set_payload(multipart(boundary, "1", "photo.png", &image))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let post: Value = test::read_body_json(res).await;
+    let file = format!("/file/{}", post["image"].as_str().unwrap());
+
+    // nothing is served until it's processed
+    let req = test::TestRequest::get().uri(&file).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let job = finish_job(&app, &token, &data, post["job"].as_str().unwrap()).await;
+    assert_eq!(job["status"], "done");
+    assert_eq!(job["attempts"], 0);
+
+    let req = test::TestRequest::get().uri("/post").cookie(token.clone()).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts[0]["status"], "ready");
+    assert_eq!(posts[0]["width"], 8);
+    assert_eq!(posts[0]["height"], 8);
+    assert_eq!(posts[0]["taken_at"], "2024-05-01T12:30:00");
+    assert_eq!(posts[0]["camera"], "Canon EOS R6");
+
+    let req = test::TestRequest::get().uri(&file).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let thumbnail = format!("/thumbnail/{}", posts[0]["thumbnail"].as_str().unwrap());
+    let req = test::TestRequest::get().uri(&thumbnail).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(test::read_body(res).await.starts_with(&[0xff, 0xd8]));
+
+    let req = test::TestRequest::get().uri(&format!("/jobs/{}", post["job"].as_str().unwrap())).to_request();
+    let res = test::try_call_service(&app, req).await;
+    assert_eq!(res.err().unwrap().as_response_error().status_code(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn failed_jobs_are_retried_later() {
+    let _queue = QUEUE.lock().await;
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("tekrar");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let image = [png([0, 0, 60]), username.as_bytes().to_vec()].concat();
+    let boundary = "jobs-boundary";
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+        .set_payload(multipart(boundary, "1", "photo.png", &image))
+        .to_request();
+    let post: Value = test::call_and_read_body_json(&app, req).await;
+    let job_id = post["job"].as_str().unwrap();
+    let job = || test::TestRequest::get().uri(&format!("/jobs/{}", job_id)).cookie(token.clone()).to_request();
+
+    // a directory where the thumbnail goes keeps it from being written
+    let stem = post["image"].as_str().unwrap().rsplit_once('.').unwrap().0.to_string();
+    let blocked = format!("thumbnails/{}.jpg", stem);
+    std::fs::create_dir_all(&blocked).unwrap();
+
+    // a second ahead, the queue is looked at by the millisecond and the job may be younger than that
+    let clock = FakeClock::new(Utc::now() + Duration::seconds(1));
+    let jobs = JobService::new(JobConfig::default()).with_clock(Arc::new(clock.clone()));
+    jobs.process(&data.classifier).await.unwrap();
+
+    let status: Value = test::call_and_read_body_json(&app, job()).await;
+    assert_eq!(status["status"], "pending");
+    assert_eq!(status["attempts"], 1);
+    assert!(status["error"].as_str().unwrap().contains("thumbnail"));
+
+    // not due again until the retry wait is over
+    std::fs::remove_dir(&blocked).unwrap();
+    jobs.process(&data.classifier).await.unwrap();
+    let status: Value = test::call_and_read_body_json(&app, job()).await;
+    assert_eq!(status["status"], "pending");
+
+    clock.advance(Duration::seconds(31));
+    jobs.process(&data.classifier).await.unwrap();
+    let status: Value = test::call_and_read_body_json(&app, job()).await;
+    assert_eq!(status["status"], "done");
+    assert_eq!(status["attempts"], 1);
+
+    let req = test::TestRequest::get().uri("/post").cookie(token).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts[0]["status"], "ready");
+    assert!(std::path::Path::new(&blocked).is_file());
+}
+
+#[actix_web::test]
+async fn classifier_failures_are_retried_and_only_undecodable_images_taken_down() {
+    let _queue = QUEUE.lock().await;
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("bozuk");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let boundary = "jobs-boundary";
+    let upload = |name: &str, body: Vec<u8>| {
+        post_request()
+            .uri("/upload")
+            .cookie(token.clone())
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(multipart(boundary, "1", name, &body))
+            .to_request()
+    };
+    let image = [png([0, 0, 70]), username.as_bytes().to_vec()].concat();
+    let post: Value = test::call_and_read_body_json(&app, upload("photo.png", image)).await;
+    // cut off in its image data, its size can be read but it can't be decoded
+    let cut = png([0, 0, 80]);
+    let truncated = [&cut[..cut.len() - 20], username.as_bytes()].concat();
+    let garbage: Value = test::call_and_read_body_json(&app, upload("cut.png", truncated)).await;
+    let job = |id: &Value| test::TestRequest::get().uri(&format!("/jobs/{}", id.as_str().unwrap())).cookie(token.clone()).to_request();
+
+    // more labels than the model has outputs, so every image it's given fails
+    let labels = ["safe", "nsfw", "other"].map(String::from).to_vec();
+    let broken = Classifier::new(stand_in_model(1), ClassifierConfig { labels, ..Default::default() });
+    let clock = FakeClock::new(Utc::now() + Duration::seconds(1));
+    let jobs = JobService::new(JobConfig::default()).with_clock(Arc::new(clock.clone()));
+    jobs.process(&broken).await.unwrap();
+
+    let status: Value = test::call_and_read_body_json(&app, job(&post["job"])).await;
+    assert_eq!(status["status"], "pending");
+    assert_eq!(status["attempts"], 1);
+    assert!(status["error"].as_str().unwrap().starts_with("classifier"));
+    let status: Value = test::call_and_read_body_json(&app, job(&garbage["job"])).await;
+    assert_eq!(status["status"], "done");
+    assert_eq!(status["result"], "invalid image");
+
+    clock.advance(Duration::seconds(31));
+    jobs.process(&data.classifier).await.unwrap();
+    let status: Value = test::call_and_read_body_json(&app, job(&post["job"])).await;
+    assert_eq!(status["status"], "done");
+    assert_eq!(status["result"], Value::Null);
+}
+
+#[actix_web::test]
+async fn a_job_given_up_on_removes_its_image_and_gives_back_the_upload() {
+    let _queue = QUEUE.lock().await;
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("vazgec");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+    let limit = || test::TestRequest::get().uri("/upload_limit").cookie(token.clone()).to_request();
+    let before = test::call_and_read_body(&app, limit()).await;
+
+    let image = [png([0, 0, 90]), username.as_bytes().to_vec()].concat();
+    let boundary = "jobs-boundary";
+    let upload = || {
+        post_request()
+            .uri("/upload")
+            .cookie(token.clone())
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(multipart(boundary, "1", "photo.png", &image))
+            .to_request()
+    };
+    let post: Value = test::call_and_read_body_json(&app, upload()).await;
+    let file = format!("images/{}", post["image"].as_str().unwrap());
+    assert!(std::path::Path::new(&file).exists());
+
+    let labels = ["safe", "nsfw", "other"].map(String::from).to_vec();
+    let broken = Classifier::new(stand_in_model(1), ClassifierConfig { labels, ..Default::default() });
+    let clock = FakeClock::new(Utc::now() + Duration::seconds(1));
+    let config = JobConfig {
+        max_attempts: 1,
+        ..Default::default()
+    };
+    JobService::new(config).with_clock(Arc::new(clock)).process(&broken).await.unwrap();
+
+    let req = test::TestRequest::get().uri(&format!("/jobs/{}", post["job"].as_str().unwrap())).cookie(token.clone()).to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["status"], "failed");
+    assert!(!std::path::Path::new(&file).exists());
+    assert_eq!(test::call_and_read_body(&app, limit()).await, before);
+
+    // nothing is left of it, so the same image can be uploaded again
+    let res = test::call_service(&app, upload()).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{stand_in_model, unique};
+use gallery_backend::ai::image_classification::{check_outputs, load_labels, Scores};
+use gallery_backend::ai::moderation::{ModerationConfig, Verdict};
+
+#[test]
+fn thresholds_apply_to_the_sum_of_the_unsafe_labels() {
+    let moderation = ModerationConfig {
+        unsafe_labels: vec!["hentai".to_string(), "porn".to_string(), "sexy".to_string()],
+        reject: 0.8,
+        review: 0.5,
+    };
+    let scores = |pairs: &[(&str, f32)]| -> Scores { pairs.iter().map(|(l, s)| (l.to_string(), *s)).collect() };
+
+    assert_eq!(moderation.verdict(&scores(&[("drawing", 0.7), ("neutral", 0.2), ("sexy", 0.1)])), Verdict::Allow);
+    assert_eq!(moderation.verdict(&scores(&[("neutral", 0.4), ("porn", 0.3), ("sexy", 0.3)])), Verdict::Review);
+    assert_eq!(moderation.verdict(&scores(&[("hentai", 0.5), ("porn", 0.4), ("neutral", 0.1)])), Verdict::Reject);
+}
+
+#[test]
+fn labels_are_read_from_next_to_the_model() {
+    let dir = std::env::temp_dir().join(unique("labels"));
+    std::fs::create_dir_all(&dir).unwrap();
+    let model = dir.join("model.onnx").to_string_lossy().to_string();
+
+    assert_eq!(load_labels(&model).unwrap(), ["safe", "nsfw"]);
+
+    std::fs::write(dir.join("model.labels.json"), r#"["drawing", "hentai", "neutral", "porn", "sexy"]"#).unwrap();
+    assert_eq!(load_labels(&model).unwrap(), ["drawing", "hentai", "neutral", "porn", "sexy"]);
+
+    std::fs::write(dir.join("model.labels.json"), "drawing,neutral").unwrap();
+    assert!(load_labels(&model).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unsafe_labels_have_to_be_the_models() {
+    let labels = ["drawing", "hentai", "neutral", "porn", "sexy"].map(String::from);
+    let moderation = |unsafe_labels: &[&str]| ModerationConfig {
+        unsafe_labels: unsafe_labels.iter().map(|l| l.to_string()).collect(),
+        ..Default::default()
+    };
+
+    assert!(moderation(&["hentai", "porn"]).validate(&labels).is_ok());
+    // the default `nsfw` isn't one of them, nothing would ever be rejected
+    assert!(ModerationConfig::default().validate(&labels).is_err());
+    assert!(moderation(&["porn", "nude"]).validate(&labels).is_err());
+    assert!(moderation(&[]).validate(&labels).is_err());
+}
+
+#[test]
+fn the_model_has_to_have_an_output_for_each_label() {
+    let model = stand_in_model(1);
+
+    assert!(check_outputs(&model, &["safe", "nsfw"].map(String::from)).is_ok());
+    assert!(check_outputs(&model, &["drawing", "hentai", "neutral", "porn", "sexy"].map(String::from)).is_err());
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::header::LOCATION;
+use actix_web::http::StatusCode;
+use actix_web::{test, Error};
+use common::oidc::MockIssuer;
+use common::{app_data_with_oidc, cookie, profile, register, setup, unique};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::service::oidc::{OidcConfig, OidcProvider};
+use serde_json::{json, Value};
+
+const FRONTEND: &str = "https://localhost/";
+
+fn config(issuer: &MockIssuer) -> OidcConfig {
+    OidcConfig {
+        providers: vec![OidcProvider {
+            name: "mock".to_string(),
+            issuer: issuer.url.clone(),
+            client_id: "gallery".to_string(),
+            client_secret: Some("client-secret".to_string()),
+            scopes: "openid email profile".to_string(),
+        }],
+        redirect_base: "https://localhost".to_string(),
+        frontend_url: FRONTEND.to_string(),
+    }
+}
+
+/// the redirect to the provider and the state cookie that came with it
+async fn start<S, B>(app: &S) -> (String, Cookie<'static>)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get().uri("/oidc/mock/login").to_request();
+    let res = test::call_service(app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+
+    let location = res.headers().get(LOCATION).unwrap().to_str().unwrap().to_string();
+    assert!(location.contains("redirect_uri=https%3A%2F%2Flocalhost%2Foidc%2Fmock%2Fcallback"));
+
+    (location, cookie(&res, "oidc_state").expect("state cookie"))
+}
+
+async fn callback<S, B>(app: &S, state_cookie: Option<&Cookie<'static>>, state: &str, code: &str) -> ServiceResponse<B>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let mut req = test::TestRequest::get().uri(&format!("/oidc/mock/callback?code={}&state={}", code, state));
+    if let Some(state_cookie) = state_cookie {
+        req = req.cookie(state_cookie.clone());
+    }
+
+    test::call_service(app, req.to_request()).await
+}
+
+/// a full round trip through the provider, returns the callback response
+async fn sign_in<S, B>(app: &S, issuer: &MockIssuer, claims: Value) -> ServiceResponse<B>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let (location, state_cookie) = start(app).await;
+    let (state, code) = issuer.authorize(&location, claims);
+
+    callback(app, Some(&state_cookie), &state, &code).await
+}
+
+#[actix_web::test]
+async fn signs_up_and_back_in_with_a_provider() {
+    let config = setup();
+    let issuer = MockIssuer::start("gallery").await;
+    let app = test::init_service(build_app(&config, app_data_with_oidc(self::config(&issuer)))).await;
+    let subject = unique("sub");
+    let email = format!("{}@example.com", unique("jane"));
+    let claims = json!({
+        "sub": subject,
+        "email": email,
+        "email_verified": true,
+        "preferred_username": "Jane Doe!",
+    });
+
+    let req = test::TestRequest::get().uri("/oidc/providers").to_request();
+    let providers: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(providers, json!(["mock"]));
+
+    let res = sign_in(&app, &issuer, claims.clone()).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers().get(LOCATION).unwrap(), FRONTEND);
+    let token = cookie(&res, "token").expect("token cookie");
+
+    let created = profile(&app, &token).await;
+    assert_eq!(created["email"], email);
+    assert_eq!(created["email_verified"], true);
+    assert!(created["username"].as_str().unwrap().starts_with("JaneDoe"));
+
+    // the identity is linked now, the next login finds the same account
+    let res = sign_in(&app, &issuer, claims).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let token = cookie(&res, "token").expect("token cookie");
+    assert_eq!(profile(&app, &token).await["id"], created["id"]);
+}
+
+#[actix_web::test]
+async fn links_existing_accounts_by_verified_email() {
+    let config = setup();
+    let issuer = MockIssuer::start("gallery").await;
+    let app = test::init_service(build_app(&config, app_data_with_oidc(self::config(&issuer)))).await;
+    let username = unique("kemal");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let claims = json!({
+        "sub": unique("sub"),
+        "email": format!("{}@example.com", username),
+        "email_verified": true,
+    });
+
+    // nobody proved owning the email of the local account yet
+    let res = sign_in(&app, &issuer, claims.clone()).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    db::surrealdb::set_email_verified(&user_id).await.unwrap();
+
+    let res = sign_in(&app, &issuer, claims).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let token = cookie(&res, "token").expect("token cookie");
+    assert_eq!(profile(&app, &token).await["username"], username);
+
+    // the provider has to vouch for the email
+    let res = sign_in(
+        &app,
+        &issuer,
+        json!({ "sub": unique("sub"), "email": format!("{}@example.com", username), "email_verified": false }),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn rejects_forged_and_replayed_callbacks() {
+    let config = setup();
+    let issuer = MockIssuer::start("gallery").await;
+    let app = test::init_service(build_app(&config, app_data_with_oidc(self::config(&issuer)))).await;
+    let claims = json!({
+        "sub": unique("sub"),
+        "email": format!("{}@example.com", unique("lale")),
+        "email_verified": true,
+    });
+
+    let (location, state_cookie) = start(&app).await;
+    let (state, code) = issuer.authorize(&location, claims);
+
+    // started in another browser
+    assert_eq!(callback(&app, None, &state, &code).await.status(), StatusCode::UNAUTHORIZED);
+    let (_, other_cookie) = start(&app).await;
+    assert_eq!(callback(&app, Some(&other_cookie), &state, &code).await.status(), StatusCode::UNAUTHORIZED);
+
+    let res = callback(&app, Some(&state_cookie), &state, &code).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert!(cookie(&res, "token").is_some());
+
+    assert_eq!(callback(&app, Some(&state_cookie), &state, &code).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get().uri("/oidc/nope/login").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,85 @@
+use gallery_backend::service::password_service::{PasswordConfig, PasswordService};
+
+fn fast() -> PasswordConfig {
+    PasswordConfig {
+        memory_kib: 1024,
+        iterations: 1,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn hash_verifies_and_rejects_wrong_password() {
+    let passwords = PasswordService::new(fast());
+    let hash = passwords.hash("correct horse battery").await;
+
+    assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    assert_ne!(hash, passwords.hash("correct horse battery").await);
+    assert!(passwords.verify("correct horse battery", Some(&hash)).await);
+    assert!(!passwords.verify("correct horse batterY", Some(&hash)).await);
+    assert!(!passwords.verify("correct horse battery", None).await);
+    assert!(!passwords.verify("correct horse battery", Some("not a hash")).await);
+}
+
+#[tokio::test]
+async fn parameter_changes_require_rehash() {
+    let passwords = PasswordService::new(fast());
+    let hash = passwords.hash("correct horse battery").await;
+    assert!(!passwords.needs_rehash(&hash));
+
+    let stronger = PasswordService::new(PasswordConfig {
+        iterations: 2,
+        ..fast()
+    });
+    assert!(stronger.needs_rehash(&hash));
+    assert!(stronger.verify("correct horse battery", Some(&hash)).await);
+
+    let argon2i = "$argon2i$v=19$m=1024,t=1,p=1$c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ";
+    assert!(passwords.needs_rehash(argon2i));
+    assert!(passwords.needs_rehash("garbage"));
+}
+
+#[tokio::test]
+async fn policy_checks_length_classes_and_personal_data() {
+    let passwords = PasswordService::new(fast());
+    let check = |password: &'static str| passwords.check_policy("password", password, "alice", "ally@example.com");
+
+    assert!(check("correct horse battery").await.is_ok());
+    assert!(check("Tr0ub4dor&3").await.is_ok());
+
+    assert_eq!(check("Sh0rt!").await.unwrap_err().message, "at least 10 characters");
+    assert!(check("onlylowercaseletters").await.is_err());
+    assert!(check("12345678901234").await.is_err());
+    assert!(check("my name is Alice!").await.is_err());
+    assert!(check("ally is my name!").await.is_err());
+}
+
+#[tokio::test]
+async fn breached_passwords_are_found_by_hash_prefix() {
+    let dir = std::env::temp_dir().join(format!("breached-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let digest = hex::encode_upper(openssl::sha::sha1(b"Password123!"));
+    let (prefix, suffix) = digest.split_at(5);
+    std::fs::write(
+        dir.join(format!("{}.txt", prefix)),
+        format!("0000000000000000000000000000000000A:3\r\n{}:42\r\n", suffix.to_lowercase()),
+    )
+    .unwrap();
+
+    let passwords = PasswordService::new(PasswordConfig {
+        breached_dir: Some(dir.clone()),
+        ..fast()
+    });
+
+    assert!(passwords.breached("Password123!").await);
+    assert!(!passwords.breached("correct horse battery").await);
+
+    let error = passwords
+        .check_policy("password", "Password123!", "bob", "bob@example.com")
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "appears in a known data breach");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
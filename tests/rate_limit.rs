@@ -0,0 +1,215 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::Duration;
+use common::{app_data_with, post_request, register, setup, unique, PASSWORD};
+use gallery_backend::build_app;
+use gallery_backend::service::rate_limiter::{RateLimitConfig, RateLimitRule, RateLimiter};
+use serde_json::json;
+
+fn login_request(username: &str, password: &str, ip: &str) -> actix_http::Request {
+    post_request()
+        .uri("/login")
+        .peer_addr(format!("{}:4000", ip).parse().unwrap())
+        .set_json(json!({"username": username, "password": password}))
+        .to_request()
+}
+
+#[actix_web::test]
+async fn route_bucket_is_per_client() {
+    let config = setup();
+    let app_data = app_data_with(RateLimitConfig {
+        rules: vec![RateLimitRule {
+            path: "/login".to_string(),
+            capacity: 2,
+            period: Duration::minutes(1),
+        }],
+        ..Default::default()
+    });
+    let app = test::init_service(build_app(&config, app_data)).await;
+
+    for _ in 0..2 {
+        let res = test::call_service(&app, login_request("nobody", "x", "10.0.0.1")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = test::call_service(&app, login_request("nobody", "x", "10.0.0.1")).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: i64 = res.headers().get("retry-after").unwrap().to_str().unwrap().parse().unwrap();
+    assert!((1..=30).contains(&retry_after));
+
+    let res = test::call_service(&app, login_request("somebody", "x", "10.0.0.2")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn account_bucket_spans_clients() {
+    let config = setup();
+    let app_data = app_data_with(RateLimitConfig {
+        rules: vec![RateLimitRule {
+            path: "/login".to_string(),
+            capacity: 2,
+            period: Duration::minutes(1),
+        }],
+        max_failures: 10,
+        ..Default::default()
+    });
+    let app = test::init_service(build_app(&config, app_data)).await;
+    let username = unique("walter");
+
+    for ip in ["10.0.2.1", "10.0.2.2"] {
+        let res = test::call_service(&app, login_request(&username, "x", ip)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = test::call_service(&app, login_request(&username, "x", "10.0.2.3")).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(res.headers().contains_key("retry-after"));
+
+    let res = test::call_service(&app, login_request(&unique("walter"), "x", "10.0.2.3")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn pruning_keeps_the_buckets_of_slower_rules_that_are_used_up() {
+    let rule = |path: &str, period| RateLimitRule {
+        path: path.to_string(),
+        capacity: 1,
+        period,
+    };
+    let (slow, fast) = (rule("/slow", Duration::hours(1)), rule("/fast", Duration::milliseconds(1)));
+    let limiter = RateLimiter::new(RateLimitConfig {
+        rules: vec![slow.clone(), fast.clone()],
+        ..Default::default()
+    });
+
+    limiter.acquire(&slow, "10.0.3.1").await.unwrap();
+    // long enough for a bucket of the fast rule to be full again, not one of the slow
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    for i in 0..10_001 {
+        limiter.acquire(&fast, &format!("10.1.{}.{}", i / 256, i % 256)).await.unwrap();
+    }
+    assert!(limiter.acquire(&slow, "10.0.3.1").await.is_err());
+}
+
+#[actix_web::test]
+async fn credential_routes_are_limited_by_default() {
+    let limiter = RateLimiter::new(RateLimitConfig::default());
+
+    for path in [
+        "/login",
+        "/login/2fa",
+        "/register",
+        "/password_reset/request",
+        "/verify_email/send",
+        "/webauthn/login/start",
+        "/webauthn/login/finish",
+        "/webauthn/register/start",
+    ] {
+        assert!(limiter.rule(path).is_some(), "{}", path);
+    }
+}
+
+#[actix_web::test]
+async fn export_starts_are_limited_but_polling_its_status_is_not() {
+    let config = setup();
+    let app_data = app_data_with(RateLimitConfig {
+        rules: vec![RateLimitRule {
+            path: "/export".to_string(),
+            capacity: 1,
+            period: Duration::minutes(1),
+        }],
+        ..Default::default()
+    });
+    let app = test::init_service(build_app(&config, app_data)).await;
+    let token = register(&app, &unique("disa")).await;
+    let from = |req: test::TestRequest| {
+        req.uri("/export")
+            .cookie(token.clone())
+            .peer_addr("10.0.2.1:4000".parse().unwrap())
+            .to_request()
+    };
+    assert!(RateLimiter::new(RateLimitConfig::default()).rule("/export").is_some());
+
+    let res = test::call_service(&app, from(post_request())).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    for _ in 0..3 {
+        let res = test::call_service(&app, from(test::TestRequest::get())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+    let res = test::call_service(&app, from(post_request())).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn repeated_failures_lock_the_account() {
+    let config = setup();
+    let app_data = app_data_with(RateLimitConfig {
+        rules: vec![],
+        max_failures: 3,
+        ..Default::default()
+    });
+    let app = test::init_service(build_app(&config, app_data)).await;
+    let username = unique("ivan");
+
+    let req = post_request()
+        .uri("/register")
+        .set_json(json!({
+            "username": username,
+            "email": format!("{}@example.com", username),
+            "password": PASSWORD,
+        }))
+        .to_request();
+    test::call_service(&app, req).await;
+
+    for _ in 0..3 {
+        let res = test::call_service(&app, login_request(&username, "wrong", "10.0.1.1")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let res = test::call_service(&app, login_request(&username, PASSWORD, "10.0.1.2")).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: i64 = res.headers().get("retry-after").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 14 * 60);
+}
+
+#[actix_web::test]
+async fn success_resets_failure_count() {
+    setup();
+    let limiter = RateLimiter::new(RateLimitConfig {
+        max_failures: 2,
+        ..Default::default()
+    });
+
+    limiter.fail("judy").await.unwrap();
+    limiter.succeed("judy").await.unwrap();
+    limiter.fail("judy").await.unwrap();
+    assert!(limiter.locked("judy").await.unwrap().is_none());
+
+    limiter.fail("judy").await.unwrap();
+    assert!(limiter.locked("judy").await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn persisted_lockout_survives_restart() {
+    setup();
+    let config = RateLimitConfig {
+        max_failures: 2,
+        persist: true,
+        ..Default::default()
+    };
+    let username = unique("mallory");
+
+    let limiter = RateLimiter::new(config.clone());
+    limiter.fail(&username).await.unwrap();
+    assert!(limiter.locked(&username).await.unwrap().is_none());
+    limiter.fail(&username).await.unwrap();
+
+    let restarted = RateLimiter::new(config);
+    let remaining = restarted.locked(&username).await.unwrap().expect("locked");
+    assert!(remaining > Duration::minutes(14));
+
+    restarted.succeed(&username).await.unwrap();
+    assert!(limiter.locked(&username).await.unwrap().is_none());
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test;
+use common::{app_data, post_request, profile, register, setup, unique};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use gallery_backend::model::import::{ImportProgress, RUNNING};
+use gallery_backend::service::export_service::ExportService;
+use gallery_backend::service::import_service::ImportService;
+
+#[actix_web::test]
+async fn export_cut_short_by_a_restart_is_failed_and_can_be_started_again() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("yarim")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+
+    let id = db::surrealdb::export_create(&user_id).await.unwrap().unwrap();
+    std::fs::create_dir_all(&config.export_dir).unwrap();
+    let part = std::path::Path::new(&config.export_dir).join(format!("{}.zip.part", id));
+    std::fs::write(&part, b"PK").unwrap();
+
+    let req = post_request().uri("/export").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+    assert!(ExportService::new(&config.export_dir).recover().await.unwrap() >= 1);
+    assert!(!part.exists());
+    let export = db::surrealdb::export_latest(&user_id).await.unwrap().unwrap();
+    assert_eq!(export.status, "failed");
+
+    let req = post_request().uri("/export").cookie(token.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::ACCEPTED);
+}
+
+#[actix_web::test]
+async fn import_cut_short_by_a_restart_is_failed_and_its_archive_removed() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("yarim")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+
+    let imports = ImportService::new(&config.import_dir);
+    let running = db::surrealdb::import_create(&user_id).await.unwrap().unwrap();
+    db::surrealdb::import_update(&running, RUNNING, &ImportProgress::default(), None).await.unwrap();
+    std::fs::create_dir_all(&config.import_dir).unwrap();
+    std::fs::write(imports.path(&running), b"PK").unwrap();
+    assert_eq!(db::surrealdb::import_create(&user_id).await.unwrap(), None);
+
+    assert!(imports.recover().await.unwrap() >= 1);
+    assert!(!imports.path(&running).exists());
+    let import = db::surrealdb::import_latest(&user_id).await.unwrap().unwrap();
+    assert_eq!(import.status, "failed");
+    assert!(db::surrealdb::import_create(&user_id).await.unwrap().is_some());
+}
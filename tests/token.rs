@@ -0,0 +1,64 @@
+use chrono::{Duration, Utc};
+use gallery_backend::utils::security::{RetiredKey, TokenError, TokenKeys, ACCESS_TOKEN, REFRESH_TOKEN};
+
+#[test]
+fn signed_token_carries_standard_claims() {
+    let keys = TokenKeys::new("k1", "secret");
+    let token = keys.sign(ACCESS_TOKEN, "user:alice", "s1");
+
+    let claims = keys.verify(&token, ACCESS_TOKEN).unwrap();
+    assert_eq!(claims.sub, "user:alice");
+    assert_eq!(claims.sid, "s1");
+    assert_eq!(claims.iss, "gallery-backend");
+    assert_eq!(claims.exp - claims.iat, keys.access_ttl.num_seconds());
+    assert_ne!(claims.jti, keys.verify(&keys.sign(ACCESS_TOKEN, "user:alice", "s1"), ACCESS_TOKEN).unwrap().jti);
+}
+
+#[test]
+fn expired_token_is_rejected() {
+    let mut keys = TokenKeys::new("k1", "secret");
+    keys.access_ttl = Duration::zero();
+    let token = keys.sign(ACCESS_TOKEN, "user:alice", "s1");
+
+    assert!(matches!(keys.verify(&token, ACCESS_TOKEN), Err(TokenError::Expired)));
+}
+
+#[test]
+fn token_type_and_issuer_are_checked() {
+    let keys = TokenKeys::new("k1", "secret");
+    let refresh = keys.sign(REFRESH_TOKEN, "user:alice", "s1");
+    assert!(matches!(keys.verify(&refresh, ACCESS_TOKEN), Err(TokenError::WrongType)));
+
+    let mut other = TokenKeys::new("k1", "secret");
+    other.issuer = "someone-else".to_string();
+    let token = other.sign(ACCESS_TOKEN, "user:alice", "s1");
+    assert!(matches!(keys.verify(&token, ACCESS_TOKEN), Err(TokenError::WrongIssuer)));
+}
+
+#[test]
+fn unknown_kid_and_bad_signature_are_rejected() {
+    let keys = TokenKeys::new("k1", "secret");
+
+    let token = TokenKeys::new("k2", "secret").sign(ACCESS_TOKEN, "user:alice", "s1");
+    assert!(matches!(keys.verify(&token, ACCESS_TOKEN), Err(TokenError::Jwt(_))));
+
+    let token = TokenKeys::new("k1", "forged").sign(ACCESS_TOKEN, "user:alice", "s1");
+    assert!(matches!(keys.verify(&token, ACCESS_TOKEN), Err(TokenError::Jwt(_))));
+}
+
+#[test]
+fn retired_key_is_accepted_during_grace_period() {
+    let old = TokenKeys::new("k1", "old-secret");
+    let token = old.sign(ACCESS_TOKEN, "user:alice", "s1");
+
+    let mut rotated = TokenKeys::new("k2", "new-secret");
+    rotated.retired.push(RetiredKey {
+        kid: "k1".to_string(),
+        secret: "old-secret".to_string(),
+        until: Utc::now() + Duration::hours(1),
+    });
+    assert_eq!(rotated.verify(&token, ACCESS_TOKEN).unwrap().sub, "user:alice");
+
+    rotated.retired[0].until = Utc::now() - Duration::seconds(1);
+    assert!(rotated.verify(&token, ACCESS_TOKEN).is_err());
+}
@@ -0,0 +1,31 @@
+use gallery_backend::utils::totp;
+
+/// RFC 6238 appendix B, SHA1 secret "12345678901234567890"
+#[test]
+fn matches_rfc_test_vectors() {
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    for (unix, expected) in [(59, "287082"), (1111111109, "081804"), (1234567890, "005924"), (2000000000, "279037")] {
+        assert_eq!(totp::code(secret, totp::step(unix)).unwrap(), expected);
+        assert_eq!(totp::verify(secret, expected, unix), Some(totp::step(unix)));
+    }
+
+    assert_eq!(totp::verify(secret, "287082", 59 + 30), Some(1));
+    assert_eq!(totp::verify(secret, "287082", 59 + 90), None);
+    assert_eq!(totp::verify(secret, "28708", 59), None);
+    assert_eq!(totp::verify(secret, "abcdef", 59), None);
+}
+
+#[test]
+fn recovery_codes_and_uri() {
+    let codes = totp::generate_recovery_codes();
+    assert_eq!(codes.len(), 10);
+    assert!(codes.iter().all(|c| c.len() == 11 && c.as_bytes()[5] == b'-'));
+    assert_eq!(totp::hash_recovery_code(&codes[0]), totp::hash_recovery_code(&codes[0].to_uppercase().replace('-', " ")));
+
+    let uri = totp::otpauth_uri("My Gallery", "alice", "ABC");
+    assert_eq!(
+        uri,
+        "otpauth://totp/My%20Gallery:alice?secret=ABC&issuer=My%20Gallery&algorithm=SHA1&digits=6&period=30"
+    );
+}
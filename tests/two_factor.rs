@@ -0,0 +1,130 @@
+mod common;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{test, Error};
+use chrono::Utc;
+use common::{app_data, cookie, post_request, register, setup, status, unique, PASSWORD};
+use gallery_backend::build_app;
+use gallery_backend::utils::totp;
+use serde_json::{json, Value};
+
+/// the step to build codes around, away from the end of a period so
+/// `step - 1` is still accepted for the rest of the test
+async fn stable_step() -> i64 {
+    let now = Utc::now().timestamp();
+    if now % totp::STEP_SECS >= totp::STEP_SECS - 3 {
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    }
+
+    totp::step(Utc::now().timestamp())
+}
+
+async fn login_two_factor<S, B>(app: &S, pre_auth: &Cookie<'static>, code: &str) -> StatusCode
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let req = post_request()
+        .uri("/login/2fa")
+        .cookie(pre_auth.clone())
+        .set_json(json!({"code": code}))
+        .to_request();
+
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn enrol_login_with_codes_and_disable() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("wendy");
+    let token = register(&app, &username).await;
+
+    let req = post_request()
+        .uri("/2fa/enroll")
+        .cookie(token.clone())
+        .to_request();
+    let enrolment: Value = test::call_and_read_body_json(&app, req).await;
+    let secret = enrolment["secret"].as_str().unwrap().to_string();
+    assert!(enrolment["uri"].as_str().unwrap().contains(&format!(":{}?secret={}", username, secret)));
+
+    let step = stable_step().await;
+    let req = post_request()
+        .uri("/2fa/enable")
+        .cookie(token.clone())
+        .set_json(json!({"code": totp::code(&secret, step - 1).unwrap()}))
+        .to_request();
+    let enabled: Value = test::call_and_read_body_json(&app, req).await;
+    let recovery_codes: Vec<String> = serde_json::from_value(enabled["recovery_codes"].clone()).unwrap();
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    assert!(cookie(&res, "token").is_none());
+    let pre_auth = cookie(&res, "pre_auth").unwrap();
+    let as_access_token = Cookie::new("token", pre_auth.value().to_string());
+    assert_eq!(status(&app, &as_access_token).await, StatusCode::UNAUTHORIZED);
+
+    let current = totp::code(&secret, step).unwrap();
+    assert_eq!(login_two_factor(&app, &pre_auth, "000000").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login_two_factor(&app, &pre_auth, &totp::code(&secret, step - 1).unwrap()).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login_two_factor(&app, &pre_auth, &current).await, StatusCode::OK);
+    assert_eq!(login_two_factor(&app, &pre_auth, &current).await, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(login_two_factor(&app, &pre_auth, &recovery_codes[0]).await, StatusCode::OK);
+    assert_eq!(login_two_factor(&app, &pre_auth, &recovery_codes[0]).await, StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri("/2fa/disable")
+        .cookie(token.clone())
+        .set_json(json!({"code": current}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let req = post_request()
+        .uri("/2fa/disable")
+        .cookie(token)
+        .set_json(json!({"code": totp::code(&secret, step + 1).unwrap()}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = post_request()
+        .uri("/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(cookie(&res, "token").is_some());
+}
+
+#[actix_web::test]
+async fn second_step_needs_the_pre_auth_cookie() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("xavier")).await;
+
+    let req = post_request()
+        .uri("/login/2fa")
+        .cookie(Cookie::new("pre_auth", token.value().to_string()))
+        .set_json(json!({"code": "123456"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let req = post_request()
+        .uri("/2fa/enable")
+        .cookie(token)
+        .set_json(json!({"code": "123456"}))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,265 @@
+mod common;
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{Method, StatusCode};
+use actix_web::test;
+use common::{app_data, finish_job, png, post_request, profile, register, setup, unique};
+use data_encoding::BASE64;
+use gallery_backend::build_app;
+use gallery_backend::db;
+use serde_json::Value;
+
+fn multipart_files(boundary: &str, ratio: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut body = format!("--{boundary}\r\nContent-Disposition: form-data; name=\"ratio\"\r\n\r\n{ratio}\r\n").into_bytes();
+    for (file_name, file) in files {
+        body.extend_from_slice(format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: image/png\r\n\r\n"
+        ).as_bytes());
+        body.extend_from_slice(file);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+    body
+}
+
+fn offset<B>(res: &ServiceResponse<B>) -> usize {
+    res.headers().get("upload-offset").unwrap().to_str().unwrap().parse().unwrap()
+}
+
+#[actix_web::test]
+async fn several_files_are_answered_one_by_one() {
+    let config = setup();
+    let data = app_data();
+    let app = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("coklu");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    // trailing bytes keep the hashes apart from images earlier runs left behind
+    let image = |color: [u8; 3]| [png(color), username.as_bytes().to_vec()].concat();
+    let (safe, red) = (image([0, 0, 30]), image([255, 0, 0]));
+    let boundary = "several-boundary";
+    let req = post_request()
+        .uri("/upload")
+        .cookie(token.clone())
+        .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+        .set_payload(multipart_files(boundary, "4:3", &[("a.png", &safe), ("red.png", &red), ("notes.txt", b"text")]))
+        .to_request();
+    let results: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+
+    let statuses: Vec<(&str, u64)> = results
+        .iter()
+        .map(|r| (r["file"].as_str().unwrap(), r["status"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(statuses, [("a.png", 202), ("red.png", 202), ("notes.txt", 400)]);
+    assert!(results[2]["error"].as_str().unwrap().starts_with("invalid image"));
+
+    let job = finish_job(&app, &token, &data, results[0]["job"].as_str().unwrap()).await;
+    assert_eq!(job["result"], Value::Null);
+    let job = finish_job(&app, &token, &data, results[1]["job"].as_str().unwrap()).await;
+    assert_eq!(job["result"], "nsfw");
+
+    let req = test::TestRequest::get().uri("/post").cookie(token).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0]["id"], results[0]["id"]);
+    assert_eq!(posts[0]["ratio"], "4:3");
+    assert_eq!(posts[0]["status"], "ready");
+}
+
+#[actix_web::test]
+async fn resumable_upload_carries_on_after_an_interruption() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("parca");
+    let token = register(&app, &username).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let content = [png([0, 0, 40]), username.as_bytes().to_vec()].concat();
+    let metadata = format!(
+        "filename {},ratio {}",
+        BASE64.encode(b"photo.png"),
+        BASE64.encode(b"16:9")
+    );
+    let create = || {
+        post_request()
+            .uri("/upload/resumable")
+            .cookie(token.clone())
+            .insert_header(("Upload-Length", content.len().to_string()))
+            .insert_header(("Upload-Metadata", metadata.clone()))
+    };
+
+    let res = test::call_service(&app, create().to_request()).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    let res = test::call_service(&app, create().insert_header(("Tus-Resumable", "1.0.0")).to_request()).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let location = res.headers().get("location").unwrap().to_str().unwrap().to_string();
+    assert!(res.headers().contains_key("upload-expires"));
+
+    let patch = |offset: usize, part: &[u8]| {
+        post_request()
+            .method(Method::PATCH)
+            .uri(&location)
+            .cookie(token.clone())
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .insert_header(("Content-Type", "application/offset+octet-stream"))
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .set_payload(part.to_vec())
+            .to_request()
+    };
+    let head = || {
+        test::TestRequest::default()
+            .method(Method::HEAD)
+            .uri(&location)
+            .cookie(token.clone())
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .to_request()
+    };
+
+    let half = content.len() / 2;
+    let res = test::call_service(&app, patch(0, &content[..half])).await;
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    assert_eq!(offset(&res), half);
+
+    // the client lost track of the second part and sends it from the start again
+    let res = test::call_service(&app, patch(0, &content)).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let res = test::call_service(&app, head()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(offset(&res), half);
+    assert_eq!(res.headers().get("upload-length").unwrap(), content.len().to_string().as_str());
+
+    let res = test::call_service(&app, patch(half, &content[half..])).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let post: Value = test::read_body_json(res).await;
+    let image = post["image"].as_str().unwrap();
+    assert_eq!(post["status"], "processing");
+    assert_eq!(std::fs::read(format!("images/{}", image)).unwrap(), content);
+
+    let req = test::TestRequest::get().uri("/post").cookie(token.clone()).to_request();
+    let posts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0]["ratio"], "16:9");
+
+    let res = test::call_service(&app, head()).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn upload_being_appended_to_on_one_worker_is_locked_on_the_others() {
+    let config = setup();
+    let data = app_data();
+    let first = test::init_service(build_app(&config, data.clone())).await;
+    let second = test::init_service(build_app(&config, data.clone())).await;
+    let username = unique("isci");
+    let token = register(&first, &username).await;
+    let user_id = format!("user:{}", profile(&first, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+    let other = register(&first, &unique("isci")).await;
+
+    let content = [png([0, 0, 50]), username.as_bytes().to_vec()].concat();
+    let req = post_request()
+        .uri("/upload/resumable")
+        .cookie(token.clone())
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .insert_header(("Upload-Length", content.len().to_string()))
+        .insert_header(("Upload-Metadata", format!("filename {},ratio {}", BASE64.encode(b"a.png"), BASE64.encode(b"1:1"))))
+        .to_request();
+    let res = test::call_service(&first, req).await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    let location = res.headers().get("location").unwrap().to_str().unwrap().to_string();
+    let id = location.rsplit('/').next().unwrap();
+
+    let patch = |token: &actix_web::cookie::Cookie<'static>| {
+        post_request()
+            .method(Method::PATCH)
+            .uri(&location)
+            .cookie(token.clone())
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .insert_header(("Content-Type", "application/offset+octet-stream"))
+            .insert_header(("Upload-Offset", "0"))
+            .set_payload(content.clone())
+            .to_request()
+    };
+
+    // as if a part were still arriving on the first worker
+    let busy = data.upload_service.lock(id).unwrap();
+    assert_eq!(test::call_service(&second, patch(&token)).await.status(), StatusCode::LOCKED);
+    drop(busy);
+
+    assert_eq!(test::call_service(&second, patch(&other)).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(test::call_service(&second, patch(&token)).await.status(), StatusCode::ACCEPTED);
+}
+
+#[actix_web::test]
+async fn an_upload_holds_on_to_no_more_than_its_budget() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("butce")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let boundary = "budget-boundary";
+    let upload = |ratio: &str, files: &[(&str, &[u8])]| {
+        post_request()
+            .uri("/upload")
+            .cookie(token.clone())
+            .insert_header(("content-type", format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(multipart_files(boundary, ratio, files))
+            .to_request()
+    };
+
+    // each is under the limit for one image, together they're more than a request holds
+    let part = vec![0; 40 * 1024 * 1024];
+    let res = test::call_service(&app, upload("1", &[("a.png", &part), ("b.png", &part), ("c.png", &part)])).await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let res = test::call_service(&app, upload(&"1".repeat(65), &[("a.png", &png([0, 0, 1]))])).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::read_body(res).await, "ratio too long");
+}
+
+#[actix_web::test]
+async fn a_user_has_only_a_few_resumable_uploads_open_at_once() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let token = register(&app, &unique("acik")).await;
+    let user_id = format!("user:{}", profile(&app, &token).await["id"].as_str().unwrap());
+    let now = chrono::Utc::now().timestamp() as u64;
+    db::surrealdb::add_premium(&user_id, &unique("0x"), &now).await.unwrap();
+
+    let create = || {
+        post_request()
+            .uri("/upload/resumable")
+            .cookie(token.clone())
+            .insert_header(("Tus-Resumable", "1.0.0"))
+            .insert_header(("Upload-Length", "1000"))
+            .insert_header(("Upload-Metadata", format!("filename {},ratio {}", BASE64.encode(b"a.png"), BASE64.encode(b"1:1"))))
+            .to_request()
+    };
+    let mut locations = Vec::new();
+    for _ in 0..5 {
+        let res = test::call_service(&app, create()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        locations.push(res.headers().get("location").unwrap().to_str().unwrap().to_string());
+    }
+    assert_eq!(test::call_service(&app, create()).await.status(), StatusCode::CONFLICT);
+
+    let req = post_request()
+        .method(Method::DELETE)
+        .uri(&locations[0])
+        .cookie(token.clone())
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(test::call_service(&app, create()).await.status(), StatusCode::CREATED);
+}
@@ -0,0 +1,89 @@
+use gallery_backend::model::post::UploadForm;
+use gallery_backend::model::user::{ChangePasswordForm, LoginForm, RegisterForm};
+use gallery_backend::utils::validation::{self, Validate};
+
+fn register(username: &str, email: &str, password: &str) -> RegisterForm {
+    RegisterForm {
+        username: username.to_string(),
+        email: email.to_string(),
+        password: password.to_string(),
+    }
+}
+
+#[test]
+fn previously_mangled_inputs_are_accepted_verbatim() {
+    let passwords = [
+        "it's a secret",
+        "\"quoted\" password",
+        "Dinner; Tom & Jerry",
+        "1=1 OR 2>1",
+        "<script>alert(1)</script>",
+        "../../etc/passwd",
+        "SELECT * FROM user",
+        "p@ss; drop it",
+    ];
+
+    for password in passwords {
+        assert_eq!(register("tom_jerry", "tom@example.com", password).validate(), Ok(()), "{}", password);
+        let login = LoginForm {
+            username: "tom_jerry".to_string(),
+            password: password.to_string(),
+        };
+        assert_eq!(login.validate(), Ok(()), "{}", password);
+    }
+
+    for ratio in ["1", "1.7778", "16:9", "4/3"] {
+        let form = UploadForm {
+            ratio: ratio.to_string(),
+        };
+        assert_eq!(form.validate(), Ok(()), "{}", ratio);
+    }
+}
+
+#[test]
+fn usernames_follow_the_rules() {
+    for ok in ["bob", "alice_1", "j.doe", "___"] {
+        assert!(validation::username("username", ok).is_ok(), "{}", ok);
+    }
+    for bad in ["ab", ".bob", "bob.", "bo..b", "tom jerry", "tom'", "<b>", "çağrı", &"a".repeat(33)] {
+        assert!(validation::username("username", bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn emails_and_passwords_are_checked() {
+    assert!(register("bob", "bob@", "password").validate().is_err());
+    assert!(register("bob", "bob@example", "password").validate().is_err());
+    assert!(register("bob", "bob'@example.com", "password").validate().is_err());
+    assert!(register("bob", "bob@example.com", "short").validate().is_err());
+    assert!(register("bob", "bob@example.com", &"x".repeat(129)).validate().is_err());
+
+    let error = ChangePasswordForm {
+        old: "".to_string(),
+        new: "long enough".to_string(),
+    }
+    .validate()
+    .unwrap_err();
+    assert_eq!(error.field, "old");
+}
+
+#[test]
+fn ids_and_file_names_reject_query_and_path_syntax() {
+    assert!(validation::record_id("id", "k5b2mcf8zv7x9y1h0l3q").is_ok());
+    assert!(validation::record_id("id", "0192f3a4-5b6c-7d8e-9f01-23456789abcd").is_ok());
+    for bad in ["", "abc; DELETE user", "user:abc", "a b", "../x"] {
+        assert!(validation::record_id("id", bad).is_err(), "{}", bad);
+    }
+
+    assert!(validation::file_name("file", "ab12cd.png").is_ok());
+    for bad in ["..", "../model.onnx", "a/b.png", "noext", "a.p/ng"] {
+        assert!(validation::file_name("file", bad).is_err(), "{}", bad);
+    }
+
+    for bad in ["", "1;", "16:9:1", "1 OR 1"] {
+        let form = UploadForm {
+            ratio: bad.to_string(),
+        };
+        assert!(form.validate().is_err(), "{}", bad);
+    }
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::{test, Error};
+use common::webauthn::{user_handle, Authenticator};
+use common::{app_data, cookie, post_request, register, setup, status, unique};
+use gallery_backend::build_app;
+use gallery_backend::db;
+use serde_json::{json, Value};
+
+async fn post<S, B>(app: &S, uri: &str, token: Option<&Cookie<'static>>, body: Value) -> ServiceResponse<B>
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let mut req = post_request().uri(uri).set_json(body);
+    if let Some(token) = token {
+        req = req.cookie(token.clone());
+    }
+
+    test::call_service(app, req.to_request()).await
+}
+
+async fn post_json<S, B>(app: &S, uri: &str, token: Option<&Cookie<'static>>, body: Value) -> Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let res = post(app, uri, token, body).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    serde_json::from_slice(&test::read_body(res).await).unwrap()
+}
+
+/// registers a passkey for a new user, returns the user's token and id
+async fn with_passkey<S, B>(app: &S, authenticator: &mut Authenticator, username: &str) -> (Cookie<'static>, String)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let token = register(app, username).await;
+    let options = post_json(app, "/webauthn/register/start", Some(&token), json!({})).await;
+    assert_eq!(options["rp"]["id"], "localhost");
+    assert_eq!(options["user"]["name"], username);
+
+    let res = post(app, "/webauthn/register/finish", Some(&token), authenticator.register(&options)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    (token, user_handle(&options))
+}
+
+#[actix_web::test]
+async fn register_and_log_in_with_passkey() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let username = unique("yara");
+    let mut authenticator = Authenticator::new();
+    let (token, user_id) = with_passkey(&app, &mut authenticator, &username).await;
+
+    let req = test::TestRequest::get().uri("/webauthn/passkeys").cookie(token.clone()).to_request();
+    let passkeys: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(passkeys.as_array().unwrap().len(), 1);
+    assert_eq!(passkeys[0]["credential_id"], authenticator.credential_id());
+    assert_eq!(passkeys[0]["name"], "test key");
+    assert!(passkeys[0].get("public_key").is_none());
+
+    let options = post_json(&app, "/webauthn/login/start", None, json!({"username": username})).await;
+    assert_eq!(options["allowCredentials"][0]["id"], authenticator.credential_id());
+
+    let res = post(&app, "/webauthn/login/finish", None, authenticator.login(&options, Some(&user_id))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let session = cookie(&res, "token").unwrap();
+    assert_eq!(status(&app, &session).await, StatusCode::OK);
+    assert!(cookie(&res, "refresh").is_some());
+
+    // discoverable login, no username and the same passkey
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    assert_eq!(options["allowCredentials"], json!([]));
+    let res = post(&app, "/webauthn/login/finish", None, authenticator.login(&options, None)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/webauthn/passkeys").cookie(token).to_request();
+    let passkeys: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(passkeys[0]["sign_count"], 2);
+    assert!(passkeys[0]["last_used"].is_string());
+}
+
+#[actix_web::test]
+async fn replayed_cloned_or_foreign_assertions_are_rejected() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let mut authenticator = Authenticator::new();
+    let (_, user_id) = with_passkey(&app, &mut authenticator, &unique("zed")).await;
+
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    let assertion = authenticator.login(&options, Some(&user_id));
+    let res = post(&app, "/webauthn/login/finish", None, assertion.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = post(&app, "/webauthn/login/finish", None, assertion).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // a copy of the key whose counter is behind
+    authenticator.sign_count = 0;
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    let res = post(&app, "/webauthn/login/finish", None, authenticator.login(&options, Some(&user_id))).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // another key claiming the same credential id
+    let mut impostor = Authenticator::new();
+    impostor.credential_id = authenticator.credential_id.clone();
+    impostor.sign_count = 100;
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    let res = post(&app, "/webauthn/login/finish", None, impostor.login(&options, Some(&user_id))).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // signed for another site
+    authenticator.sign_count = 100;
+    authenticator.origin = "https://evil.example".to_string();
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    let res = post(&app, "/webauthn/login/finish", None, authenticator.login(&options, Some(&user_id))).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn registration_checks_challenge_origin_and_duplicates() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let mut authenticator = Authenticator::new();
+    authenticator.attestation = "none";
+    let (token, _) = with_passkey(&app, &mut authenticator, &unique("abel")).await;
+
+    let options = post_json(&app, "/webauthn/register/start", Some(&token), json!({})).await;
+    assert_eq!(options["excludeCredentials"][0]["id"], authenticator.credential_id());
+    let res = post(&app, "/webauthn/register/finish", Some(&token), authenticator.register(&options)).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    let mut other = Authenticator::new();
+    let res = post(&app, "/webauthn/register/finish", Some(&token), other.register(&options)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    other.rp_id = "evil.example".to_string();
+    let options = post_json(&app, "/webauthn/register/start", Some(&token), json!({})).await;
+    let res = post(&app, "/webauthn/register/finish", Some(&token), other.register(&options)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // challenges belong to the user who asked for them
+    let stranger = register(&app, &unique("bella")).await;
+    let options = post_json(&app, "/webauthn/register/start", Some(&token), json!({})).await;
+    let res = post(&app, "/webauthn/register/finish", Some(&stranger), Authenticator::new().register(&options)).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn deleted_passkey_can_no_longer_log_in() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let mut authenticator = Authenticator::new();
+    let (token, user_id) = with_passkey(&app, &mut authenticator, &unique("cyrus")).await;
+
+    let req = test::TestRequest::get().uri("/webauthn/passkeys").cookie(token.clone()).to_request();
+    let passkeys: Value = test::call_and_read_body_json(&app, req).await;
+    let id = passkeys[0]["id"].as_str().unwrap().to_string();
+
+    let req = post_request()
+        .uri("/webauthn/passkeys/delete")
+        .cookie(token)
+        .set_payload(id)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let options = post_json(&app, "/webauthn/login/start", None, json!({})).await;
+    let res = post(&app, "/webauthn/login/finish", None, authenticator.login(&options, Some(&user_id))).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn expired_challenges_are_cleared_as_new_ones_are_made() {
+    let config = setup();
+    let app = test::init_service(build_app(&config, app_data())).await;
+    let stale = unique("stale");
+    db::surrealdb::DB
+        .query("CREATE type::thing('webauthn_challenge', $id) SET purpose = 'login', created_at = time::now() - 10m;")
+        .bind(("id", stale.clone()))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+    post_json(&app, "/webauthn/login/start", None, json!({})).await;
+
+    let mut result = db::surrealdb::DB
+        .query("SELECT VALUE record::id(id) FROM type::thing('webauthn_challenge', $id);")
+        .bind(("id", stale))
+        .await
+        .unwrap();
+    let left: Vec<String> = result.take(0).unwrap();
+    assert!(left.is_empty());
+}